@@ -14,15 +14,1389 @@
 // To conserve gas, efficient serialization is achieved through Borsh (http://borsh.io/)
 use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
 use near_sdk::{
-    env, near_bindgen, setup_alloc, AccountId, Balance,
-    collections::{ UnorderedMap },
-    json_types:: { U128 }
+    assert_one_yocto, env, ext_contract, near_bindgen, setup_alloc, AccountId, Balance, BorshStorageKey, Promise, PromiseResult,
+    collections::{ LookupMap, UnorderedMap, Vector },
+    json_types:: { Base64VecU8, I128, U128 },
+    serde::{ Deserialize, Serialize },
+    serde_json::{ self, json }
 };
 
 setup_alloc!();
 
 const ONE_NEAR: u128 = 1_000_000_000_000_000_000_000_000;
+// yoctoNEAR per NEAR, as a power of ten
+const TOKEN_DECIMALS: u8 = 24;
 const PROB: u8 = 128;
+const MAX_BPS: u16 = 10_000;
+const NS_PER_HOUR: u64 = 60 * 60 * 1_000_000_000;
+const NS_PER_DAY: u64 = 24 * NS_PER_HOUR;
+const NS_PER_YEAR: u64 = 365 * NS_PER_DAY;
+// length of the window net losses for cashback are accumulated over
+const CASHBACK_WINDOW: u64 = 30 * NS_PER_DAY;
+const EVENT_STANDARD: &str = "coin_flip";
+const EVENT_VERSION: &str = "1.0.0";
+const MAX_REASON_LENGTH: usize = 256;
+// Effective odds never decay below this, so winning always stays possible
+const MIN_EFFECTIVE_ODDS: u8 = 1;
+const MAX_TOP_HOLDERS: u64 = 50;
+// most rounds a single `play_batch` call may request, to stay well within the gas limit
+const MAX_BATCH_ROUNDS: u32 = 20;
+// plays kept per account by `get_player_history`; older ones are evicted
+const MAX_HISTORY: usize = 20;
+// deposits kept per account by `get_deposit_history`; older ones are evicted
+const MAX_DEPOSIT_HISTORY: usize = 50;
+// most accounts a single `process_payouts` call may pay out
+const MAX_PAYOUT_BATCH: usize = 20;
+// gas attached to the callbacks `process_payouts` and an auto-payout win chain after their
+// transfers
+const GAS_FOR_PAYOUT_CALLBACK: u64 = 10_000_000_000_000;
+const GAS_FOR_AUTO_PAYOUT_CALLBACK: u64 = 10_000_000_000_000;
+// prepaid gas below these fails early: `withdraw` schedules a transfer, and
+// `process_payouts` needs its base amount plus a callback's worth per account paid, and a
+// play by an auto-payout account the callback its win would schedule
+const MIN_GAS_FOR_WITHDRAW: u64 = 20_000_000_000_000;
+const MIN_GAS_FOR_PAYOUTS: u64 = 20_000_000_000_000;
+const MIN_GAS_FOR_AUTO_PAYOUT_PLAY: u64 = 20_000_000_000_000 + GAS_FOR_AUTO_PAYOUT_CALLBACK;
+// longest single `contribute_entropy` value, so a contribution costs one bounded hash
+const MAX_ENTROPY_LENGTH: usize = 64;
+const MAX_NAME_LENGTH: usize = 64;
+const MAX_DESCRIPTION_LENGTH: usize = 512;
+// longest run `backtest` will evaluate, keeping its loss-probability sum cheap
+const MAX_BACKTEST_PLAYS: u32 = 10_000;
+// most players a single `airdrop_by_activity` page may cover
+const MAX_AIRDROP_PAGE: u64 = 50;
+// most accounts a single `fund_accounts` call may credit
+const MAX_FUND_ACCOUNTS: usize = 50;
+// most accounts a single `export_state` page may return
+const MAX_EXPORT_PAGE: u64 = 50;
+// odds changes kept by `odds_at`; older ones are evicted
+const MAX_ODDS_TIMELINE: u64 = 100;
+// idempotency keys remembered per account by `play`; older ones are evicted
+const MAX_IDEMPOTENCY_KEYS: usize = 10;
+const MAX_IDEMPOTENCY_KEY_LENGTH: usize = 64;
+const MAX_CLIENT_SEED_LENGTH: usize = 64;
+const DELAYED_PLAY_REJECTION: &str = "Plays resolve a block later, use play/resolve";
+// most pending bets a single `get_resolvable_bets` page may scan
+const MAX_RESOLVABLE_PAGE: u64 = 50;
+// hourly buckets `get_window_stats` keeps, so it covers at most the last day
+const WINDOW_BUCKETS: u64 = 24;
+// credit entries `health_assertions` sums when checking `total_player_credits`
+const MAX_HEALTH_SAMPLE: u64 = 50;
+// credit entries scanned for a dust account to evict when `credits` is full
+const MAX_EVICTION_SCAN: u64 = 50;
+
+#[ext_contract(ext_self)]
+pub trait SelfCallbacks {
+    fn on_payout_transfer(&mut self, account_id: AccountId, amount: U128);
+    fn on_auto_payout_transfer(&mut self, account_id: AccountId, amount: U128);
+}
+
+// A player's credits locked in the staking vault. The APR is fixed at stake time so that
+// later owner changes to `vault_apr_bps` don't affect positions that are already open.
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct VaultPosition {
+    pub amount: U128,
+    pub apr_bps: u16,
+    pub staked_at: u64,
+    pub unlock_at: u64,
+}
+
+// How basis-point math (payouts and fees) rounds. `Nearest` rounds halves up.
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub enum RoundingMode {
+    Floor,
+    Ceil,
+    Nearest,
+}
+
+impl RoundingMode {
+    fn apply_bps(self, amount: Balance, bps: u128) -> Balance {
+        let numerator = amount * bps;
+        let denominator = MAX_BPS as u128;
+        match self {
+            RoundingMode::Floor => numerator / denominator,
+            RoundingMode::Ceil => numerator.div_ceil(denominator),
+            RoundingMode::Nearest => (numerator + denominator / 2) / denominator,
+        }
+    }
+}
+
+// Game parameters the owner can tune. A roll wins when it's below `odds`, paying out
+// `payout_bps` of the stake (so 20_000 is 2x). `fee_bps` is charged on top of every stake.
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct GameConfig {
+    // number of winning rolls, counted up from `win_low`
+    pub odds: u8,
+    pub payout_bps: u32,
+    pub fee_bps: u16,
+    pub min_bet: U128,
+    pub max_bet: U128,
+    // stakes have to be a multiple of this; 0 allows any stake
+    pub tick_size: U128,
+    // round an off-tick stake down to the tick instead of rejecting it. The remainder is
+    // never taken from the credits, so it stays with the player.
+    pub round_stakes_to_tick: bool,
+    pub rounding: RoundingMode,
+    // odds lost per consecutive win, reset on a loss
+    pub odds_decay_per_win: u8,
+    // share of a losing stake credited back. Rebates are ordinary credits: they can be
+    // played or withdrawn right away, with no wagering requirement attached.
+    pub rebate_bps: u16,
+    // highest premium `play_with_insurance` accepts, 0 disables insurance
+    pub max_insurance_bps: u16,
+    // share of the stake an insured loss refunds
+    pub insurance_refund_bps: u16,
+    // (total_wagered threshold, odds bonus) pairs with strictly increasing thresholds
+    pub vip_tiers: Vec<(U128, u8)>,
+    // (net loss threshold, share of the loss paid back) pairs with strictly increasing
+    // thresholds; see `claim_cashback`
+    pub cashback_tiers: Vec<(U128, u16)>,
+    // (reward points threshold, basis points off `fee_bps`) pairs with strictly increasing
+    // thresholds; points aren't spent to get the discount
+    pub points_fee_tiers: Vec<(U128, u16)>,
+    // when set, winnings from `play` accrue in `claimable` until `claim_winnings` is called
+    pub pull_payouts: bool,
+    // when set, `play` only places the bet and a later block's `resolve` rolls it, so the
+    // seed that decides it can't be known while betting
+    pub delayed_resolution: bool,
+    // when set, `request_play` places delayed bets that only record a commitment to their
+    // stake until `resolve` reveals it; see `bet_commitment`
+    pub hidden_bets: bool,
+    // blocks a hidden bet has to be revealed in before anyone can refund its escrow with
+    // `refund_hidden_bet`
+    pub reveal_timeout_blocks: u64,
+    // loyalty points awarded per play as basis points of the stake, and whether only winning
+    // plays earn them. Points are a separate, non-withdrawable balance.
+    pub reward_points_bps: u16,
+    pub reward_points_wins_only: bool,
+    // fractional digits `format_balance` shows, at most TOKEN_DECIMALS
+    pub display_decimals: u8,
+    // log events for player activity (deposits, plays, withdrawals, payouts); off trades
+    // off-chain observability for privacy
+    pub emit_events: bool,
+    // charge players for the storage their plays and bets add, on every way of playing, and
+    // refund what they free
+    pub charge_play_storage: bool,
+    // lets players opt into having winnings sent to their wallet; see `set_auto_payout`
+    pub allow_auto_payout: bool,
+    // lets players move credits into the house bankroll for a share of it; see
+    // `provide_liquidity`
+    pub allow_liquidity: bool,
+    // hard ceiling on the odds after every bonus has been applied
+    pub max_effective_odds: u8,
+    // lowest winning roll; see `set_win_range`. The range is kept as `win_low` plus `odds`
+    // winning rolls rather than a stored `win_high`, because every odds bonus, the decay and
+    // the house edge check work on `odds`. `get_win_range` reports the high end.
+    pub win_low: u8,
+    // whether the boundary roll `win_low + odds` wins too. Inclusive makes `odds + 1` rolls
+    // win, and every odds figure (effective odds, house edge, win range) counts it.
+    pub win_boundary_inclusive: bool,
+    // exact win probability `odds_numerator / odds_denominator`, for odds 1/256 steps can't
+    // express. A play then wins when a u32 roll modulo the denominator is below the
+    // numerator, with no bonuses, decay or win range applied. A denominator of 0 keeps the
+    // byte rolls and `odds`.
+    pub odds_numerator: u32,
+    pub odds_denominator: u32,
+    // reserve the house keeps; a play that could pay out into it is rejected
+    pub min_house_balance: U128,
+    // share of the house's take on each losing bet kept in the bankroll for good, out of
+    // reach of `migrate_bankroll`
+    pub reinvest_bps: u16,
+    // band above `min_house_balance` in which winnings shrink as the house nears the floor;
+    // see `current_payout_bps`. 0 disables the soft cap.
+    pub soft_cap_margin: U128,
+    // steers payouts back towards what the odds predict: when the plays of the last
+    // `dynamic_payout_window_ns` (in whole hours) paid out more of their stakes than
+    // expected, `payout_bps` is scaled down by the excess, and up when they paid less,
+    // by at most `max_payout_adjust_bps` either way. 0 disables it.
+    pub dynamic_payout_window_ns: u64,
+    pub max_payout_adjust_bps: u16,
+    // pause instead of just rejecting the play when the random seed looks degenerate
+    pub auto_pause_on_degenerate_seed: bool,
+    // circuit breaker on the total winnings paid out within a single block
+    pub max_block_payout: U128,
+    // and on the number of winning plays in one, so plays are rejected once it's reached
+    pub max_winners_per_block: u32,
+    // most an account can win (payouts less stakes) in a day; 0 means unlimited
+    pub daily_win_limit: U128,
+    // cap on an account's unpaid winnings plus a new play's payout, as a share of the house
+    // balance; 0 disables it
+    pub max_player_exposure_bps: u16,
+    // cap on each stake as a share of the player's credits before the play; 0 disables it
+    pub max_stake_pct_bps: u16,
+    // block timestamp until which the config can't be changed; only `lock_config` sets it
+    pub config_locked_until: u64,
+    // least time between two owner config changes, so settings can't flicker around a bet
+    pub config_change_min_interval_ns: u64,
+    // lifetime cap on plays per account; 0 means unlimited
+    pub max_lifetime_plays: u64,
+    // minimum time between an account's plays, plus `cooldown_per_near_ns` for every whole
+    // NEAR of the previous stake; `play_priority` skips it for `priority_fee`
+    pub cooldown_ns: u64,
+    pub cooldown_per_near_ns: u64,
+    pub priority_fee: U128,
+    // burst limit: `rapid_play_limit` plays within `rapid_play_window_ns` of the first one
+    // start a break of `break_duration_ns` that no way of playing can skip; 0 disables it
+    pub rapid_play_limit: u32,
+    pub rapid_play_window_ns: u64,
+    pub break_duration_ns: u64,
+    // reward points `spend_points_reset_cooldown` takes to clear a cooldown; 0 disables it
+    pub points_per_cooldown_reset: U128,
+    // testnet only: how long after a play `undo_last_play` can revert it; 0 disables it
+    pub undo_window_ns: u64,
+    // how long a claimable entry must sit untouched before `reclaim_stuck` can clear it
+    pub stuck_reclaim_timeout: u64,
+    // how long a match can wait for an opponent before anyone can `refund_match` it; 0 means never
+    pub match_timeout_ns: u64,
+    // how long credits can sit without a deposit, play or withdrawal before anyone can sweep
+    // them into the house bankroll with `expire_credits`; 0 means never
+    pub credit_expiry_ns: u64,
+    // most accounts `credits` may hold, and how a new account is handled once it's full
+    pub max_credit_accounts: u64,
+    pub credits_cap_policy: CreditsCapPolicy,
+    pub eviction_dust_threshold: U128,
+    // paid from the house to an account that has deposited at least `min_bet` and played
+    // before when `try_play` is turned away for the house's reasons (a broken seed, the
+    // bankroll or block cap), at most once per `consolation_interval_ns`; 0 disables it
+    pub consolation_amount: U128,
+    pub consolation_interval_ns: u64,
+    // every `milestone_interval`th play overall wins `milestone_bonus` from the milestone
+    // pool, if the pool can cover it; 0 disables it
+    pub milestone_interval: u64,
+    pub milestone_bonus: U128,
+    // share of every stake the house gives up to `charity_pool`, which `donate_to_charity`
+    // sends on to `charity_account`; 0 disables it
+    pub charity_bps: u16,
+    pub charity_account: Option<AccountId>,
+    // after this many losses in a row the next play gets `comeback_bonus` extra odds; 0 disables it
+    pub comeback_after_losses: u32,
+    pub comeback_bonus: u8,
+    // how long after its first deposit or play an account becomes eligible for promotional
+    // bonuses, so freshly created accounts can't farm them
+    pub min_account_age_ns: u64,
+    // promotional bonus on deposits, paid from the house bankroll until the timestamp passes
+    pub deposit_match_bps: u16,
+    pub deposit_match_until: u64,
+    // free spins at `min_bet` every new account gets before `play` touches its credits. Their
+    // winnings are bonus credits, which unlock into credits once the account has wagered
+    // `bonus_wagering_multiplier` times the bonus with real credits.
+    pub trial_spins: u8,
+    pub bonus_wagering_multiplier: u16,
+}
+
+impl Default for GameConfig {
+    fn default() -> Self {
+        Self {
+            odds: PROB,
+            // 2x, not the original 10x: at even odds 10x returns five times every stake, and
+            // `new` validates these defaults like any other config
+            payout_bps: 20_000,
+            fee_bps: 0,
+            min_bet: ONE_NEAR.into(),
+            max_bet: ONE_NEAR.into(),
+            tick_size: U128::from(0),
+            round_stakes_to_tick: true,
+            rounding: RoundingMode::Floor,
+            odds_decay_per_win: 0,
+            rebate_bps: 0,
+            max_insurance_bps: 0,
+            insurance_refund_bps: 0,
+            vip_tiers: vec![],
+            cashback_tiers: vec![],
+            points_fee_tiers: vec![],
+            pull_payouts: false,
+            delayed_resolution: false,
+            hidden_bets: false,
+            reveal_timeout_blocks: 86_400,
+            reward_points_bps: 0,
+            reward_points_wins_only: false,
+            display_decimals: 6,
+            emit_events: true,
+            charge_play_storage: false,
+            allow_auto_payout: false,
+            allow_liquidity: false,
+            max_effective_odds: u8::MAX,
+            win_low: 0,
+            win_boundary_inclusive: false,
+            odds_numerator: 0,
+            odds_denominator: 0,
+            min_house_balance: U128::from(0),
+            reinvest_bps: 0,
+            soft_cap_margin: U128::from(0),
+            dynamic_payout_window_ns: 0,
+            max_payout_adjust_bps: 0,
+            auto_pause_on_degenerate_seed: true,
+            max_block_payout: U128::from(u128::MAX),
+            max_winners_per_block: u32::MAX,
+            daily_win_limit: U128::from(0),
+            max_player_exposure_bps: 0,
+            max_stake_pct_bps: 0,
+            config_locked_until: 0,
+            config_change_min_interval_ns: 0,
+            max_lifetime_plays: 0,
+            cooldown_ns: 0,
+            cooldown_per_near_ns: 0,
+            priority_fee: U128::from(0),
+            rapid_play_limit: 0,
+            rapid_play_window_ns: 0,
+            break_duration_ns: 0,
+            points_per_cooldown_reset: U128::from(0),
+            undo_window_ns: 0,
+            stuck_reclaim_timeout: NS_PER_YEAR / 2,
+            match_timeout_ns: 0,
+            credit_expiry_ns: 0,
+            max_credit_accounts: u64::MAX,
+            credits_cap_policy: CreditsCapPolicy::Reject,
+            eviction_dust_threshold: U128::from(0),
+            consolation_amount: U128::from(0),
+            consolation_interval_ns: NS_PER_DAY,
+            milestone_interval: 0,
+            milestone_bonus: U128::from(0),
+            charity_bps: 0,
+            charity_account: None,
+            deposit_match_bps: 0,
+            deposit_match_until: 0,
+            comeback_after_losses: 0,
+            comeback_bonus: 0,
+            min_account_age_ns: 0,
+            trial_spins: 0,
+            bonus_wagering_multiplier: 10,
+        }
+    }
+}
+
+impl GameConfig {
+    fn apply(&mut self, update: GameConfigUpdate) {
+        if let Some(odds) = update.odds { self.odds = odds; }
+        if let Some(payout_bps) = update.payout_bps { self.payout_bps = payout_bps; }
+        if let Some(fee_bps) = update.fee_bps { self.fee_bps = fee_bps; }
+        if let Some(min_bet) = update.min_bet { self.min_bet = min_bet; }
+        if let Some(max_bet) = update.max_bet { self.max_bet = max_bet; }
+        if let Some(tick_size) = update.tick_size { self.tick_size = tick_size; }
+        if let Some(round_stakes_to_tick) = update.round_stakes_to_tick { self.round_stakes_to_tick = round_stakes_to_tick; }
+        if let Some(rounding) = update.rounding { self.rounding = rounding; }
+        if let Some(odds_decay_per_win) = update.odds_decay_per_win { self.odds_decay_per_win = odds_decay_per_win; }
+        if let Some(rebate_bps) = update.rebate_bps { self.rebate_bps = rebate_bps; }
+        if let Some(max_insurance_bps) = update.max_insurance_bps { self.max_insurance_bps = max_insurance_bps; }
+        if let Some(insurance_refund_bps) = update.insurance_refund_bps { self.insurance_refund_bps = insurance_refund_bps; }
+        if let Some(vip_tiers) = update.vip_tiers { self.vip_tiers = vip_tiers; }
+        if let Some(cashback_tiers) = update.cashback_tiers { self.cashback_tiers = cashback_tiers; }
+        if let Some(points_fee_tiers) = update.points_fee_tiers { self.points_fee_tiers = points_fee_tiers; }
+        if let Some(pull_payouts) = update.pull_payouts { self.pull_payouts = pull_payouts; }
+        if let Some(delayed_resolution) = update.delayed_resolution { self.delayed_resolution = delayed_resolution; }
+        if let Some(hidden_bets) = update.hidden_bets { self.hidden_bets = hidden_bets; }
+        if let Some(reveal_timeout_blocks) = update.reveal_timeout_blocks { self.reveal_timeout_blocks = reveal_timeout_blocks; }
+        if let Some(reward_points_bps) = update.reward_points_bps { self.reward_points_bps = reward_points_bps; }
+        if let Some(reward_points_wins_only) = update.reward_points_wins_only { self.reward_points_wins_only = reward_points_wins_only; }
+        if let Some(display_decimals) = update.display_decimals { self.display_decimals = display_decimals; }
+        if let Some(emit_events) = update.emit_events { self.emit_events = emit_events; }
+        if let Some(charge_play_storage) = update.charge_play_storage { self.charge_play_storage = charge_play_storage; }
+        if let Some(allow_auto_payout) = update.allow_auto_payout { self.allow_auto_payout = allow_auto_payout; }
+        if let Some(allow_liquidity) = update.allow_liquidity { self.allow_liquidity = allow_liquidity; }
+        if let Some(max_effective_odds) = update.max_effective_odds { self.max_effective_odds = max_effective_odds; }
+        if let Some(win_low) = update.win_low { self.win_low = win_low; }
+        if let Some(win_boundary_inclusive) = update.win_boundary_inclusive { self.win_boundary_inclusive = win_boundary_inclusive; }
+        if let Some(odds_numerator) = update.odds_numerator { self.odds_numerator = odds_numerator; }
+        if let Some(odds_denominator) = update.odds_denominator { self.odds_denominator = odds_denominator; }
+        if let Some(min_house_balance) = update.min_house_balance { self.min_house_balance = min_house_balance; }
+        if let Some(reinvest_bps) = update.reinvest_bps { self.reinvest_bps = reinvest_bps; }
+        if let Some(soft_cap_margin) = update.soft_cap_margin { self.soft_cap_margin = soft_cap_margin; }
+        if let Some(dynamic_payout_window_ns) = update.dynamic_payout_window_ns { self.dynamic_payout_window_ns = dynamic_payout_window_ns; }
+        if let Some(max_payout_adjust_bps) = update.max_payout_adjust_bps { self.max_payout_adjust_bps = max_payout_adjust_bps; }
+        if let Some(auto_pause) = update.auto_pause_on_degenerate_seed { self.auto_pause_on_degenerate_seed = auto_pause; }
+        if let Some(max_block_payout) = update.max_block_payout { self.max_block_payout = max_block_payout; }
+        if let Some(max_winners_per_block) = update.max_winners_per_block { self.max_winners_per_block = max_winners_per_block; }
+        if let Some(daily_win_limit) = update.daily_win_limit { self.daily_win_limit = daily_win_limit; }
+        if let Some(max_player_exposure_bps) = update.max_player_exposure_bps { self.max_player_exposure_bps = max_player_exposure_bps; }
+        if let Some(max_stake_pct_bps) = update.max_stake_pct_bps { self.max_stake_pct_bps = max_stake_pct_bps; }
+        if let Some(config_change_min_interval_ns) = update.config_change_min_interval_ns { self.config_change_min_interval_ns = config_change_min_interval_ns; }
+        if let Some(max_lifetime_plays) = update.max_lifetime_plays { self.max_lifetime_plays = max_lifetime_plays; }
+        if let Some(cooldown_ns) = update.cooldown_ns { self.cooldown_ns = cooldown_ns; }
+        if let Some(cooldown_per_near_ns) = update.cooldown_per_near_ns { self.cooldown_per_near_ns = cooldown_per_near_ns; }
+        if let Some(priority_fee) = update.priority_fee { self.priority_fee = priority_fee; }
+        if let Some(rapid_play_limit) = update.rapid_play_limit { self.rapid_play_limit = rapid_play_limit; }
+        if let Some(rapid_play_window_ns) = update.rapid_play_window_ns { self.rapid_play_window_ns = rapid_play_window_ns; }
+        if let Some(break_duration_ns) = update.break_duration_ns { self.break_duration_ns = break_duration_ns; }
+        if let Some(points_per_cooldown_reset) = update.points_per_cooldown_reset { self.points_per_cooldown_reset = points_per_cooldown_reset; }
+        if let Some(undo_window_ns) = update.undo_window_ns { self.undo_window_ns = undo_window_ns; }
+        if let Some(stuck_reclaim_timeout) = update.stuck_reclaim_timeout { self.stuck_reclaim_timeout = stuck_reclaim_timeout; }
+        if let Some(match_timeout_ns) = update.match_timeout_ns { self.match_timeout_ns = match_timeout_ns; }
+        if let Some(credit_expiry_ns) = update.credit_expiry_ns { self.credit_expiry_ns = credit_expiry_ns; }
+        if let Some(max_credit_accounts) = update.max_credit_accounts { self.max_credit_accounts = max_credit_accounts; }
+        if let Some(credits_cap_policy) = update.credits_cap_policy { self.credits_cap_policy = credits_cap_policy; }
+        if let Some(eviction_dust_threshold) = update.eviction_dust_threshold { self.eviction_dust_threshold = eviction_dust_threshold; }
+        if let Some(consolation_amount) = update.consolation_amount { self.consolation_amount = consolation_amount; }
+        if let Some(consolation_interval_ns) = update.consolation_interval_ns { self.consolation_interval_ns = consolation_interval_ns; }
+        if let Some(milestone_interval) = update.milestone_interval { self.milestone_interval = milestone_interval; }
+        if let Some(milestone_bonus) = update.milestone_bonus { self.milestone_bonus = milestone_bonus; }
+        if let Some(charity_bps) = update.charity_bps { self.charity_bps = charity_bps; }
+        if let Some(charity_account) = update.charity_account { self.charity_account = Some(charity_account); }
+        if let Some(comeback_after_losses) = update.comeback_after_losses { self.comeback_after_losses = comeback_after_losses; }
+        if let Some(comeback_bonus) = update.comeback_bonus { self.comeback_bonus = comeback_bonus; }
+        if let Some(min_account_age_ns) = update.min_account_age_ns { self.min_account_age_ns = min_account_age_ns; }
+        if let Some(trial_spins) = update.trial_spins { self.trial_spins = trial_spins; }
+        if let Some(bonus_wagering_multiplier) = update.bonus_wagering_multiplier { self.bonus_wagering_multiplier = bonus_wagering_multiplier; }
+    }
+
+    // The house edge is non-negative when the expected return of a unit stake,
+    // (odds * payout_bps + (256 - odds) * rebate_bps) / 256, doesn't exceed what the
+    // player pays in (stake plus fee). Odds are taken at the best VIP tier plus any comeback
+    // bonus (after the `max_effective_odds` clamp), so no player can be offered a game the
+    // house loses on, not even on a comeback spin.
+    fn assert_valid(&self) {
+        assert!(self.winning_rolls() > 0, "Odds must be positive!");
+        assert!(self.payout_bps >= MAX_BPS as u32, "Payout must return at least the stake!");
+        assert!(self.fee_bps <= MAX_BPS, "Fee can't exceed 100%!");
+        assert!(self.rebate_bps <= MAX_BPS, "Rebate can't exceed 100%!");
+        assert!(self.max_insurance_bps <= MAX_BPS, "Insurance premium can't exceed 100%!");
+        assert!(self.insurance_refund_bps <= MAX_BPS, "Insurance refund can't exceed 100%!");
+        assert!(
+            self.rebate_bps as u32 + self.insurance_refund_bps as u32 <= MAX_BPS as u32,
+            "Rebate and insurance refund can't exceed 100% together!"
+        );
+        assert!(self.max_stake_pct_bps <= MAX_BPS, "Max stake can't exceed 100% of credits!");
+        assert!(self.reinvest_bps <= MAX_BPS, "Can't reinvest more than 100%!");
+        assert!(self.max_payout_adjust_bps <= MAX_BPS, "Payout adjustment can't exceed 100%!");
+        assert!(self.charity_bps <= MAX_BPS, "Can't donate more than 100%!");
+        assert!(self.reveal_timeout_blocks > 0, "Hidden bets need a reveal timeout!");
+        assert!(self.charity_bps == 0 || self.charity_account.is_some(), "Donations need a charity account!");
+        assert!(
+            self.charity_account.as_ref().is_none_or(|account_id| env::is_valid_account_id(account_id.as_bytes())),
+            "Invalid charity account!"
+        );
+        assert!(self.min_bet.0 > 0, "Minimum bet must be positive!");
+        assert!(self.min_bet.0 <= self.max_bet.0, "Minimum bet can't exceed the maximum bet!");
+        assert!(
+            self.tick_size.0 == 0 || self.min_bet.0.is_multiple_of(self.tick_size.0),
+            "Minimum bet must be a multiple of the tick size!"
+        );
+        assert!(self.max_effective_odds >= MIN_EFFECTIVE_ODDS, "Maximum effective odds are too low!");
+        assert!(self.display_decimals <= TOKEN_DECIMALS, "Can't display more than {} decimals!", TOKEN_DECIMALS);
+
+        assert!(
+            self.vip_tiers.windows(2).all(|tiers| tiers[0].0 .0 < tiers[1].0 .0),
+            "VIP tier thresholds must be strictly increasing!"
+        );
+        assert!(
+            self.cashback_tiers.windows(2).all(|tiers| tiers[0].0 .0 < tiers[1].0 .0),
+            "Cashback tier thresholds must be strictly increasing!"
+        );
+        assert!(self.cashback_tiers.iter().all(|(_, bps)| *bps <= MAX_BPS), "Cashback can't exceed 100%!");
+        assert!(
+            self.points_fee_tiers.windows(2).all(|tiers| tiers[0].0 .0 < tiers[1].0 .0),
+            "Fee discount tier thresholds must be strictly increasing!"
+        );
+
+        assert!(self.odds_numerator == 0 || self.odds_denominator > 0, "Odds denominator must be positive!");
+        if let Some((numerator, denominator)) = self.exact_odds() {
+            assert!(numerator > 0, "Odds must be positive!");
+            assert!(numerator < denominator, "Odds numerator must be below the denominator!");
+        }
+        assert!(self.has_house_edge(), "Configuration gives the house a negative edge!");
+    }
+
+    fn has_house_edge(&self) -> bool {
+        let (winning, rolls) = match self.exact_odds() {
+            Some((numerator, denominator)) => (numerator as u128, denominator as u128),
+            None => (self.boosted_odds(self.max_vip_bonus().saturating_add(self.active_comeback_bonus())) as u128, 256),
+        };
+        // the edge has to hold at the best fee discount and highest dynamic payout too, and
+        // still cover the charity donation every play makes win or lose
+        let min_fee_bps = self.fee_bps.saturating_sub(self.max_fee_discount_bps());
+        let expected_return = winning * self.max_dynamic_payout_bps() as u128 + rolls.saturating_sub(winning) * self.rebate_bps as u128;
+        expected_return + rolls * self.charity_bps as u128 <= rolls * (MAX_BPS as u128 + min_fee_bps as u128)
+    }
+
+    fn max_dynamic_payout_bps(&self) -> u32 {
+        if self.dynamic_payout_window_ns == 0 {
+            return self.payout_bps;
+        }
+        (self.payout_bps as u64 * (MAX_BPS as u64 + self.max_payout_adjust_bps as u64) / MAX_BPS as u64) as u32
+    }
+
+    // Chance of winning at the base odds, before any per-account bonus
+    fn base_win_chance(&self) -> (u128, u128) {
+        match self.exact_odds() {
+            Some((numerator, denominator)) => (numerator as u128, denominator as u128),
+            None => (self.boosted_odds(0) as u128, 256),
+        }
+    }
+
+    // Share of the stakes that winning plays are expected to pay out, at the base odds
+    fn expected_paid_bps(&self) -> u128 {
+        let (winning, rolls) = self.base_win_chance();
+        winning * self.payout_bps as u128 / rolls
+    }
+
+    // Expected return of a play as a share of its stake plus fee, at the base odds and full
+    // payout (so before VIP or comeback bonuses and the soft cap), rounded down
+    fn rtp_bps(&self) -> u16 {
+        let (winning, rolls) = self.base_win_chance();
+        let expected_return = winning * self.payout_bps as u128 + rolls.saturating_sub(winning) * self.rebate_bps as u128;
+        let cost = rolls * (MAX_BPS as u128 + self.fee_bps as u128);
+        (expected_return * MAX_BPS as u128 / cost).min(MAX_BPS as u128) as u16
+    }
+
+    fn exact_odds(&self) -> Option<(u32, u32)> {
+        if self.odds_denominator > 0 { Some((self.odds_numerator, self.odds_denominator)) } else { None }
+    }
+
+    // Closest 1/256 odds to the exact odds, rounded down, for the estimates that work in
+    // byte rolls
+    fn approximate_exact_odds(numerator: u32, denominator: u32) -> u8 {
+        (numerator as u64 * 256 / denominator as u64).max(MIN_EFFECTIVE_ODDS as u64) as u8
+    }
+
+    fn active_comeback_bonus(&self) -> u8 {
+        if self.comeback_after_losses > 0 { self.comeback_bonus } else { 0 }
+    }
+
+    // Whether a loss streak this long earns the next play the comeback bonus
+    fn is_comeback(&self, loss_streak: u32) -> bool {
+        self.comeback_after_losses > 0 && loss_streak >= self.comeback_after_losses
+    }
+
+    fn max_vip_bonus(&self) -> u8 {
+        self.vip_tiers.iter().map(|(_, bonus)| *bonus).max().unwrap_or(0)
+    }
+
+    // 1-based index of the highest tier reached, 0 when below every threshold
+    fn vip_tier(&self, total_wagered: Balance) -> u8 {
+        self.vip_tiers.iter().filter(|(threshold, _)| total_wagered >= threshold.0).count() as u8
+    }
+
+    fn cashback_bps(&self, net_loss: Balance) -> u16 {
+        self.cashback_tiers
+            .iter()
+            .rev()
+            .find(|(threshold, _)| net_loss >= threshold.0)
+            .map(|(_, bps)| *bps)
+            .unwrap_or(0)
+    }
+
+    fn fee_discount_bps(&self, reward_points: Balance) -> u16 {
+        self.points_fee_tiers
+            .iter()
+            .rev()
+            .find(|(threshold, _)| reward_points >= threshold.0)
+            .map(|(_, bps)| *bps)
+            .unwrap_or(0)
+    }
+
+    fn max_fee_discount_bps(&self) -> u16 {
+        self.points_fee_tiers.iter().map(|(_, bps)| *bps).max().unwrap_or(0)
+    }
+
+    fn fee_bps_for(&self, reward_points: Balance) -> u16 {
+        self.fee_bps.saturating_sub(self.fee_discount_bps(reward_points))
+    }
+
+    fn vip_bonus(&self, total_wagered: Balance) -> u8 {
+        match self.vip_tier(total_wagered) {
+            0 => 0,
+            tier => self.vip_tiers[tier as usize - 1].1,
+        }
+    }
+
+    // A roll wins when it falls in the `odds` rolls starting at `win_low`. The window is cut
+    // off at 255, so a high `win_low` can only ever make bonuses worth less.
+    fn wins(&self, roll: u8, odds: u8) -> bool {
+        roll >= self.win_low && ((roll - self.win_low) as u32) < odds as u32
+    }
+
+    // Number of winning rolls before any bonus, including the boundary roll if it's inclusive
+    fn winning_rolls(&self) -> u8 {
+        self.odds.saturating_add(self.win_boundary_inclusive as u8)
+    }
+
+    // Base winning rolls plus a bonus, clamped to `max_effective_odds`. Saturating at 255
+    // always leaves one losing roll.
+    fn boosted_odds(&self, bonus: u8) -> u8 {
+        self.winning_rolls().saturating_add(bonus).min(self.max_effective_odds)
+    }
+
+    // The stake actually played: rounded down to the tick, or unchanged if it's on a tick
+    // or rounding is off (in which case `play_rejection` rejects it)
+    fn round_to_tick(&self, stake: Balance) -> Balance {
+        let tick = self.tick_size.0;
+        if tick == 0 || !self.round_stakes_to_tick {
+            return stake;
+        }
+        stake - stake % tick
+    }
+
+    // How long a play of `stake` makes the account wait before its next one
+    fn cooldown_for(&self, stake: Balance) -> u64 {
+        let whole_near = (stake / ONE_NEAR).min(u64::MAX as u128) as u64;
+        self.cooldown_ns.saturating_add(whole_near.saturating_mul(self.cooldown_per_near_ns))
+    }
+
+    fn fee(&self, stake: Balance) -> Balance {
+        self.rounding.apply_bps(stake, self.fee_bps as u128)
+    }
+
+    // The fee after the best discount any points tier gives
+    fn min_fee(&self, stake: Balance) -> Balance {
+        self.rounding.apply_bps(stake, self.fee_bps.saturating_sub(self.max_fee_discount_bps()) as u128)
+    }
+
+    fn payout(&self, stake: Balance) -> Balance {
+        self.rounding.apply_bps(stake, self.payout_bps as u128)
+    }
+
+    fn rebate(&self, stake: Balance) -> Balance {
+        self.rounding.apply_bps(stake, self.rebate_bps as u128)
+    }
+
+    // Smallest premium that covers the expected refund at these odds, rounded up so the
+    // insurance never costs the house anything on average
+    fn min_insurance_bps(&self, odds: u8) -> u16 {
+        let losing_rolls = 256 - odds as u32;
+        (self.insurance_refund_bps as u32 * losing_rolls).div_ceil(256) as u16
+    }
+
+    // Whether winning with this stake returns more than the stake and fee paid for it
+    fn is_viable_stake(&self, stake: Balance) -> bool {
+        self.payout(stake) > stake + self.fee(stake)
+    }
+
+    // Smallest stake from which every larger stake is viable, or None if winning never
+    // pays. Rounding moves the payout and fee by at most one yocto each, so any stake with
+    // `stake * margin > 2 * MAX_BPS` is viable and the search only walks down from there.
+    fn min_viable_stake(&self) -> Option<Balance> {
+        let margin = self.payout_bps as i128 - MAX_BPS as i128 - self.fee_bps as i128;
+        if margin <= 0 {
+            return None;
+        }
+
+        let mut stake = (2 * MAX_BPS as i128 / margin + 1) as Balance;
+        while stake > 1 && self.is_viable_stake(stake - 1) {
+            stake -= 1;
+        }
+        Some(stake)
+    }
+}
+
+// Partial update for `set_config`; fields left out keep their current value
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Default, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct GameConfigUpdate {
+    pub odds: Option<u8>,
+    pub payout_bps: Option<u32>,
+    pub fee_bps: Option<u16>,
+    pub min_bet: Option<U128>,
+    pub max_bet: Option<U128>,
+    pub tick_size: Option<U128>,
+    pub round_stakes_to_tick: Option<bool>,
+    pub rounding: Option<RoundingMode>,
+    pub odds_decay_per_win: Option<u8>,
+    pub rebate_bps: Option<u16>,
+    pub max_insurance_bps: Option<u16>,
+    pub insurance_refund_bps: Option<u16>,
+    pub vip_tiers: Option<Vec<(U128, u8)>>,
+    pub cashback_tiers: Option<Vec<(U128, u16)>>,
+    pub points_fee_tiers: Option<Vec<(U128, u16)>>,
+    pub pull_payouts: Option<bool>,
+    pub delayed_resolution: Option<bool>,
+    pub hidden_bets: Option<bool>,
+    pub reveal_timeout_blocks: Option<u64>,
+    pub reward_points_bps: Option<u16>,
+    pub reward_points_wins_only: Option<bool>,
+    pub display_decimals: Option<u8>,
+    pub emit_events: Option<bool>,
+    pub charge_play_storage: Option<bool>,
+    pub allow_auto_payout: Option<bool>,
+    pub allow_liquidity: Option<bool>,
+    pub max_effective_odds: Option<u8>,
+    pub win_low: Option<u8>,
+    pub win_boundary_inclusive: Option<bool>,
+    pub odds_numerator: Option<u32>,
+    pub odds_denominator: Option<u32>,
+    pub min_house_balance: Option<U128>,
+    pub reinvest_bps: Option<u16>,
+    pub soft_cap_margin: Option<U128>,
+    pub dynamic_payout_window_ns: Option<u64>,
+    pub max_payout_adjust_bps: Option<u16>,
+    pub auto_pause_on_degenerate_seed: Option<bool>,
+    pub max_block_payout: Option<U128>,
+    pub max_winners_per_block: Option<u32>,
+    pub daily_win_limit: Option<U128>,
+    pub max_player_exposure_bps: Option<u16>,
+    pub max_stake_pct_bps: Option<u16>,
+    pub config_change_min_interval_ns: Option<u64>,
+    pub max_lifetime_plays: Option<u64>,
+    pub cooldown_ns: Option<u64>,
+    pub cooldown_per_near_ns: Option<u64>,
+    pub priority_fee: Option<U128>,
+    pub rapid_play_limit: Option<u32>,
+    pub rapid_play_window_ns: Option<u64>,
+    pub break_duration_ns: Option<u64>,
+    pub points_per_cooldown_reset: Option<U128>,
+    pub undo_window_ns: Option<u64>,
+    pub stuck_reclaim_timeout: Option<u64>,
+    pub match_timeout_ns: Option<u64>,
+    pub credit_expiry_ns: Option<u64>,
+    pub max_credit_accounts: Option<u64>,
+    pub credits_cap_policy: Option<CreditsCapPolicy>,
+    pub eviction_dust_threshold: Option<U128>,
+    pub consolation_amount: Option<U128>,
+    pub consolation_interval_ns: Option<u64>,
+    pub milestone_interval: Option<u64>,
+    pub milestone_bonus: Option<U128>,
+    pub charity_bps: Option<u16>,
+    pub charity_account: Option<AccountId>,
+    pub comeback_after_losses: Option<u32>,
+    pub comeback_bonus: Option<u8>,
+    pub min_account_age_ns: Option<u64>,
+    pub trial_spins: Option<u8>,
+    pub bonus_wagering_multiplier: Option<u16>,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct SolvencyReport {
+    pub account_balance: U128,
+    pub storage_cost: U128,
+    pub total_player_credits: U128,
+    pub total_locked_credits: U128,
+    pub total_claimable: U128,
+    // free balance minus everything owed to players; negative when insolvent
+    pub house_equity: I128,
+    pub is_solvent: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct StorageInfo {
+    pub storage_usage: u64,
+    pub storage_cost: U128,
+    pub credit_entries: u64,
+    pub vault_entries: u64,
+    pub match_entries: u64,
+    pub blacklist_entries: u64,
+}
+
+// Single health-check endpoint for monitoring, combining the solvency and storage views
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct ContractHealth {
+    // accounts with a credits entry, including ones that have since been drained to zero
+    pub player_count: u64,
+    // bytes of state and the balance they lock for storage staking
+    pub storage_usage: u64,
+    pub storage_cost: U128,
+    // everything owed to players: playable, locked and claimable credits
+    pub liabilities: U128,
+    // free balance minus liabilities; negative when insolvent
+    pub house_equity: I128,
+    pub is_solvent: bool,
+    // set by the owner or automatically on a degenerate random seed; blocks all play
+    pub paused: bool,
+}
+
+// Every guardrail the owner can configure, for monitoring to read in one call. Fields
+// mirror the `GameConfig` ones of the same name, where 0 disables a cap unless noted;
+// `max_payout` is what a winning `max_bet` pays at the full payout.
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct Limits {
+    pub min_bet: U128,
+    pub max_bet: U128,
+    pub max_payout: U128,
+    pub max_stake_pct_bps: u16,
+    pub min_house_balance: U128,
+    pub max_block_payout: U128,
+    // u32::MAX when unlimited
+    pub max_winners_per_block: u32,
+    pub daily_win_limit: U128,
+    pub max_player_exposure_bps: u16,
+    pub cooldown_ns: u64,
+    pub cooldown_per_near_ns: u64,
+    pub max_lifetime_plays: u64,
+    // u64::MAX when unlimited
+    pub max_credit_accounts: u64,
+    pub config_change_min_interval_ns: u64,
+}
+
+// Lifetime per-player statistics
+#[derive(BorshDeserialize, BorshSerialize, Default)]
+pub struct PlayerStats {
+    pub total_deposited: Balance,
+    pub total_wagered: Balance,
+    pub win_streak: u32,
+    pub total_plays: u64,
+    pub loss_streak: u32,
+    pub last_played_at: u64,
+    // stake of the last play, which sets how long its cooldown lasts
+    pub last_stake: Balance,
+}
+
+// Everything stored for one account, as exported by `export_state`
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct AccountExport {
+    pub account_id: AccountId,
+    pub credits: U128,
+    pub claimable: U128,
+    pub claimable_since: Option<u64>,
+    pub total_deposited: U128,
+    pub total_wagered: U128,
+    pub total_withdrawn: U128,
+    pub total_plays: u64,
+    pub win_streak: u32,
+    pub loss_streak: u32,
+    pub vault: Option<VaultPosition>,
+    pub blacklisted: bool,
+    pub reward_points: U128,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct StateChunk {
+    pub accounts: Vec<AccountExport>,
+    // accounts across all pages, so the caller knows when it has them all
+    pub total_accounts: u64,
+}
+
+// Global state, as exported by `export_config`
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct ConfigExport {
+    pub owner_id: AccountId,
+    pub config: GameConfig,
+    pub metadata: GameMetadata,
+    pub network: NetworkMode,
+    pub paused: bool,
+    pub vault_apr_bps: u16,
+    pub treasury_splits: Vec<(AccountId, u16)>,
+    pub accumulated_fees: U128,
+    pub total_player_credits: U128,
+    pub total_locked_credits: U128,
+    pub total_claimable: U128,
+    pub total_wagered: U128,
+    pub nonce: u64,
+    pub next_match_id: u64,
+    pub event_seq: u64,
+    pub created_at: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct OwnerEarnings {
+    // fees not collected yet
+    pub accumulated_fees: U128,
+    // what the house has won on bets, net of payouts, rebates and insurance refunds, plus
+    // the insurance and priority premiums it kept
+    pub house_pnl: I128,
+    pub fees_collected: U128,
+    // how much of `accumulated_fees` `collect_fees` could pay out right now
+    pub available_to_withdraw: U128,
+    // winnings retained under `reinvest_bps`, and the house surplus left over for the owner
+    pub reinvested: U128,
+    pub withdrawable_surplus: U128,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct NetPosition {
+    pub total_deposited: U128,
+    pub total_withdrawn: U128,
+    pub credits: U128,
+    // withdrawn + credits - deposited; negative when the player is down overall
+    pub net: I128,
+}
+
+// Every balance an account holds, zero where it has none. All amounts are yoctoNEAR except
+// `reward_points` and `lp_shares`.
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct AllBalances {
+    // playable and withdrawable
+    pub credits: U128,
+    // principal locked in a vault position, not counting interest
+    pub vault: U128,
+    // stakes escrowed in the open matches the account created
+    pub match_escrow: U128,
+    // stake and fee of a bet waiting for `resolve`
+    pub pending_bet: U128,
+    // winnings waiting for `claim_winnings`
+    pub claimable: U128,
+    // trial winnings that unlock into credits once wagered through
+    pub bonus_credits: U128,
+    // withdraw-only; see `set_savings_split`
+    pub savings: U128,
+    // prefunded for recurring deposits; see `set_recurring_deposit`
+    pub recurring_allowance: U128,
+    // loyalty points, not redeemable for NEAR
+    pub reward_points: U128,
+    // share of the house bankroll, and what it's worth now
+    pub lp_shares: U128,
+    pub lp_value: U128,
+}
+
+// Everything an account has in flight: winnings waiting to be paid out, a locked vault
+// position and the open matches it created
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct PendingActions {
+    pub claimable: U128,
+    // block timestamp the claimable balance was first owed at
+    pub claimable_since: Option<u64>,
+    pub vault: Option<VaultPosition>,
+    pub open_matches: Vec<u64>,
+}
+
+// Everything needed to recompute a play's roll off-chain:
+//   roll = sha256(random_seed || entropy_pool || account_id as UTF-8 bytes || nonce as u64 little-endian)[0]
+// Once the account has set a client seed, the hashed input goes on with
+//   || server_seed || client_seed as UTF-8 bytes || seed_nonce as u64 little-endian
+// where the server seed is the one `set_client_seed` reveals when it's next rotated.
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct PlayProof {
+    pub random_seed: Base64VecU8,
+    pub entropy_pool: Base64VecU8,
+    pub account_id: AccountId,
+    pub nonce: u64,
+    pub block_index: u64,
+    pub roll: u8,
+    pub client_seed: Option<String>,
+    pub seed_nonce: Option<u64>,
+}
+
+// An account's provably-fair seeds: its own client seed, the server seed drawn when the
+// client seed was set, and how many plays have used the pair
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct FairnessSeeds {
+    pub client_seed: String,
+    pub server_seed: Vec<u8>,
+    pub nonce: u64,
+}
+
+// What `set_client_seed` returns: the retired seeds, so plays made with them can be checked,
+// and the hash of the new server seed
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct SeedRotation {
+    pub previous_client_seed: Option<String>,
+    pub previous_server_seed: Option<Base64VecU8>,
+    pub previous_nonce: u64,
+    pub server_seed_hash: Base64VecU8,
+}
+
+// How the account's last play was decided: it won iff `win_low <= roll < win_low + threshold`,
+// where `threshold` is the number of winning rolls after every bonus and decay. Under exact
+// odds it won iff `exact_roll < odds_numerator` instead, and `threshold` is only the closest
+// 1/256 approximation. The rest is how the play settled, which `undo_last_play` reverses.
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct LastRollDetail {
+    // sha256 of the block's random seed
+    pub seed_hash: Base64VecU8,
+    pub nonce: u64,
+    pub win_low: u8,
+    pub threshold: u8,
+    pub roll: u8,
+    pub exact_roll: Option<u32>,
+    pub won: bool,
+    pub played_at: u64,
+    pub stake: U128,
+    pub fee: U128,
+    pub payout: U128,
+    // loss rebate plus insurance refund
+    pub refund: U128,
+    pub points: U128,
+    // what the house gave to the charity pool out of this play
+    pub donation: U128,
+    // false once undone, or when winnings left the player's credits or it completed a
+    // milestone interval
+    pub undoable: bool,
+    pub block_index: u64,
+    // the account's (win, loss) streaks before this play, which an undo restores
+    pub streaks_before: (u32, u32),
+}
+
+// Outcome of a single play. `payout` is the gross amount won (zero on a loss, rebates
+// aside) and `credits` the player's balance afterwards.
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct PlayResult {
+    pub roll: u8,
+    pub won: bool,
+    pub stake: U128,
+    pub payout: U128,
+    pub credits: U128,
+    // credited back on an insured loss from `play_with_insurance`, 0 otherwise
+    pub insurance_refund: U128,
+}
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct DepositRecord {
+    pub amount: U128,
+    // promotional credits added on top by an active deposit match
+    pub bonus: U128,
+    pub timestamp: u64,
+}
+
+// Result of `try_play`, serialized as {"status": "Ok", "data": PlayResult} or
+// {"status": "Rejected", "data": reason}
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde", tag = "status", content = "data")]
+pub enum PlayOutcome {
+    Ok(PlayResult),
+    Rejected(String),
+}
+
+// Projection of `plays` rounds at a fixed stake. The house profit is the sum of independent
+// per-round results, so its mean and standard deviation are closed-form; the chance the
+// player ends the run down overall is a binomial tail over the number of wins.
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct BacktestResult {
+    pub expected_house_profit: I128,
+    pub house_profit_std_dev: U128,
+    pub player_loss_probability_bps: u16,
+}
+
+// Minimum prepaid gas for the methods that schedule transfers or callbacks
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct GasRequirements {
+    pub withdraw: u64,
+    pub process_payouts: u64,
+    pub process_payouts_per_account: u64,
+    pub play_with_auto_payout: u64,
+    // attached to each callback, out of the prepaid gas above
+    pub payout_callback: u64,
+    pub auto_payout_callback: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct WithdrawResult {
+    pub amount: U128,
+    pub remaining: U128,
+}
+
+// Outcome of `play_batch`. Fewer rounds than requested are played when the player runs
+// out of credits partway through.
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct BatchResult {
+    pub rounds_played: u32,
+    pub rounds_requested: u32,
+    pub results: Vec<PlayResult>,
+    pub ending_balance: U128,
+}
+
+// An open PvP match waiting for a second player. Joined matches are resolved and removed
+// immediately, so only the creator's escrowed stake needs to be kept here.
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct Match {
+    pub creator: AccountId,
+    pub stake: U128,
+    pub created_at: u64,
+}
+
+// A config update announced ahead of time by `schedule_config_change`. Plays keep using the
+// current config until `effective_at`.
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct PendingConfigChange {
+    pub update: GameConfigUpdate,
+    pub effective_at: u64,
+}
+
+// Owner actions that, once signers are set, only run after `required_approvals` of them
+// approve a proposal for it
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(crate = "near_sdk::serde", tag = "action")]
+pub enum OwnerAction {
+    AdjustCredits { account_id: AccountId, new_balance: U128, reason: String },
+    MigrateBankroll { new_contract: AccountId, amount: U128 },
+    SetSigners { signers: Vec<AccountId>, required_approvals: u8 },
+}
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct Proposal {
+    pub action: OwnerAction,
+    pub proposer: AccountId,
+    pub approvals: Vec<AccountId>,
+    pub created_at: u64,
+}
+
+// Which network the contract is deployed on, fixed at init. Testnet unlocks conveniences
+// that would be unsafe with real funds, such as forcing the next roll.
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub enum NetworkMode {
+    Testnet,
+    Mainnet,
+}
+
+// What a deposit from a new account does once `credits` holds `max_credit_accounts`
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub enum CreditsCapPolicy {
+    Reject,
+    // drops the least recently active account holding at most `eviction_dust_threshold`
+    EvictDust,
+}
+
+// Human-readable name and description, so a directory of deployed games can list each one
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct GameMetadata {
+    pub name: String,
+    pub description: String,
+}
+
+impl Default for GameMetadata {
+    fn default() -> Self {
+        Self {
+            name: String::from("Coin Flip"),
+            description: String::new(),
+        }
+    }
+}
+
+impl GameMetadata {
+    fn assert_valid(&self) {
+        assert!(!self.name.trim().is_empty(), "Name can't be empty!");
+        assert!(self.name.len() <= MAX_NAME_LENGTH, "Name is too long!");
+        assert!(self.description.len() <= MAX_DESCRIPTION_LENGTH, "Description is too long!");
+        assert!(
+            !self.name.chars().chain(self.description.chars()).any(char::is_control),
+            "Name and description can't contain control characters!"
+        );
+    }
+}
+
+// Progress of an `airdrop_by_activity` run. Shares are computed against the total wagered
+// when the airdrop started, so pages paid at different times still add up to at most
+// `total_amount`.
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct Airdrop {
+    pub total_amount: Balance,
+    pub total_wagered: Balance,
+    pub page_size: u64,
+    pub distributed: Balance,
+    pub processed_pages: Vec<u64>,
+}
+
+// Progress of a `recompute_total_credits` recount, committed by `finalize_total_credits`
+#[derive(BorshDeserialize, BorshSerialize, Clone)]
+pub struct CreditsRecount {
+    pub page_size: u64,
+    pub sum: Balance,
+    pub processed_pages: Vec<u64>,
+}
+
+// Winnings from trial spins, held back until `wagering_remaining` more has been staked
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct BonusCredits {
+    pub amount: U128,
+    pub wagering_remaining: U128,
+}
+
+// A bet `play` placed under `delayed_resolution`, waiting for a block after `block_index`.
+// Its stake and fee are held in escrow until then. A hidden bet from `request_play` only
+// has its `commitment`: `stake` is then the escrow the revealed stake and fee must fit in,
+// and `fee` is 0.
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct PendingBet {
+    pub stake: U128,
+    pub fee: U128,
+    pub block_index: u64,
+    pub commitment: Option<Base64VecU8>,
+}
+
+// The stake and salt a hidden bet was committed to, revealed to `resolve`
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct BetReveal {
+    pub stake: U128,
+    pub salt: Base64VecU8,
+}
+
+// An account's plays in its current `rapid_play_window_ns`, and the end of its last
+// enforced break
+#[derive(BorshDeserialize, BorshSerialize, Default)]
+pub struct RapidPlays {
+    pub window_started_at: u64,
+    pub plays: u32,
+    pub break_until: u64,
+}
+
+// Plays, wins, stakes and winnings paid over a recent window; see `get_window_stats`
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct WindowStats {
+    pub plays: u64,
+    pub wins: u64,
+    pub wagered: U128,
+    pub paid: U128,
+}
+
+// How full `credits` is against `max_credit_accounts`
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct CreditsCapacity {
+    pub accounts: u64,
+    pub max_accounts: u64,
+    pub policy: CreditsCapPolicy,
+}
+
+// Donations waiting to be sent with `donate_to_charity`, and everything sent so far
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct CharityPool {
+    pub charity_account: Option<AccountId>,
+    pub charity_bps: u16,
+    pub pool: U128,
+    pub total_donated: U128,
+}
+
+// How far the game is towards its next milestone play; see `fund_milestone_pool`
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct MilestoneProgress {
+    pub plays_since_milestone: u64,
+    pub milestone_interval: u64,
+    pub pool: U128,
+    pub bonus: U128,
+}
+
+// A standing deposit of `amount_per_period` every `period_ns`, drawn from `allowance`, which the
+// player funds up front through `set_recurring_deposit`
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct RecurringDeposit {
+    pub amount_per_period: U128,
+    pub period_ns: u64,
+    pub next_due: u64,
+    pub allowance: U128,
+}
+
+// An account's net losses (staked plus fees, minus everything paid back) since `started_at`.
+// Negative while the account is ahead.
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct CashbackWindow {
+    pub started_at: u64,
+    pub net_loss: i128,
+}
+
+impl VaultPosition {
+    // Simple (non-compounding) interest over the locked duration. The yearly amount is split
+    // into quotient and remainder so the multiplication by a nanosecond duration can't overflow.
+    pub fn interest(&self) -> Balance {
+        let duration = (self.unlock_at - self.staked_at) as u128;
+        let yearly = self.amount.0 * self.apr_bps as u128 / MAX_BPS as u128;
+        let year = NS_PER_YEAR as u128;
+        yearly / year * duration + yearly % year * duration / year
+    }
+}
+
+// The runtime should never hand us a malformed caller, but one would be written into every
+// per-account map, so it's checked anyway before anything is keyed by it
+fn assert_valid_caller(account_id: &AccountId) {
+    assert!(env::is_valid_account_id(account_id.as_bytes()), "Invalid caller account!");
+}
+
+// Commitment to a hidden bet: sha256(account_id || stake as 16-byte little-endian || salt).
+// The salt should be 32 random bytes kept secret until the reveal, so the stake can't be
+// found by hashing every possible amount.
+fn bet_commitment(account_id: &AccountId, stake: Balance, salt: &[u8]) -> Vec<u8> {
+    let mut input = account_id.as_bytes().to_vec();
+    input.extend_from_slice(&stake.to_le_bytes());
+    input.extend_from_slice(salt);
+    env::sha256(&input)
+}
+
+// Fails before any work is done, rather than leaving a transfer or callback short of gas
+fn assert_prepaid_gas(required: u64) {
+    assert!(env::prepaid_gas() >= required, "Attach at least {} gas", required);
+}
+
+// Exactly `amount * part / whole`, rounded down, whenever that fits in a u128 (which
+// `part <= whole` guarantees). The product can need
+// more than 128 bits, so it's built up one bit of `part` at a time as a quotient and a
+// remainder modulo `whole`.
+fn pro_rata(amount: Balance, part: Balance, whole: Balance) -> Balance {
+    // a + b modulo `whole`, counting the wrap-around into the quotient
+    fn add_mod(a: u128, b: u128, whole: u128, quotient: &mut u128) -> u128 {
+        if a >= whole - b {
+            *quotient += 1;
+            a - (whole - b)
+        } else {
+            a + b
+        }
+    }
+
+    let (amount_quotient, amount_remainder) = (amount / whole, amount % whole);
+    let (mut quotient, mut remainder) = (0, 0);
+    for bit in (0..128).rev() {
+        quotient *= 2;
+        remainder = add_mod(remainder, remainder, whole, &mut quotient);
+        if (part >> bit) & 1 == 1 {
+            quotient += amount_quotient;
+            remainder = add_mod(remainder, amount_remainder, whole, &mut quotient);
+        }
+    }
+    quotient
+}
+
+// Prefixes of every persistent collection, one variant each. Collections are only ever
+// built from a variant here, never a raw byte prefix, so two can't end up sharing one.
+// New variants go at the end: a variant's prefix is its position.
+#[derive(BorshStorageKey, BorshSerialize)]
+enum StorageKey {
+    Credits,
+    Vaults,
+    Matches,
+    Blacklist,
+    Stats,
+    TotalWithdrawn,
+    PlayProofs,
+    Claimable,
+    History,
+    Airdrops,
+    ClaimableSince,
+    RollHistogram,
+    DepositHistory,
+    FirstSeen,
+    Cashback,
+    AutoPayout,
+    Allowances,
+    RewardPoints,
+    DailyWins,
+    LastRolls,
+    Proposals,
+    LpShares,
+    LastActivity,
+    TipDestinations,
+    TotalTips,
+    LastConsolation,
+    OddsTimeline,
+    TrialsRemaining,
+    BonusCredits,
+    PlayKeys,
+    SavingsSplitBps,
+    Savings,
+    RecurringDeposits,
+    WindowBuckets,
+    FairnessSeeds,
+    PendingBets,
+    RapidPlays,
+}
 
 // Structs in Rust are similar to other languages, and may include impl keyword as shown below
 // Note: the names of the structs are not important when calling the smart contract, but the function names are
@@ -30,158 +1404,8366 @@ const PROB: u8 = 128;
 #[derive(BorshDeserialize, BorshSerialize)]
 pub struct SlotMachine {
     owner_id: AccountId,
-    credits: UnorderedMap<AccountId, Balance>
+    credits: UnorderedMap<AccountId, Balance>,
+    vault_apr_bps: u16,
+    vaults: UnorderedMap<AccountId, VaultPosition>,
+    nonce: u64,
+    config: GameConfig,
+    accumulated_fees: Balance,
+    matches: UnorderedMap<u64, Match>,
+    next_match_id: u64,
+    // playable credits across all accounts, and credits locked in vaults and match escrow
+    total_player_credits: Balance,
+    total_locked_credits: Balance,
+    // interest owed on open vault positions, set aside from the house when they're opened
+    total_vault_interest: Balance,
+    blacklist: UnorderedMap<AccountId, bool>,
+    stats: UnorderedMap<AccountId, PlayerStats>,
+    total_withdrawn: UnorderedMap<AccountId, Balance>,
+    play_proofs: UnorderedMap<AccountId, PlayProof>,
+    claimable: UnorderedMap<AccountId, Balance>,
+    total_claimable: Balance,
+    // last MAX_HISTORY plays per account, oldest first
+    history: UnorderedMap<AccountId, Vec<PlayResult>>,
+    // beneficiaries of `collect_fees` and their shares in basis points
+    treasury_splits: Vec<(AccountId, u16)>,
+    paused: bool,
+    // rolling hash of third-party `contribute_entropy` values, empty until the first one
+    entropy_pool: Vec<u8>,
+    metadata: GameMetadata,
+    // sequence number of the last emitted event
+    event_seq: u64,
+    // block index and the winnings paid out in it so far
+    block_payout: (u64, Balance),
+    // block index and the number of winning plays in it so far
+    block_winners: (u64, u32),
+    // block timestamp of initialization
+    created_at: u64,
+    // sum of every account's `total_wagered`
+    total_wagered: Balance,
+    airdrops: UnorderedMap<u64, Airdrop>,
+    // when each `claimable` entry was created
+    claimable_since: UnorderedMap<AccountId, u64>,
+    network: NetworkMode,
+    // testnet only: the roll the next play will get instead of a derived one
+    forced_roll: Option<u8>,
+    // how often each roll byte has come up. One counter per key, so a play only reads and
+    // writes its own bucket instead of all 256.
+    roll_histogram: LookupMap<u8, u64>,
+    // last MAX_DEPOSIT_HISTORY deposits per account, oldest first
+    deposit_history: UnorderedMap<AccountId, Vec<DepositRecord>>,
+    // block timestamp of each account's first deposit or play
+    first_seen: UnorderedMap<AccountId, u64>,
+    cashback: UnorderedMap<AccountId, CashbackWindow>,
+    // accounts that want winnings transferred instead of credited
+    auto_payout: UnorderedMap<AccountId, bool>,
+    // (player, delegate) to how much more the delegate may spend on plays for the player
+    allowances: LookupMap<(AccountId, AccountId), Balance>,
+    // see `OwnerEarnings`
+    house_pnl: i128,
+    fees_collected: Balance,
+    // house winnings retained under `reinvest_bps`
+    reinvested_profit: Balance,
+    // loyalty points; not credits, so they never count towards liabilities
+    reward_points: UnorderedMap<AccountId, Balance>,
+    credits_recount: Option<CreditsRecount>,
+    // start of each account's current day and what it has won in it
+    daily_wins: UnorderedMap<AccountId, (u64, Balance)>,
+    last_rolls: UnorderedMap<AccountId, LastRollDetail>,
+    // while `required_approvals` is non-zero the actions in `OwnerAction` need that many
+    // signers to approve them instead of the owner calling them directly
+    signers: Vec<AccountId>,
+    required_approvals: u8,
+    proposals: UnorderedMap<u64, Proposal>,
+    next_proposal_id: u64,
+    // shares of the house bankroll; the owner holds the bankroll that was there before the
+    // first provider joined
+    lp_shares: UnorderedMap<AccountId, Balance>,
+    total_lp_shares: Balance,
+    // block timestamp of each account's last deposit, play or withdrawal
+    last_activity: LookupMap<AccountId, u64>,
+    pending_config: Option<PendingConfigChange>,
+    // block timestamp of the owner's last config change, scheduled ones included
+    last_config_change: Option<u64>,
+    // accounts the owner allows players to tip, and the tips each has received
+    tip_destinations: UnorderedMap<AccountId, bool>,
+    total_tips: UnorderedMap<AccountId, Balance>,
+    // block timestamp of each account's last consolation
+    last_consolation: LookupMap<AccountId, u64>,
+    // (block timestamp, odds) for every change of the base odds, oldest first. `odds_since`
+    // is the entry before the first one: the odds at deployment, or the last evicted change.
+    odds_since: (u64, u8),
+    odds_timeline: Vector<(u64, u8)>,
+    // trial spins left for accounts seen since trials were enabled, and the locked bonus
+    // their wins earned
+    trials_remaining: UnorderedMap<AccountId, u8>,
+    bonus_credits: UnorderedMap<AccountId, BonusCredits>,
+    total_bonus_credits: Balance,
+    // the last MAX_IDEMPOTENCY_KEYS keys each account passed to `play`, oldest first, with
+    // the result of the play each one made
+    play_keys: UnorderedMap<AccountId, Vec<(String, PlayResult)>>,
+    // share of each deposit an account routes into savings, and the savings balances; savings
+    // can only be withdrawn, never played
+    savings_split_bps: LookupMap<AccountId, u16>,
+    savings: UnorderedMap<AccountId, Balance>,
+    total_savings: Balance,
+    // standing deposits and the allowances still waiting to be moved into credits
+    recurring_deposits: UnorderedMap<AccountId, RecurringDeposit>,
+    total_recurring_allowance: Balance,
+    // ring of WINDOW_BUCKETS hourly buckets, each tagged with the hour it holds; a bucket
+    // is stale once the ring has come round to it again
+    window_buckets: LookupMap<u64, (u64, WindowStats)>,
+    // owner-funded balance milestone bonuses are paid from, and plays towards the next one
+    milestone_pool: Balance,
+    plays_since_milestone: u64,
+    // donations waiting for `donate_to_charity`, and everything sent to the charity so far
+    charity_pool: Balance,
+    total_donated: Balance,
+    // accounts' client seeds and the server seeds paired with them, and how many accounts
+    // have set one, so rolls skip the lookup while none have
+    fairness_seeds: LookupMap<AccountId, FairnessSeeds>,
+    seeded_accounts: u64,
+    // bets waiting for `resolve`, at most one per account
+    pending_bets: UnorderedMap<AccountId, PendingBet>,
+    rapid_plays: LookupMap<AccountId, RapidPlays>,
+}
+
+impl Default for SlotMachine {
+    fn default() -> Self {
+        panic!("Should be initialized before usage")
+    }
 }
 
-impl Default for SlotMachine {
-    fn default() -> Self {
-        panic!("Should be initialized before usage")
+#[near_bindgen]
+impl SlotMachine {
+    #[init]
+    pub fn new(owner_id: AccountId) -> Self {
+        Self::new_with_config(owner_id, GameConfig::default())
+    }
+
+    // Initializes every game parameter in the same transaction, so a deployment is never
+    // live with a partial or invalid configuration
+    #[init]
+    pub fn new_with_config(owner_id: AccountId, config: GameConfig) -> Self {
+        Self::new_with_options(owner_id, config, GameMetadata::default(), NetworkMode::Mainnet)
+    }
+
+    // Like `new_with_config`, but also sets the game's metadata and the network it runs on.
+    // Only the metadata can be changed later.
+    #[init]
+    pub fn new_with_options(owner_id: AccountId, config: GameConfig, metadata: GameMetadata, network: NetworkMode) -> Self {
+        assert!(env::is_valid_account_id(owner_id.as_bytes()), "Invalid owner account!");
+        assert!(!env::state_exists(), "Already initialized!");
+        config.assert_valid();
+        metadata.assert_valid();
+
+        env::log(format!("Creating a SlotMachine with owner id '{}'", &owner_id).as_bytes());
+
+        let odds_since = (env::block_timestamp(), config.odds);
+        Self {
+            owner_id: owner_id.clone(),
+            credits: UnorderedMap::new(StorageKey::Credits),
+            vault_apr_bps: 0,
+            vaults: UnorderedMap::new(StorageKey::Vaults),
+            nonce: 0,
+            config,
+            accumulated_fees: 0,
+            matches: UnorderedMap::new(StorageKey::Matches),
+            next_match_id: 0,
+            total_player_credits: 0,
+            total_locked_credits: 0,
+            total_vault_interest: 0,
+            blacklist: UnorderedMap::new(StorageKey::Blacklist),
+            stats: UnorderedMap::new(StorageKey::Stats),
+            total_withdrawn: UnorderedMap::new(StorageKey::TotalWithdrawn),
+            play_proofs: UnorderedMap::new(StorageKey::PlayProofs),
+            claimable: UnorderedMap::new(StorageKey::Claimable),
+            total_claimable: 0,
+            history: UnorderedMap::new(StorageKey::History),
+            treasury_splits: vec![(owner_id, MAX_BPS)],
+            paused: false,
+            entropy_pool: vec![],
+            metadata,
+            event_seq: 0,
+            block_payout: (0, 0),
+            block_winners: (0, 0),
+            created_at: env::block_timestamp(),
+            total_wagered: 0,
+            airdrops: UnorderedMap::new(StorageKey::Airdrops),
+            claimable_since: UnorderedMap::new(StorageKey::ClaimableSince),
+            network,
+            forced_roll: None,
+            roll_histogram: LookupMap::new(StorageKey::RollHistogram),
+            deposit_history: UnorderedMap::new(StorageKey::DepositHistory),
+            first_seen: UnorderedMap::new(StorageKey::FirstSeen),
+            cashback: UnorderedMap::new(StorageKey::Cashback),
+            auto_payout: UnorderedMap::new(StorageKey::AutoPayout),
+            allowances: LookupMap::new(StorageKey::Allowances),
+            house_pnl: 0,
+            fees_collected: 0,
+            reinvested_profit: 0,
+            reward_points: UnorderedMap::new(StorageKey::RewardPoints),
+            credits_recount: None,
+            daily_wins: UnorderedMap::new(StorageKey::DailyWins),
+            last_rolls: UnorderedMap::new(StorageKey::LastRolls),
+            signers: Vec::new(),
+            required_approvals: 0,
+            proposals: UnorderedMap::new(StorageKey::Proposals),
+            next_proposal_id: 0,
+            lp_shares: UnorderedMap::new(StorageKey::LpShares),
+            total_lp_shares: 0,
+            last_activity: LookupMap::new(StorageKey::LastActivity),
+            pending_config: None,
+            last_config_change: None,
+            tip_destinations: UnorderedMap::new(StorageKey::TipDestinations),
+            total_tips: UnorderedMap::new(StorageKey::TotalTips),
+            last_consolation: LookupMap::new(StorageKey::LastConsolation),
+            odds_since,
+            odds_timeline: Vector::new(StorageKey::OddsTimeline),
+            trials_remaining: UnorderedMap::new(StorageKey::TrialsRemaining),
+            bonus_credits: UnorderedMap::new(StorageKey::BonusCredits),
+            total_bonus_credits: 0,
+            play_keys: UnorderedMap::new(StorageKey::PlayKeys),
+            savings_split_bps: LookupMap::new(StorageKey::SavingsSplitBps),
+            savings: UnorderedMap::new(StorageKey::Savings),
+            total_savings: 0,
+            recurring_deposits: UnorderedMap::new(StorageKey::RecurringDeposits),
+            total_recurring_allowance: 0,
+            window_buckets: LookupMap::new(StorageKey::WindowBuckets),
+            milestone_pool: 0,
+            plays_since_milestone: 0,
+            charity_pool: 0,
+            total_donated: 0,
+            fairness_seeds: LookupMap::new(StorageKey::FairnessSeeds),
+            seeded_accounts: 0,
+            pending_bets: UnorderedMap::new(StorageKey::PendingBets),
+            rapid_plays: LookupMap::new(StorageKey::RapidPlays),
+        }
+    }
+
+    #[payable]
+    // Returns the account's credits after the deposit
+    pub fn deposit(&mut self) -> U128 {
+        let account_id = env::signer_account_id();
+        assert_valid_caller(&account_id);
+        self.assert_not_blacklisted(&account_id);
+
+        self.deposit_internal(&account_id, env::attached_deposit());
+
+        self.assert_solvent();
+        self.credits.get(&account_id).unwrap_or(0).into()
+    }
+
+    // Splits the attached deposit into credits for several accounts, e.g. to onboard a team.
+    // Whatever the allocations leave over is refunded to the caller.
+    #[payable]
+    pub fn fund_accounts(&mut self, allocations: Vec<(AccountId, U128)>) {
+        let funder = env::predecessor_account_id();
+        assert!(!allocations.is_empty(), "Nothing to fund!");
+        assert!(allocations.len() <= MAX_FUND_ACCOUNTS, "At most {} accounts per call!", MAX_FUND_ACCOUNTS);
+        let total = allocations.iter().fold(0u128, |total, (_, amount)| total.checked_add(amount.0).expect("Allocations overflow!"));
+        let deposit = env::attached_deposit();
+        assert!(total <= deposit, "Allocations of {} exceed the attached deposit of {}", total, deposit);
+
+        for (account_id, amount) in allocations {
+            assert!(env::is_valid_account_id(account_id.as_bytes()), "Invalid account '{}'!", account_id);
+            self.assert_not_blacklisted(&account_id);
+            self.admit_account(&account_id);
+            self.add_credits(&account_id, amount.0);
+            self.emit_player_event("account_funded", json!({
+                "funder": funder,
+                "account_id": account_id,
+                "amount": amount,
+            }));
+        }
+        if deposit > total {
+            Promise::new(funder).transfer(deposit - total);
+        }
+
+        self.assert_solvent();
+    }
+
+    // `stake` is optional so existing callers keep playing the minimum bet. A retried call
+    // with the same `idempotency_key` returns the first call's roll without playing again.
+    // Not payable on purpose, and the deposit is checked here as well as by near_bindgen so
+    // the rejection can't be lost. `deposit_and_play` is the way to top up and spin.
+    pub fn play(&mut self, stake: Option<U128>, idempotency_key: Option<String>) -> u8{
+        assert!(env::attached_deposit() == 0, "play doesn't take a deposit, use deposit_and_play!");
+        self.apply_due_config_change();
+        let account_id = env::signer_account_id();
+        assert_valid_caller(&account_id);
+        if let Some(key) = &idempotency_key {
+            assert!(key.len() <= MAX_IDEMPOTENCY_KEY_LENGTH, "Idempotency key is too long!");
+            if let Some(result) = self.keyed_play(&account_id, key) {
+                return result.roll;
+            }
+        }
+        self.assert_not_blacklisted(&account_id);
+        self.assert_not_paused();
+        self.assert_cooldown_passed(&account_id);
+        if self.pause_on_degenerate_seed() {
+            // nothing was staked, so the roll is only a placeholder
+            return u8::MAX;
+        }
+
+        let storage_before = env::storage_usage();
+        // trial spins roll on the spot, so they wait while plays resolve a block later
+        let result = if self.config.delayed_resolution {
+            self.place_pending_bet(&account_id, stake.unwrap_or(self.config.min_bet).0)
+        } else if self.trials_remaining(&account_id) > 0 {
+            self.trial_play(&account_id)
+        } else {
+            let stake = stake.unwrap_or(self.config.min_bet).0;
+            self.play_internal(&account_id, stake, false)
+        };
+        if let Some(key) = idempotency_key {
+            self.remember_play_key(&account_id, key, &result);
+        }
+        self.settle_play_storage(&account_id, storage_before);
+
+        self.assert_solvent();
+        result.roll
+    }
+
+    // Like `play`, but returns the reason instead of panicking when the play can't be made.
+    // Apart from a possible consolation nothing changes on a rejection; in particular a
+    // degenerate seed is reported here rather than pausing the game.
+    pub fn try_play(&mut self, stake: U128) -> PlayOutcome {
+        self.apply_due_config_change();
+        let account_id = env::signer_account_id();
+        if self.config.delayed_resolution {
+            return PlayOutcome::Rejected(String::from(DELAYED_PLAY_REJECTION));
+        }
+        if let Some(reason) = self.full_play_rejection(&account_id, stake.0) {
+            if self.is_house_rejection(&account_id, stake.0, &reason) {
+                self.pay_consolation(&account_id);
+            }
+            return PlayOutcome::Rejected(reason);
+        }
+
+        let storage_before = env::storage_usage();
+        let result = self.play_internal(&account_id, stake.0, false);
+        self.settle_play_storage(&account_id, storage_before);
+
+        self.assert_solvent();
+        PlayOutcome::Ok(result)
+    }
+
+    // The canonical way to show a yoctoNEAR amount in NEAR: cut (not rounded) to
+    // `display_decimals` fractional digits, with trailing zeros trimmed, e.g. "1.5" or "12"
+    pub fn format_balance(&self, amount: U128) -> String {
+        let amount: Balance = amount.into();
+        let whole = amount / ONE_NEAR;
+        let cut = 10u128.pow((TOKEN_DECIMALS - self.config.display_decimals) as u32);
+        let fraction = amount % ONE_NEAR / cut;
+        if fraction == 0 {
+            return whole.to_string();
+        }
+        let digits = format!("{:0width$}", fraction, width = self.config.display_decimals as usize);
+        format!("{}.{}", whole, digits.trim_end_matches('0'))
+    }
+
+    // Cooldown a play of `stake` would start
+    pub fn get_effective_cooldown(&self, stake: U128) -> u64 {
+        self.config.cooldown_for(stake.into())
+    }
+
+    // Earliest block timestamp the account's cooldown lets it play again at
+    pub fn get_next_play_at(&self, account_id: AccountId) -> u64 {
+        self.next_play_at(&account_id)
+    }
+
+    // End of the account's enforced break, while it's on one
+    pub fn get_enforced_break(&self, account_id: AccountId) -> Option<u64> {
+        self.enforced_break(&account_id)
+    }
+
+    // Factor the next play's payout multiplier is scaled by; MAX_BPS leaves it unchanged
+    pub fn get_payout_adjustment_bps(&self) -> u32 {
+        self.payout_adjustment_bps()
+    }
+
+    // Whether `play` with the minimum bet would go through for the account right now, for
+    // enabling a UI's play button
+    pub fn can_play(&self, account_id: AccountId) -> bool {
+        self.full_play_rejection(&account_id, self.config.min_bet.0).is_none()
+    }
+
+    // Plays up to `rounds` times at the same stake, stopping early instead of failing once
+    // the credits no longer cover the stake and fee, or a later round is turned away, e.g.
+    // by a break or cap the batch itself ran into
+    pub fn play_batch(&mut self, rounds: u32, stake: Option<U128>) -> BatchResult {
+        self.apply_due_config_change();
+        self.assert_immediate_play();
+        let account_id = env::signer_account_id();
+        self.assert_not_blacklisted(&account_id);
+        assert!(rounds > 0 && rounds <= MAX_BATCH_ROUNDS, "Rounds must be between 1 and {}", MAX_BATCH_ROUNDS);
+        self.assert_not_paused();
+        // the batch counts as one visit, so only its first round waits for the cooldown
+        self.assert_cooldown_passed(&account_id);
+
+        let stake = self.config.round_to_tick(stake.unwrap_or(self.config.min_bet).0);
+        let cost = stake + self.account_fee(&account_id, stake);
+        let storage_before = env::storage_usage();
+        let mut results = vec![];
+        while !self.pause_on_degenerate_seed() && results.len() < rounds as usize && self.credits.get(&account_id).unwrap_or(0) >= cost {
+            if let Some(reason) = self.play_rejection(&account_id, stake) {
+                assert!(!results.is_empty(), "{}", reason);
+                break;
+            }
+            results.push(self.play_round(&account_id, stake, false));
+        }
+
+        // one event for the whole batch; `rolls`, `won` and `payouts` hold each round in order
+        if !results.is_empty() {
+            let total_wagered = stake * results.len() as u128;
+            let total_won: Balance = results.iter().map(|result| result.payout.0).sum();
+            self.emit_player_event("batch_play", json!({
+                "account_id": account_id,
+                "rounds": results.len(),
+                "stake": U128::from(stake),
+                "total_wagered": U128::from(total_wagered),
+                "total_won": U128::from(total_won),
+                "net": (total_won as i128 - total_wagered as i128).to_string(),
+                "rolls": results.iter().map(|result| result.roll).collect::<Vec<_>>(),
+                "won": results.iter().map(|result| result.won).collect::<Vec<_>>(),
+                "payouts": results.iter().map(|result| result.payout).collect::<Vec<_>>(),
+            }));
+        }
+        self.settle_play_storage(&account_id, storage_before);
+
+        self.assert_solvent();
+        BatchResult {
+            rounds_played: results.len() as u32,
+            rounds_requested: rounds,
+            results,
+            ending_balance: self.credits.get(&account_id).unwrap_or(0).into(),
+        }
+    }
+
+    // Plays up to MAX_BATCH_ROUNDS times, and only starts a round if losing it would keep
+    // the batch's net loss (stakes and fees less everything paid back) within
+    // `max_total_loss`. Wins make room for more rounds. Like `play_batch`, a round turned
+    // away after the first ends the batch early.
+    pub fn play_until_loss(&mut self, stake: U128, max_total_loss: U128) -> BatchResult {
+        self.apply_due_config_change();
+        self.assert_immediate_play();
+        let account_id = env::signer_account_id();
+        self.assert_not_blacklisted(&account_id);
+        self.assert_not_paused();
+        self.assert_cooldown_passed(&account_id);
+
+        let stake = self.config.round_to_tick(stake.0);
+        let cost = stake + self.account_fee(&account_id, stake);
+        let worst_loss = (cost - self.config.rebate(stake)) as i128;
+        let mut net_loss: i128 = 0;
+        let storage_before = env::storage_usage();
+        let mut results = vec![];
+        while results.len() < MAX_BATCH_ROUNDS as usize
+            && net_loss + worst_loss <= max_total_loss.0 as i128
+            && self.credits.get(&account_id).unwrap_or(0) >= cost
+            && !self.pause_on_degenerate_seed()
+        {
+            if let Some(reason) = self.play_rejection(&account_id, stake) {
+                assert!(!results.is_empty(), "{}", reason);
+                break;
+            }
+            let result = self.play_internal(&account_id, stake, false);
+            let returned = if result.won { result.payout.0 } else { self.config.rebate(stake) };
+            net_loss += cost as i128 - returned as i128;
+            results.push(result);
+        }
+        self.settle_play_storage(&account_id, storage_before);
+
+        self.assert_solvent();
+        BatchResult {
+            rounds_played: results.len() as u32,
+            rounds_requested: MAX_BATCH_ROUNDS,
+            results,
+            ending_balance: self.credits.get(&account_id).unwrap_or(0).into(),
+        }
+    }
+
+    // Like `play`, but also pays a premium of `insurance_bps` of the stake to get
+    // `insurance_refund_bps` of the stake back on a loss. The premium goes to the house and
+    // must be at least the expected refund at the player's current odds.
+    pub fn play_with_insurance(&mut self, stake: U128, insurance_bps: u16) -> PlayResult {
+        self.apply_due_config_change();
+        self.assert_immediate_play();
+        let account_id = env::signer_account_id();
+        self.assert_not_blacklisted(&account_id);
+        self.assert_not_paused();
+        self.assert_cooldown_passed(&account_id);
+        let stake = self.config.round_to_tick(stake.into());
+
+        let min_insurance_bps = self.config.min_insurance_bps(self.effective_odds(&account_id));
+        assert!(
+            self.config.max_insurance_bps > 0
+                && insurance_bps >= min_insurance_bps
+                && insurance_bps <= self.config.max_insurance_bps,
+            "Insurance premium must be between {} and {} bps",
+            min_insurance_bps, self.config.max_insurance_bps
+        );
+        let premium = self.config.rounding.apply_bps(stake, insurance_bps as u128);
+        let cost = stake + self.account_fee(&account_id, stake) + premium;
+        let credits = self.credits.get(&account_id).unwrap_or(0);
+        assert!(credits >= cost, "Insufficient credits: have {}, need {}", credits, cost);
+        if self.pause_on_degenerate_seed() {
+            // nothing was staked or insured
+            return self.unplayed_result(&account_id);
+        }
+
+        let storage_before = env::storage_usage();
+        self.remove_credits(&account_id, premium);
+        self.house_pnl += premium as i128;
+        let result = self.play_internal(&account_id, stake, true);
+        self.settle_play_storage(&account_id, storage_before);
+
+        self.assert_solvent();
+        result
+    }
+
+    // Plays right away even if the account's cooldown hasn't passed, for `priority_fee` on
+    // top of the stake and fee. The priority fee goes to the house bankroll.
+    pub fn play_priority(&mut self, stake: U128) -> PlayResult {
+        self.apply_due_config_change();
+        self.assert_immediate_play();
+        let account_id = env::signer_account_id();
+        self.assert_not_blacklisted(&account_id);
+        self.assert_not_paused();
+
+        let stake = self.config.round_to_tick(stake.into());
+        let priority_fee = self.config.priority_fee.0;
+        let cost = stake + self.account_fee(&account_id, stake) + priority_fee;
+        let credits = self.credits.get(&account_id).unwrap_or(0);
+        assert!(credits >= cost, "Insufficient credits: have {}, need {}", credits, cost);
+        if self.pause_on_degenerate_seed() {
+            return self.unplayed_result(&account_id);
+        }
+
+        let storage_before = env::storage_usage();
+        self.remove_credits(&account_id, priority_fee);
+        self.house_pnl += priority_fee as i128;
+        let result = self.play_internal(&account_id, stake, false);
+        self.settle_play_storage(&account_id, storage_before);
+
+        self.assert_solvent();
+        result
+    }
+
+    // Spends `points_per_cooldown_reset` reward points to end the caller's cooldown now.
+    // `points` is what the caller is willing to spend and has to cover the rate.
+    pub fn spend_points_reset_cooldown(&mut self, points: U128) {
+        let account_id = env::signer_account_id();
+        let cost = self.config.points_per_cooldown_reset.0;
+        assert!(cost > 0, "Cooldown resets are disabled!");
+        let now = env::block_timestamp();
+        assert!(now < self.next_play_at(&account_id), "Not in cooldown!");
+        assert!(points.0 >= cost, "A cooldown reset costs {} points", cost);
+        let balance = self.reward_points.get(&account_id).unwrap_or(0);
+        assert!(balance >= cost, "Insufficient reward points: have {}, need {}", balance, cost);
+
+        self.reward_points.insert(&account_id, &(balance - cost));
+        let mut stats = self.stats.get(&account_id).unwrap_or_default();
+        stats.last_played_at = now.saturating_sub(self.config.cooldown_for(stats.last_stake));
+        self.stats.insert(&account_id, &stats);
+        self.emit_player_event("cooldown_reset", json!({
+            "account_id": account_id,
+            "points": U128::from(cost),
+        }));
+    }
+
+    // Lets `delegate` play for the caller with up to `limit` of their credits, counting
+    // stakes and fees. A zero limit revokes the approval.
+    pub fn approve_player(&mut self, delegate: AccountId, limit: U128) {
+        let account_id = env::signer_account_id();
+        assert!(env::is_valid_account_id(delegate.as_bytes()), "Invalid delegate account!");
+        assert_ne!(account_id, delegate, "Can't approve yourself!");
+
+        let key = (account_id, delegate);
+        if limit.0 == 0 {
+            self.allowances.remove(&key);
+        } else {
+            self.allowances.insert(&key, &limit.0);
+        }
+    }
+
+    pub fn get_allowance(&self, owner: AccountId, delegate: AccountId) -> U128 {
+        self.allowances.get(&(owner, delegate)).unwrap_or(0).into()
+    }
+
+    // Places a hidden delayed bet, committing to its stake with `bet_commitment` so nothing
+    // about it shows until `resolve`. Escrows as much of the caller's credits as the largest
+    // bet could cost, since the stake itself isn't known yet, and returns the escrow.
+    pub fn request_play(&mut self, commitment: Base64VecU8) -> U128 {
+        self.apply_due_config_change();
+        let account_id = env::signer_account_id();
+        assert_valid_caller(&account_id);
+        assert!(self.config.hidden_bets, "Hidden bets are disabled!");
+        assert!(commitment.0.len() == 32, "Commitment must be a sha256 hash!");
+        self.assert_not_blacklisted(&account_id);
+        self.assert_not_paused();
+        self.assert_cooldown_passed(&account_id);
+        assert!(self.pending_bets.get(&account_id).is_none(), "Resolve your pending bet first!");
+
+        let max_bet = self.config.max_bet.0;
+        let credits = self.credits.get(&account_id).unwrap_or(0);
+        let escrow = (max_bet + self.account_fee(&account_id, max_bet)).min(credits);
+        assert!(escrow > 0, "Not enough credits to bet!");
+        let storage_before = env::storage_usage();
+        self.remove_credits(&account_id, escrow);
+        self.total_locked_credits += escrow;
+        let bet = PendingBet { stake: escrow.into(), fee: 0.into(), block_index: env::block_index(), commitment: Some(commitment) };
+        self.pending_bets.insert(&account_id, &bet);
+        self.settle_play_storage(&account_id, storage_before);
+
+        self.emit_player_event("bet_committed", json!({
+            "account_id": account_id,
+            "escrow": bet.stake,
+            "block_index": bet.block_index,
+        }));
+        self.assert_solvent();
+        escrow.into()
+    }
+
+    // Rolls the caller's pending bet with this block's seed. A bet that can no longer be
+    // played, e.g. because the house can't cover it any more, is refunded instead. A hidden
+    // bet needs the `reveal` of the stake and salt it was committed to.
+    pub fn resolve(&mut self, reveal: Option<BetReveal>) -> PlayResult {
+        self.apply_due_config_change();
+        let account_id = env::signer_account_id();
+        let result = self.resolve_internal(&account_id, reveal);
+
+        self.assert_solvent();
+        result
+    }
+
+    // Like `resolve`, but for any account's bet, so keepers can settle bets left pending.
+    // Hidden bets can only be resolved by their player, who knows the reveal.
+    pub fn resolve_for(&mut self, account_id: AccountId) -> PlayResult {
+        self.apply_due_config_change();
+        let result = self.resolve_internal(&account_id, None);
+
+        self.assert_solvent();
+        result
+    }
+
+    // Anyone can return the escrow of a hidden bet left unrevealed for `reveal_timeout_blocks`
+    // to its player, since nobody else can ever resolve it
+    pub fn refund_hidden_bet(&mut self, account_id: AccountId) -> U128 {
+        let bet = self.pending_bets.get(&account_id).expect("No pending bet!");
+        assert!(bet.commitment.is_some(), "Only hidden bets can be refunded!");
+        let expires_at = bet.block_index.saturating_add(self.config.reveal_timeout_blocks);
+        assert!(env::block_index() >= expires_at, "Bet can't be refunded before block {}", expires_at);
+
+        let escrow = bet.stake.0 + bet.fee.0;
+        let storage_before = env::storage_usage();
+        self.pending_bets.remove(&account_id);
+        self.total_locked_credits -= escrow;
+        self.add_credits(&account_id, escrow);
+        self.settle_play_storage(&account_id, storage_before);
+        self.emit_player_event("bet_refunded", json!({
+            "account_id": account_id,
+            "stake": bet.stake,
+            "reason": "Reveal timed out",
+        }));
+
+        self.assert_solvent();
+        escrow.into()
+    }
+
+    pub fn get_pending_bet(&self, account_id: AccountId) -> Option<PendingBet> {
+        self.pending_bets.get(&account_id)
+    }
+
+    // Scans `limit` pending bets from `from_index` and returns the (account, bet block index)
+    // of those a block has passed since, so a page can hold fewer than `limit`. Hidden bets
+    // are left out, since only their player can resolve them.
+    pub fn get_resolvable_bets(&self, from_index: u64, limit: u64) -> Vec<(AccountId, u64)> {
+        assert!(limit > 0 && limit <= MAX_RESOLVABLE_PAGE, "Page size must be between 1 and {}", MAX_RESOLVABLE_PAGE);
+
+        let keys = self.pending_bets.keys_as_vector();
+        let values = self.pending_bets.values_as_vector();
+        let end = (from_index + limit).min(keys.len());
+        (from_index..end)
+            .map(|index| (keys.get(index).unwrap(), values.get(index).unwrap()))
+            .filter(|(_, bet)| bet.block_index < env::block_index() && bet.commitment.is_none())
+            .map(|(account_id, bet)| (account_id, bet.block_index))
+            .collect()
+    }
+
+    // Plays from `owner`'s credits on behalf of the calling delegate, which must have
+    // enough allowance left for the stake and fee
+    pub fn play_for(&mut self, owner: AccountId, stake: U128) -> PlayResult {
+        self.apply_due_config_change();
+        self.assert_immediate_play();
+        let delegate = env::predecessor_account_id();
+        self.assert_not_blacklisted(&owner);
+        self.assert_not_paused();
+        self.assert_cooldown_passed(&owner);
+
+        let stake = self.config.round_to_tick(stake.into());
+        let cost = stake + self.account_fee(&owner, stake);
+        let key = (owner.clone(), delegate);
+        let allowance = self.allowances.get(&key).unwrap_or(0);
+        assert!(allowance >= cost, "Allowance of {} doesn't cover the stake and fee of {}", allowance, cost);
+        if self.pause_on_degenerate_seed() {
+            // nothing was staked, so the allowance is untouched
+            return self.unplayed_result(&owner);
+        }
+
+        let storage_before = env::storage_usage();
+        self.allowances.insert(&key, &(allowance - cost));
+        let result = self.play_internal(&owner, stake, false);
+        self.settle_play_storage(&owner, storage_before);
+
+        self.assert_solvent();
+        result
+    }
+
+    // Credits the attached deposit and plays in one call, so a new player doesn't need a
+    // separate deposit transaction first
+    #[payable]
+    pub fn deposit_and_play(&mut self, stake: U128) -> PlayResult {
+        self.apply_due_config_change();
+        self.assert_immediate_play();
+        let account_id = env::signer_account_id();
+        self.assert_not_blacklisted(&account_id);
+
+        let deposit_amount = env::attached_deposit();
+        let credits = self.credits.get(&account_id).unwrap_or(0);
+        let cost = stake.0 + self.account_fee(&account_id, stake.0);
+        assert!(
+            credits + deposit_amount >= cost,
+            "Attached deposit of {} plus existing credits of {} doesn't cover the stake and fee of {}",
+            deposit_amount, credits, cost
+        );
+
+        self.assert_not_paused();
+        self.assert_cooldown_passed(&account_id);
+
+        self.deposit_internal(&account_id, deposit_amount);
+        if self.pause_on_degenerate_seed() {
+            // the deposit is kept as credits, but nothing is staked
+            return self.unplayed_result(&account_id);
+        }
+        let storage_before = env::storage_usage();
+        let result = self.play_internal(&account_id, stake.0, false);
+        self.settle_play_storage(&account_id, storage_before);
+
+        self.assert_solvent();
+        result
+    }
+
+    // Still available to blacklisted accounts so they can exit with their credits. The
+    // transfer is scheduled before returning.
+    pub fn withdraw(&mut self, amount: U128) -> WithdrawResult {
+        assert_prepaid_gas(MIN_GAS_FOR_WITHDRAW);
+        let account_id = env::signer_account_id();
+        assert_valid_caller(&account_id);
+        let amount: Balance = amount.into();
+        assert!(amount > 0, "Nothing to withdraw!");
+
+        let credits = self.credits.get(&account_id).unwrap_or(0);
+        assert!(credits >= amount, "Not enough credits to withdraw!");
+        // don't schedule a transfer the contract can't cover without dipping into storage stake
+        assert!(amount <= self.free_balance(), "Temporarily insufficient contract liquidity");
+        self.remove_credits(&account_id, amount);
+        self.record_activity(&account_id);
+        let withdrawn = self.total_withdrawn.get(&account_id).unwrap_or(0);
+        self.total_withdrawn.insert(&account_id, &(withdrawn + amount));
+
+        self.emit_player_event("withdraw", json!({
+            "account_id": account_id,
+            "amount": U128::from(amount),
+        }));
+
+        self.assert_solvent();
+        Promise::new(account_id.clone()).transfer(amount);
+        WithdrawResult {
+            amount: amount.into(),
+            remaining: self.credits.get(&account_id).unwrap_or(0).into(),
+        }
+    }
+
+    // Routes `split_bps` of the caller's future deposits into savings
+    pub fn set_savings_split(&mut self, split_bps: u16) {
+        let account_id = env::signer_account_id();
+        assert!(split_bps <= MAX_BPS, "Savings split can't exceed 100%!");
+        if split_bps == 0 {
+            self.savings_split_bps.remove(&account_id);
+        } else {
+            self.savings_split_bps.insert(&account_id, &split_bps);
+        }
+    }
+
+    pub fn get_savings_split(&self, account_id: AccountId) -> u16 {
+        self.savings_split_bps.get(&account_id).unwrap_or(0)
+    }
+
+    pub fn get_savings(&self, account_id: AccountId) -> U128 {
+        self.savings.get(&account_id).unwrap_or(0).into()
+    }
+
+    // Like `withdraw`, but out of savings
+    pub fn withdraw_savings(&mut self, amount: U128) -> WithdrawResult {
+        assert_prepaid_gas(MIN_GAS_FOR_WITHDRAW);
+        let account_id = env::signer_account_id();
+        assert_valid_caller(&account_id);
+        let amount: Balance = amount.into();
+        assert!(amount > 0, "Nothing to withdraw!");
+
+        let savings = self.savings.get(&account_id).unwrap_or(0);
+        assert!(savings >= amount, "Not enough savings to withdraw!");
+        assert!(amount <= self.free_balance(), "Temporarily insufficient contract liquidity");
+        if savings == amount {
+            self.savings.remove(&account_id);
+        } else {
+            self.savings.insert(&account_id, &(savings - amount));
+        }
+        self.total_savings -= amount;
+        self.record_activity(&account_id);
+        let withdrawn = self.total_withdrawn.get(&account_id).unwrap_or(0);
+        self.total_withdrawn.insert(&account_id, &(withdrawn + amount));
+
+        self.emit_player_event("savings_withdraw", json!({
+            "account_id": account_id,
+            "amount": U128::from(amount),
+        }));
+
+        self.assert_solvent();
+        Promise::new(account_id).transfer(amount);
+        WithdrawResult {
+            amount: amount.into(),
+            remaining: (savings - amount).into(),
+        }
+    }
+
+    // Schedules a deposit of `amount_per_period` every `period_ns`, the first one due now. The
+    // contract can't pull funds from the wallet, so the attached deposit tops up an allowance
+    // the deposits are drawn from; `execute_recurring` makes them once due.
+    #[payable]
+    pub fn set_recurring_deposit(&mut self, amount_per_period: U128, period_ns: u64) -> RecurringDeposit {
+        let account_id = env::signer_account_id();
+        assert_valid_caller(&account_id);
+        self.assert_not_blacklisted(&account_id);
+        assert!(amount_per_period.0 > 0, "Recurring amount must be positive!");
+        assert!(period_ns > 0, "Recurring period must be positive!");
+
+        let topped_up = env::attached_deposit();
+        let (next_due, allowance) = match self.recurring_deposits.get(&account_id) {
+            Some(recurring) => (recurring.next_due, recurring.allowance.0 + topped_up),
+            None => (env::block_timestamp(), topped_up),
+        };
+        let recurring = RecurringDeposit { amount_per_period, period_ns, next_due, allowance: allowance.into() };
+        self.recurring_deposits.insert(&account_id, &recurring);
+        self.total_recurring_allowance += topped_up;
+
+        self.assert_solvent();
+        recurring
+    }
+
+    // Stops the caller's recurring deposit and refunds what's left of the allowance
+    pub fn cancel_recurring_deposit(&mut self) -> U128 {
+        let account_id = env::signer_account_id();
+        let recurring = self.recurring_deposits.remove(&account_id).expect("No recurring deposit!");
+        let refund = recurring.allowance.0;
+        self.total_recurring_allowance -= refund;
+        if refund > 0 {
+            Promise::new(account_id).transfer(refund);
+        }
+
+        self.assert_solvent();
+        refund.into()
+    }
+
+    // Makes `account_id`'s recurring deposit once it's due. Anyone can call it, e.g. a keeper bot.
+    pub fn execute_recurring(&mut self, account_id: AccountId) -> U128 {
+        self.assert_not_blacklisted(&account_id);
+        let mut recurring = self.recurring_deposits.get(&account_id).expect("No recurring deposit!");
+        let now = env::block_timestamp();
+        assert!(now >= recurring.next_due, "Recurring deposit isn't due until {}", recurring.next_due);
+        let amount = recurring.amount_per_period.0;
+        assert!(recurring.allowance.0 >= amount, "Recurring allowance is used up, top it up!");
+
+        recurring.allowance = (recurring.allowance.0 - amount).into();
+        recurring.next_due = now.saturating_add(recurring.period_ns);
+        self.recurring_deposits.insert(&account_id, &recurring);
+        self.total_recurring_allowance -= amount;
+        self.deposit_internal(&account_id, amount);
+
+        self.assert_solvent();
+        self.credits.get(&account_id).unwrap_or(0).into()
+    }
+
+    pub fn get_recurring_deposit(&self, account_id: AccountId) -> Option<RecurringDeposit> {
+        self.recurring_deposits.get(&account_id)
+    }
+
+    // Adds the attached deposit to the pool milestone bonuses are paid from. Pooled funds are
+    // set aside for players and no longer count towards the house bankroll.
+    #[payable]
+    pub fn fund_milestone_pool(&mut self) -> U128 {
+        self.assert_owner();
+        self.milestone_pool += env::attached_deposit();
+        self.emit_event("milestone_pool_funded", json!({
+            "amount": U128::from(env::attached_deposit()),
+            "pool": U128::from(self.milestone_pool),
+        }));
+        self.milestone_pool.into()
+    }
+
+    // Anyone can send the pooled donations on to the charity account
+    pub fn donate_to_charity(&mut self) -> U128 {
+        let charity_account = self.config.charity_account.clone().expect("No charity account set!");
+        let amount = self.charity_pool;
+        assert!(amount > 0, "Nothing to donate!");
+
+        self.charity_pool = 0;
+        self.total_donated += amount;
+        Promise::new(charity_account.clone()).transfer(amount);
+        self.emit_event("charity_donation", json!({
+            "charity_account": charity_account,
+            "amount": U128::from(amount),
+            "total_donated": U128::from(self.total_donated),
+        }));
+
+        self.assert_solvent();
+        amount.into()
+    }
+
+    pub fn get_charity_pool(&self) -> CharityPool {
+        CharityPool {
+            charity_account: self.config.charity_account.clone(),
+            charity_bps: self.config.charity_bps,
+            pool: self.charity_pool.into(),
+            total_donated: self.total_donated.into(),
+        }
+    }
+
+    pub fn get_milestone_progress(&self) -> MilestoneProgress {
+        MilestoneProgress {
+            plays_since_milestone: self.plays_since_milestone,
+            milestone_interval: self.config.milestone_interval,
+            pool: self.milestone_pool.into(),
+            bonus: self.config.milestone_bonus,
+        }
+    }
+
+    // Totals over the hourly buckets `window_ns` reaches back into, counting the current one.
+    // The oldest bucket is counted whole, and windows longer than WINDOW_BUCKETS hours are
+    // cut to that.
+    pub fn get_window_stats(&self, window_ns: u64) -> WindowStats {
+        let hours = window_ns.div_ceil(NS_PER_HOUR).min(WINDOW_BUCKETS);
+        let current_hour = env::block_timestamp() / NS_PER_HOUR;
+        let mut totals = WindowStats { plays: 0, wins: 0, wagered: 0.into(), paid: 0.into() };
+        for hour in (current_hour + 1).saturating_sub(hours)..=current_hour {
+            if let Some((bucket_hour, stats)) = self.window_buckets.get(&(hour % WINDOW_BUCKETS)) {
+                if bucket_hour == hour {
+                    totals.plays += stats.plays;
+                    totals.wins += stats.wins;
+                    totals.wagered = (totals.wagered.0 + stats.wagered.0).into();
+                    totals.paid = (totals.paid.0 + stats.paid.0).into();
+                }
+            }
+        }
+        totals
+    }
+
+    // Moves accrued winnings into playable (and withdrawable) credits
+    pub fn claim_winnings(&mut self) -> U128 {
+        let account_id = env::signer_account_id();
+        let amount = self.remove_claimable(&account_id);
+        assert!(amount > 0, "Nothing to claim!");
+
+        self.add_credits(&account_id, amount);
+
+        env::log(format!("'{}' claimed {} in winnings", &account_id, amount).as_bytes());
+
+        self.assert_solvent();
+        amount.into()
+    }
+
+    // Pushes claimable winnings straight to the players' wallets. Accounts with nothing to
+    // claim are skipped; a failed transfer is restored to `claimable` by the callback.
+    pub fn process_payouts(&mut self, accounts: Vec<AccountId>) {
+        self.assert_owner();
+        assert!(accounts.len() <= MAX_PAYOUT_BATCH, "At most {} accounts per call!", MAX_PAYOUT_BATCH);
+        assert_prepaid_gas(MIN_GAS_FOR_PAYOUTS + GAS_FOR_PAYOUT_CALLBACK * accounts.len() as u64);
+
+        for account_id in accounts {
+            let amount = self.claimable.get(&account_id).unwrap_or(0);
+            if amount == 0 {
+                continue;
+            }
+            assert!(amount <= self.free_balance(), "Temporarily insufficient contract liquidity");
+            self.remove_claimable(&account_id);
+
+            self.emit_player_event("payout_processed", json!({
+                "account_id": account_id,
+                "amount": U128::from(amount),
+            }));
+
+            Promise::new(account_id.clone()).transfer(amount).then(ext_self::on_payout_transfer(
+                account_id,
+                amount.into(),
+                &env::current_account_id(),
+                0,
+                GAS_FOR_PAYOUT_CALLBACK,
+            ));
+        }
+
+        self.assert_solvent();
+    }
+
+    #[private]
+    pub fn on_payout_transfer(&mut self, account_id: AccountId, amount: U128) {
+        if let PromiseResult::Successful(_) = env::promise_result(0) {
+            return;
+        }
+
+        self.add_claimable(&account_id, amount.0);
+        env::log(format!("Payout of {} to '{}' failed and was restored", amount.0, &account_id).as_bytes());
+    }
+
+    // Restores an auto-payout win to credits if the transfer didn't go through
+    #[private]
+    pub fn on_auto_payout_transfer(&mut self, account_id: AccountId, amount: U128) {
+        if let PromiseResult::Successful(_) = env::promise_result(0) {
+            return;
+        }
+
+        self.add_credits(&account_id, amount.0);
+        env::log(format!("Auto-payout of {} to '{}' failed and was credited", amount.0, &account_id).as_bytes());
+    }
+
+    // With auto-payout on, winnings from non-pull plays are transferred to the player's
+    // wallet right away instead of being added to credits
+    pub fn set_auto_payout(&mut self, enabled: bool) {
+        let account_id = env::signer_account_id();
+        if enabled {
+            assert!(self.config.allow_auto_payout, "Auto-payout is disabled!");
+            self.auto_payout.insert(&account_id, &true);
+        } else {
+            self.auto_payout.remove(&account_id);
+        }
+    }
+
+    pub fn get_auto_payout(&self, account_id: AccountId) -> bool {
+        self.config.allow_auto_payout && self.auto_payout.get(&account_id).unwrap_or(false)
+    }
+
+    // Anyone can mix extra entropy into future rolls, so that predicting them takes more
+    // than knowing the block's random seed
+    pub fn contribute_entropy(&mut self, value: Base64VecU8) {
+        let value = Vec::<u8>::from(value);
+        assert!(!value.is_empty(), "Entropy can't be empty!");
+        assert!(value.len() <= MAX_ENTROPY_LENGTH, "Entropy can be at most {} bytes!", MAX_ENTROPY_LENGTH);
+
+        let mut input = self.entropy_pool.clone();
+        input.extend_from_slice(&value);
+        self.entropy_pool = env::sha256(&input);
+    }
+
+    pub fn get_claimable(&self, account_id: AccountId) -> U128 {
+        self.claimable.get(&account_id).unwrap_or(0).into()
+    }
+
+    // The most that can be withdrawn right now: the balance not locked for storage
+    pub fn available_liquidity(&self) -> U128 {
+        self.free_balance().into()
+    }
+
+    pub fn get_credits(&self, account_id: AccountId) -> U128 {
+        println!("get_credits");
+        self.credits.get(&account_id).unwrap_or(0).into()
+    }
+
+    // Sets or rotates the caller's client seed. Rotating draws a fresh server seed, restarts
+    // the seed nonce and reveals the retired server seed. Server seeds sit in contract state,
+    // so they only stop the player from picking a seed against a known one; the block's
+    // random seed keeps each roll unpredictable.
+    pub fn set_client_seed(&mut self, client_seed: String) -> SeedRotation {
+        let account_id = env::signer_account_id();
+        assert!(!client_seed.is_empty(), "Client seed can't be empty!");
+        assert!(client_seed.len() <= MAX_CLIENT_SEED_LENGTH, "Client seed is too long!");
+
+        let previous = self.fairness_seeds.get(&account_id);
+        let mut input = env::random_seed();
+        input.extend_from_slice(account_id.as_bytes());
+        if let Some(previous) = &previous {
+            input.extend_from_slice(&previous.server_seed);
+        }
+        let server_seed = env::sha256(&input);
+        if previous.is_none() {
+            self.seeded_accounts += 1;
+        }
+        let rotation = SeedRotation {
+            previous_client_seed: previous.as_ref().map(|seeds| seeds.client_seed.clone()),
+            previous_server_seed: previous.as_ref().map(|seeds| seeds.server_seed.clone().into()),
+            previous_nonce: previous.as_ref().map_or(0, |seeds| seeds.nonce),
+            server_seed_hash: env::sha256(&server_seed).into(),
+        };
+        self.fairness_seeds.insert(&account_id, &FairnessSeeds { client_seed, server_seed, nonce: 0 });
+        self.emit_player_event("seed_rotated", json!({
+            "account_id": account_id,
+            "previous_server_seed": rotation.previous_server_seed,
+            "server_seed_hash": rotation.server_seed_hash,
+        }));
+        rotation
+    }
+
+    // sha256 of the server seed the account's plays currently use
+    pub fn get_server_seed_hash(&self, account_id: AccountId) -> Option<Base64VecU8> {
+        self.fairness_seeds.get(&account_id).map(|seeds| env::sha256(&seeds.server_seed).into())
+    }
+
+    // The seed nonce the account's next play will use
+    pub fn get_current_nonce(&self, account_id: AccountId) -> u64 {
+        self.fairness_seeds.get(&account_id).map_or(0, |seeds| seeds.nonce)
+    }
+
+    // Inputs of the account's last play; see `PlayProof` for how to recompute the roll
+    pub fn get_play_proof(&self, account_id: AccountId) -> Option<PlayProof> {
+        self.play_proofs.get(&account_id)
+    }
+
+    // The roll, threshold and outcome of the account's last play
+    pub fn get_last_roll_detail(&self, account_id: AccountId) -> Option<LastRollDetail> {
+        self.last_rolls.get(&account_id)
+    }
+
+    // Most recent plays first, at most MAX_HISTORY of them
+    pub fn get_player_history(&self, account_id: AccountId, limit: u64) -> Vec<PlayResult> {
+        let history = self.history.get(&account_id).unwrap_or_default();
+        history.into_iter().rev().take(limit.min(MAX_HISTORY as u64) as usize).collect()
+    }
+
+    // Deposits oldest first, at most MAX_DEPOSIT_HISTORY of them
+    pub fn get_deposit_history(&self, account_id: AccountId, from_index: u64, limit: u64) -> Vec<DepositRecord> {
+        let history = self.deposit_history.get(&account_id).unwrap_or_default();
+        history.into_iter().skip(from_index as usize).take(limit as usize).collect()
+    }
+
+    // Largest credit balances, descending, ties broken by account id. This reads and sorts
+    // the whole credits map, so gas grows with the number of accounts; `limit` only bounds
+    // the response size and is capped at MAX_TOP_HOLDERS.
+    pub fn get_top_holders(&self, limit: u64) -> Vec<(AccountId, U128)> {
+        let mut holders: Vec<(AccountId, Balance)> = self.credits.iter().filter(|(_, credits)| *credits > 0).collect();
+        holders.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+
+        holders
+            .into_iter()
+            .take(limit.min(MAX_TOP_HOLDERS) as usize)
+            .map(|(account_id, credits)| (account_id, credits.into()))
+            .collect()
+    }
+
+    pub fn get_vip_tier(&self, account_id: AccountId) -> u8 {
+        let total_wagered = self.stats.get(&account_id).unwrap_or_default().total_wagered;
+        self.config.vip_tier(total_wagered)
+    }
+
+    // How much the account still has to deposit to afford one play of `stake` with its fee,
+    // after its credits and any deposit match it would get. The contract pays for its own
+    // storage, so a new account needs nothing extra for that.
+    pub fn required_deposit_for(&self, account_id: AccountId, stake: U128) -> U128 {
+        let stake: Balance = stake.into();
+        assert!(
+            stake >= self.config.min_bet.0 && stake <= self.config.max_bet.0,
+            "Stake must be between {} and {}", self.config.min_bet.0, self.config.max_bet.0
+        );
+        let credits = self.credits.get(&account_id).unwrap_or(0);
+        let shortfall = (stake + self.account_fee(&account_id, stake)).saturating_sub(credits);
+        if shortfall == 0 {
+            return 0.into();
+        }
+
+        // the smallest deposit that, bonus included, covers the shortfall
+        let match_bps = if self.is_bonus_eligible(&account_id) { self.active_deposit_match_bps() } else { 0 };
+        let mut deposit = pro_rata(shortfall, MAX_BPS as u128, (MAX_BPS + match_bps) as u128);
+        if deposit + self.deposit_bonus(&account_id, deposit) < shortfall {
+            deposit += 1;
+        }
+        deposit.into()
+    }
+
+    // Plays of `stake` the account's credits cover, each costing the stake (rounded to the
+    // tick as `play` does) plus its fee. Winnings and rebates along the way aren't counted.
+    pub fn plays_remaining(&self, account_id: AccountId, stake: U128) -> u64 {
+        let stake = self.config.round_to_tick(stake.into());
+        assert!(stake > 0, "Stake must be positive!");
+        let credits = self.credits.get(&account_id).unwrap_or(0);
+        (credits / (stake + self.account_fee(&account_id, stake))).min(u64::MAX as u128) as u64
+    }
+
+    // Win probability in basis points at which a play is worth exactly its stake plus fee:
+    //   p * payout + (1 - p) * rebate = 1 + fee  =>  p = (1 + fee - rebate) / (payout - rebate)
+    // rounded down, and capped at 100% when no odds would break even. Compare with
+    // `get_effective_odds(..) * 10000 / 256` for the house's edge.
+    pub fn breakeven_odds(&self) -> u32 {
+        let config = &self.config;
+        let cost = MAX_BPS as u32 + config.fee_bps as u32 - config.rebate_bps as u32;
+        if config.payout_bps <= config.rebate_bps as u32 {
+            return MAX_BPS as u32;
+        }
+        let gain = config.payout_bps - config.rebate_bps as u32;
+        (cost as u64 * MAX_BPS as u64 / gain as u64).min(MAX_BPS as u64) as u32
+    }
+
+    // Theoretical return to player in basis points of what a play costs, stake plus fee
+    pub fn get_rtp_bps(&self) -> u16 {
+        self.config.rtp_bps()
+    }
+
+    // What the house keeps on average, so that RTP plus edge is always MAX_BPS
+    pub fn get_house_edge_bps(&self) -> u16 {
+        MAX_BPS - self.config.rtp_bps()
+    }
+
+    // Smallest stake whose winning payout exceeds the stake plus fee; zero if no stake does
+    pub fn min_viable_stake(&self) -> U128 {
+        self.config.min_viable_stake().unwrap_or(0).into()
+    }
+
+    // Whether a roll byte wins under the current settings, using the same rule as `play`.
+    // With an account the odds include its VIP bonus and win-streak decay.
+    pub fn outcome_for_roll(&self, roll: u8, account_id: Option<AccountId>) -> bool {
+        let odds = match account_id {
+            Some(account_id) => self.effective_odds(&account_id),
+            None => self.config.boosted_odds(0).max(MIN_EFFECTIVE_ODDS),
+        };
+        self.config.wins(roll, odds)
+    }
+
+    // Count of every roll byte so far, indexed by the byte, for fairness audits
+    pub fn get_roll_distribution(&self) -> Vec<u64> {
+        (0..=u8::MAX).map(|roll| self.roll_histogram.get(&roll).unwrap_or(0)).collect()
+    }
+
+    // The base winning range, inclusive, before any per-account bonuses
+    pub fn get_win_range(&self) -> (u8, u8) {
+        (self.config.win_low, self.config.win_low.saturating_add(self.config.winning_rolls().max(MIN_EFFECTIVE_ODDS) - 1))
+    }
+
+    // Payout multiplier a win gets right now, after the soft cap near the bankroll floor
+    pub fn get_current_payout_bps(&self) -> u32 {
+        self.current_payout_bps()
+    }
+
+    // `fee_bps` after the account's reward points discount
+    pub fn get_effective_fee_bps(&self, account_id: AccountId) -> u16 {
+        self.config.fee_bps_for(self.reward_points.get(&account_id).unwrap_or(0))
+    }
+
+    pub fn get_player_exposure(&self, account_id: AccountId) -> U128 {
+        self.player_exposure(&account_id).into()
+    }
+
+    pub fn get_max_stake(&self, account_id: AccountId) -> U128 {
+        self.max_stake(&account_id).into()
+    }
+
+    // What the account can still win today before plays are rejected
+    pub fn get_remaining_daily_winnings(&self, account_id: AccountId) -> U128 {
+        self.remaining_daily_winnings(&account_id).into()
+    }
+
+    // Winnings that can still be paid out in the current block
+    pub fn get_block_payout_allowance(&self) -> U128 {
+        self.block_payout_allowance().into()
+    }
+
+    // Winning plays the current block still has room for
+    pub fn get_remaining_winner_slots(&self) -> u32 {
+        self.config.max_winners_per_block.saturating_sub(self.winners_this_block())
+    }
+
+    // Plays left under `max_lifetime_plays`, or u64::MAX when there's no limit
+    pub fn get_remaining_plays(&self, account_id: AccountId) -> u64 {
+        self.remaining_plays(&account_id)
+    }
+
+    // "What if" projection for proposed settings; it ignores the current config and state
+    // entirely, and rounds the payout and fee down
+    pub fn backtest(&self, stake: U128, plays: u32, odds: u8, payout_bps: u32, fee_bps: u16) -> BacktestResult {
+        assert!(plays > 0 && plays <= MAX_BACKTEST_PLAYS, "Plays must be between 1 and {}", MAX_BACKTEST_PLAYS);
+        let stake = stake.0;
+        let cost = stake + stake * fee_bps as u128 / MAX_BPS as u128;
+        let payout = stake * payout_bps as u128 / MAX_BPS as u128;
+        let (n, odds) = (plays as i128, odds as i128);
+
+        // per round the house keeps `cost` and pays `payout` with probability odds / 256
+        let expected_house_profit = n * (256 * cost as i128 - odds * payout as i128) / 256;
+        let (p, q) = (odds as f64 / 256.0, 1.0 - odds as f64 / 256.0);
+        let house_profit_std_dev = payout as f64 * (n as f64 * p * q).sqrt();
+
+        // the player finishes down when wins * payout < plays * cost. The binomial terms are
+        // accumulated in log space so long runs don't underflow.
+        let loss_probability = if p == 0.0 {
+            1.0
+        } else {
+            let mut log_pmf = n as f64 * q.ln();
+            let mut total = 0.0;
+            let mut wins = 0;
+            while wins <= n && (wins as u128) * payout < (n as u128) * cost {
+                total += log_pmf.exp();
+                log_pmf += ((n - wins) as f64 / (wins + 1) as f64).ln() + (p / q).ln();
+                wins += 1;
+            }
+            total.min(1.0)
+        };
+
+        BacktestResult {
+            expected_house_profit: expected_house_profit.into(),
+            house_profit_std_dev: (house_profit_std_dev as u128).into(),
+            player_loss_probability_bps: (loss_probability * MAX_BPS as f64).round() as u16,
+        }
+    }
+
+    pub fn get_gas_requirements(&self) -> GasRequirements {
+        GasRequirements {
+            withdraw: MIN_GAS_FOR_WITHDRAW,
+            process_payouts: MIN_GAS_FOR_PAYOUTS,
+            process_payouts_per_account: GAS_FOR_PAYOUT_CALLBACK,
+            play_with_auto_payout: MIN_GAS_FOR_AUTO_PAYOUT_PLAY,
+            payout_callback: GAS_FOR_PAYOUT_CALLBACK,
+            auto_payout_callback: GAS_FOR_AUTO_PAYOUT_CALLBACK,
+        }
+    }
+
+    // Nanoseconds since the contract was initialized
+    pub fn get_contract_age(&self) -> u64 {
+        env::block_timestamp() - self.created_at
+    }
+
+    // Sequence number of the last emitted event, for indexers to confirm they're caught up
+    pub fn get_event_seq(&self) -> u64 {
+        self.event_seq
+    }
+
+    // Winning rolls out of 256. Under exact odds this is the closest approximation; see
+    // `get_win_probability` for the precise figure.
+    pub fn get_effective_odds(&self, account_id: AccountId) -> u8 {
+        self.effective_odds(&account_id)
+    }
+
+    // The account's chance of winning as (numerator, denominator)
+    pub fn get_win_probability(&self, account_id: AccountId) -> (u32, u32) {
+        self.config.exact_odds().unwrap_or_else(|| (self.effective_odds(&account_id) as u32, 256))
+    }
+
+    // Consecutive losses since the account's last win or comeback spin
+    pub fn get_loss_streak(&self, account_id: AccountId) -> u32 {
+        self.stats.get(&account_id).map(|stats| stats.loss_streak).unwrap_or(0)
+    }
+
+    // Kelly-optimal stake out of the player's credits, at their effective odds. Per unit of
+    // stake a win nets `W = payout - 1 - fee` and a loss costs `L = 1 + fee - rebate`, so the
+    // optimal fraction is `(p*W - q*L) / (W*L)`. `assert_valid` never allows a game that
+    // favours the player, so under a valid config this is zero: the best bet is no bet.
+    // The result ignores min_bet and max_bet.
+    pub fn kelly_stake(&self, account_id: AccountId) -> U128 {
+        let credits = self.credits.get(&account_id).unwrap_or(0);
+        let odds = self.effective_odds(&account_id) as i128;
+        let max_bps = MAX_BPS as i128;
+        let win = self.config.payout_bps as i128 - max_bps - self.config.fee_bps as i128;
+        let loss = max_bps + self.config.fee_bps as i128 - self.config.rebate_bps as i128;
+        let edge = odds * win - (256 - odds) * loss;
+        if win <= 0 || edge <= 0 {
+            return 0.into();
+        }
+
+        // a loss that costs nothing makes every bet free money
+        let fraction_bps = if loss <= 0 { max_bps } else { (edge * max_bps * max_bps / (256 * win * loss)).min(max_bps) };
+        (credits * fraction_bps as u128 / MAX_BPS as u128).into()
+    }
+
+    pub fn get_net_position(&self, account_id: AccountId) -> NetPosition {
+        let total_deposited = self.stats.get(&account_id).unwrap_or_default().total_deposited;
+        let total_withdrawn = self.total_withdrawn.get(&account_id).unwrap_or(0);
+        let credits = self.credits.get(&account_id).unwrap_or(0);
+
+        NetPosition {
+            total_deposited: total_deposited.into(),
+            total_withdrawn: total_withdrawn.into(),
+            credits: credits.into(),
+            net: (total_withdrawn as i128 + credits as i128 - total_deposited as i128).into(),
+        }
+    }
+
+    pub fn get_all_balances(&self, account_id: AccountId) -> AllBalances {
+        let match_escrow: Balance = self
+            .matches
+            .values()
+            .filter(|game| game.creator == account_id)
+            .map(|game| game.stake.0)
+            .sum();
+
+        AllBalances {
+            credits: self.credits.get(&account_id).unwrap_or(0).into(),
+            vault: self.vaults.get(&account_id).map_or(0, |position| position.amount.0).into(),
+            match_escrow: match_escrow.into(),
+            pending_bet: self.pending_bets.get(&account_id).map_or(0, |bet| bet.stake.0 + bet.fee.0).into(),
+            claimable: self.claimable.get(&account_id).unwrap_or(0).into(),
+            bonus_credits: self.bonus_credits.get(&account_id).map_or(0, |bonus| bonus.amount.0).into(),
+            savings: self.savings.get(&account_id).unwrap_or(0).into(),
+            recurring_allowance: self.recurring_deposits.get(&account_id).map_or(0, |recurring| recurring.allowance.0).into(),
+            reward_points: self.reward_points.get(&account_id).unwrap_or(0).into(),
+            lp_shares: self.lp_shares.get(&account_id).unwrap_or(0).into(),
+            lp_value: self.get_lp_value(account_id),
+        }
+    }
+
+    pub fn get_pending_actions(&self, account_id: AccountId) -> PendingActions {
+        let open_matches = self
+            .matches
+            .iter()
+            .filter(|(_, game)| game.creator == account_id)
+            .map(|(match_id, _)| match_id)
+            .collect();
+
+        PendingActions {
+            claimable: self.claimable.get(&account_id).unwrap_or(0).into(),
+            claimable_since: self.claimable_since.get(&account_id),
+            vault: self.vaults.get(&account_id),
+            open_matches,
+        }
+    }
+
+    // Moves credits out of the playable balance into a time-locked vault position. Locked
+    // credits are no longer in `credits`, so they can't be bet in `play` until unstaked. The
+    // interest is reserved from the house bankroll up front, so unstaking never depends on
+    // how the bankroll has done since.
+    pub fn stake_vault(&mut self, amount: U128, duration_ns: u64) {
+        let account_id = env::signer_account_id();
+        let amount: Balance = amount.into();
+        self.assert_not_blacklisted(&account_id);
+
+        assert!(amount > 0, "Nothing to stake!");
+        assert!(duration_ns > 0, "Duration must be positive!");
+        assert!(self.vaults.get(&account_id).is_none(), "Vault position already open!");
+
+        let credits = self.credits.get(&account_id).unwrap_or(0);
+        assert!(credits >= amount, "Not enough credits to stake!");
+
+        let now = env::block_timestamp();
+        let position = VaultPosition {
+            amount: amount.into(),
+            apr_bps: self.vault_apr_bps,
+            staked_at: now,
+            unlock_at: now.checked_add(duration_ns).expect("Duration overflows the unlock time!"),
+        };
+        let interest = position.interest();
+        let surplus = self.free_balance().saturating_sub(self.liabilities());
+        assert!(interest <= surplus, "House can't cover {} of vault interest", interest);
+        self.remove_credits(&account_id, amount);
+        self.total_locked_credits += amount;
+        self.total_vault_interest += interest;
+        self.vaults.insert(&account_id, &position);
+
+        env::log(format!("'{}' staked {} in the vault until {}", &account_id, amount, position.unlock_at).as_bytes());
+
+        self.assert_solvent();
+    }
+
+    // Returns the locked credits plus the interest reserved for them
+    pub fn unstake(&mut self) -> U128 {
+        let account_id = env::signer_account_id();
+        let position = self.vaults.get(&account_id).expect("No vault position!");
+
+        assert!(env::block_timestamp() >= position.unlock_at, "Vault position is still locked!");
+
+        let interest = position.interest();
+        let payout = position.amount.0 + interest;
+        self.total_locked_credits -= position.amount.0;
+        self.total_vault_interest -= interest;
+        self.add_credits(&account_id, payout);
+        self.vaults.remove(&account_id);
+
+        env::log(format!("'{}' unstaked {} from the vault", &account_id, payout).as_bytes());
+
+        self.assert_solvent();
+        payout.into()
+    }
+
+    pub fn get_vault(&self, account_id: AccountId) -> Option<VaultPosition> {
+        self.vaults.get(&account_id)
+    }
+
+    // Escrows the creator's stake until someone joins or the creator cancels
+    pub fn create_match(&mut self, stake: U128) -> u64 {
+        let account_id = env::signer_account_id();
+        let stake: Balance = stake.into();
+        self.assert_not_blacklisted(&account_id);
+        self.assert_not_paused();
+        assert!(stake > 0, "Stake must be positive!");
+
+        let credits = self.credits.get(&account_id).unwrap_or(0);
+        assert!(credits >= stake, "Not enough credits to create a match!");
+        self.remove_credits(&account_id, stake);
+        self.total_locked_credits += stake;
+
+        let match_id = self.next_match_id;
+        self.next_match_id += 1;
+        self.matches.insert(&match_id, &Match {
+            creator: account_id.clone(),
+            stake: stake.into(),
+            created_at: env::block_timestamp(),
+        });
+
+        env::log(format!("'{}' created match {} for {}", &account_id, match_id, stake).as_bytes());
+
+        self.assert_solvent();
+        match_id
+    }
+
+    // Matches the creator's stake and flips: the creator wins on a roll below 128. The
+    // winner is credited the whole pot minus the configured fee.
+    pub fn join_match(&mut self, match_id: u64) -> AccountId {
+        let account_id = env::signer_account_id();
+        self.assert_not_blacklisted(&account_id);
+        self.assert_not_paused();
+        let game = self.matches.get(&match_id).expect("Match not found!");
+        assert_ne!(account_id, game.creator, "Can't join your own match!");
+
+        let stake = game.stake.0;
+        let credits = self.credits.get(&account_id).unwrap_or(0);
+        assert!(credits >= stake, "Not enough credits to join the match!");
+        self.remove_credits(&account_id, stake);
+
+        let roll = self.derive_roll(&account_id, self.nonce);
+        self.nonce += 1;
+        let winner = if roll < 128 { game.creator.clone() } else { account_id.clone() };
+
+        let pot = 2 * stake;
+        let fee = self.config.fee(pot);
+        self.accumulated_fees += fee;
+        self.total_locked_credits -= stake;
+        self.add_credits(&winner, pot - fee);
+        self.matches.remove(&match_id);
+
+        env::log(format!("Match {} between '{}' and '{}' won by '{}'", match_id, &game.creator, &account_id, &winner).as_bytes());
+
+        self.assert_solvent();
+        winner
+    }
+
+    pub fn cancel_match(&mut self, match_id: u64) {
+        let account_id = env::signer_account_id();
+        let game = self.matches.get(&match_id).expect("Match not found!");
+        assert_eq!(account_id, game.creator, "Only the creator can cancel a match!");
+
+        self.total_locked_credits -= game.stake.0;
+        self.add_credits(&account_id, game.stake.0);
+        self.matches.remove(&match_id);
+
+        env::log(format!("'{}' cancelled match {}", &account_id, match_id).as_bytes());
+
+        self.assert_solvent();
+    }
+
+    // Anyone can return the stake of a match nobody joined within `match_timeout_ns` to its
+    // creator, deleting the match. Joined matches are settled and gone already.
+    pub fn refund_match(&mut self, match_id: u64) -> U128 {
+        assert!(self.config.match_timeout_ns > 0, "Matches never time out!");
+        let game = self.matches.get(&match_id).expect("Match not found!");
+        let expires_at = game.created_at.saturating_add(self.config.match_timeout_ns);
+        assert!(env::block_timestamp() >= expires_at, "Match can't be refunded before {}", expires_at);
+
+        self.total_locked_credits -= game.stake.0;
+        self.add_credits(&game.creator, game.stake.0);
+        self.matches.remove(&match_id);
+        self.emit_event("match_refunded", json!({
+            "match_id": match_id,
+            "creator": game.creator,
+            "stake": game.stake,
+        }));
+
+        self.assert_solvent();
+        game.stake
+    }
+
+    pub fn get_match(&self, match_id: u64) -> Option<Match> {
+        self.matches.get(&match_id)
+    }
+
+    // A page of every account that ever held credits, in storage order. Together with
+    // `export_config` this is enough to rebuild the contract's state off-chain.
+    pub fn export_state(&self, from_index: u64, limit: u64) -> StateChunk {
+        assert!(limit > 0 && limit <= MAX_EXPORT_PAGE, "Page size must be between 1 and {}", MAX_EXPORT_PAGE);
+
+        let keys = self.credits.keys_as_vector();
+        let end = (from_index + limit).min(keys.len());
+        let accounts = (from_index..end)
+            .map(|index| {
+                let account_id = keys.get(index).unwrap();
+                let stats = self.stats.get(&account_id).unwrap_or_default();
+                AccountExport {
+                    credits: self.credits.get(&account_id).unwrap_or(0).into(),
+                    claimable: self.claimable.get(&account_id).unwrap_or(0).into(),
+                    claimable_since: self.claimable_since.get(&account_id),
+                    total_deposited: stats.total_deposited.into(),
+                    total_wagered: stats.total_wagered.into(),
+                    total_withdrawn: self.total_withdrawn.get(&account_id).unwrap_or(0).into(),
+                    total_plays: stats.total_plays,
+                    win_streak: stats.win_streak,
+                    loss_streak: stats.loss_streak,
+                    vault: self.vaults.get(&account_id),
+                    blacklisted: self.blacklist.get(&account_id).unwrap_or(false),
+                    reward_points: self.reward_points.get(&account_id).unwrap_or(0).into(),
+                    account_id,
+                }
+            })
+            .collect();
+
+        StateChunk { accounts, total_accounts: keys.len() }
+    }
+
+    pub fn export_config(&self) -> ConfigExport {
+        ConfigExport {
+            owner_id: self.owner_id.clone(),
+            config: self.config.clone(),
+            metadata: self.metadata.clone(),
+            network: self.network,
+            paused: self.paused,
+            vault_apr_bps: self.vault_apr_bps,
+            treasury_splits: self.treasury_splits.clone(),
+            accumulated_fees: self.accumulated_fees.into(),
+            total_player_credits: self.total_player_credits.into(),
+            total_locked_credits: self.total_locked_credits.into(),
+            total_claimable: self.total_claimable.into(),
+            total_wagered: self.total_wagered.into(),
+            nonce: self.nonce,
+            next_match_id: self.next_match_id,
+            event_seq: self.event_seq,
+            created_at: self.created_at,
+        }
+    }
+
+    pub fn get_owner_earnings(&self) -> OwnerEarnings {
+        OwnerEarnings {
+            accumulated_fees: self.accumulated_fees.into(),
+            house_pnl: self.house_pnl.into(),
+            fees_collected: self.fees_collected.into(),
+            available_to_withdraw: self.accumulated_fees.min(self.free_balance()).into(),
+            reinvested: self.reinvested_profit.into(),
+            withdrawable_surplus: self.withdrawable_surplus().into(),
+        }
+    }
+
+    pub fn get_solvency(&self) -> SolvencyReport {
+        let house_equity = self.free_balance() as i128 - self.liabilities() as i128;
+
+        SolvencyReport {
+            account_balance: env::account_balance().into(),
+            storage_cost: self.storage_cost().into(),
+            total_player_credits: self.total_player_credits.into(),
+            total_locked_credits: self.total_locked_credits.into(),
+            total_claimable: self.total_claimable.into(),
+            house_equity: house_equity.into(),
+            is_solvent: house_equity >= 0,
+        }
+    }
+
+    pub fn get_storage_usage(&self) -> StorageInfo {
+        StorageInfo {
+            storage_usage: env::storage_usage(),
+            storage_cost: self.storage_cost().into(),
+            credit_entries: self.credits.len(),
+            vault_entries: self.vaults.len(),
+            match_entries: self.matches.len(),
+            blacklist_entries: self.blacklist.len(),
+        }
+    }
+
+    pub fn get_credits_capacity(&self) -> CreditsCapacity {
+        CreditsCapacity {
+            accounts: self.credits.len(),
+            max_accounts: self.config.max_credit_accounts,
+            policy: self.config.credits_cap_policy,
+        }
+    }
+
+    pub fn contract_health(&self) -> ContractHealth {
+        let house_equity = self.free_balance() as i128 - self.liabilities() as i128;
+
+        ContractHealth {
+            player_count: self.credits.len(),
+            storage_usage: env::storage_usage(),
+            storage_cost: self.storage_cost().into(),
+            liabilities: self.liabilities().into(),
+            house_equity: house_equity.into(),
+            is_solvent: house_equity >= 0,
+            paused: self.paused,
+        }
+    }
+
+    // Runs the contract's invariants without changing state, so monitoring can alert on
+    // any check that comes back false. Credits are summed over at most `MAX_HEALTH_SAMPLE`
+    // entries: the sum has to match the total exactly when that covers the whole map, and
+    // can't exceed it otherwise.
+    pub fn health_assertions(&self) -> Vec<(String, bool)> {
+        let values = self.credits.values_as_vector();
+        let sampled = values.len().min(MAX_HEALTH_SAMPLE);
+        let sample_sum = (0..sampled).map(|index| values.get(index).unwrap()).sum::<Balance>();
+        let credits_consistent = if sampled == values.len() {
+            sample_sum == self.total_player_credits
+        } else {
+            sample_sum <= self.total_player_credits
+        };
+
+        vec![
+            ("solvency".to_string(), self.liabilities() <= self.free_balance()),
+            ("total_player_credits".to_string(), credits_consistent),
+            ("house_edge".to_string(), self.config.has_house_edge()),
+            (
+                "config_lock".to_string(),
+                self.pending_config
+                    .as_ref()
+                    .is_none_or(|pending| pending.effective_at >= self.config.config_locked_until),
+            ),
+        ]
+    }
+
+    pub fn get_config(&self) -> GameConfig {
+        self.config.clone()
+    }
+
+    pub fn get_limits(&self) -> Limits {
+        let config = &self.config;
+        Limits {
+            min_bet: config.min_bet,
+            max_bet: config.max_bet,
+            max_payout: config.rounding.apply_bps(config.max_bet.0, config.payout_bps as u128).into(),
+            max_stake_pct_bps: config.max_stake_pct_bps,
+            min_house_balance: config.min_house_balance,
+            max_block_payout: config.max_block_payout,
+            max_winners_per_block: config.max_winners_per_block,
+            daily_win_limit: config.daily_win_limit,
+            max_player_exposure_bps: config.max_player_exposure_bps,
+            cooldown_ns: config.cooldown_ns,
+            cooldown_per_near_ns: config.cooldown_per_near_ns,
+            max_lifetime_plays: config.max_lifetime_plays,
+            max_credit_accounts: config.max_credit_accounts,
+            config_change_min_interval_ns: config.config_change_min_interval_ns,
+        }
+    }
+
+    // Applies every provided field at once and validates the resulting set as a whole, so
+    // the game never passes through an inconsistent intermediate state.
+    pub fn set_config(&mut self, config: GameConfigUpdate) {
+        self.assert_owner();
+        self.assert_config_unlocked();
+        self.record_config_change();
+
+        let mut updated = self.config.clone();
+        updated.apply(config);
+        updated.assert_valid();
+        // the scheduled change has to stay valid on top of the new config
+        if let Some(pending) = &self.pending_config {
+            let mut scheduled = updated.clone();
+            scheduled.apply(pending.update.clone());
+            scheduled.assert_valid();
+        }
+        self.config = updated;
+        self.record_odds();
+
+        env::log(format!("Config updated: {:?}", &self.config).as_bytes());
+    }
+
+    // Announces a config update that plays pick up from `effective_at` on, replacing any
+    // change already scheduled. It's validated against the current config now, and again
+    // whenever `set_config` changes the config underneath it.
+    pub fn schedule_config_change(&mut self, config: GameConfigUpdate, effective_at: u64) {
+        self.assert_owner();
+        assert!(effective_at > env::block_timestamp(), "Change must take effect in the future!");
+        assert!(
+            effective_at >= self.config.config_locked_until,
+            "Config is locked until {}", self.config.config_locked_until
+        );
+        self.record_config_change();
+        let mut updated = self.config.clone();
+        updated.apply(config.clone());
+        updated.assert_valid();
+
+        env::log(format!("Config change scheduled for {}: {:?}", effective_at, &updated).as_bytes());
+        self.pending_config = Some(PendingConfigChange { update: config, effective_at });
+    }
+
+    pub fn get_pending_config_change(&self) -> Option<PendingConfigChange> {
+        self.pending_config.clone()
+    }
+
+    // Base odds that were live at `timestamp`, before any per-account bonus
+    pub fn odds_at(&self, timestamp: u64) -> u8 {
+        assert!(timestamp >= self.odds_since.0, "No odds recorded before {}", self.odds_since.0);
+        // the number of changes at or before `timestamp`
+        let (mut low, mut high) = (0, self.odds_timeline.len());
+        while low < high {
+            let mid = (low + high) / 2;
+            if self.odds_timeline.get(mid).unwrap().0 <= timestamp {
+                low = mid + 1;
+            } else {
+                high = mid;
+            }
+        }
+        match low {
+            0 => self.odds_since.1,
+            changes => self.odds_timeline.get(changes - 1).unwrap().1,
+        }
+    }
+
+    // Makes every roll in `[low, high]` win. The width of the range becomes the base odds
+    // (less the boundary roll if that's inclusive), so the house edge is validated exactly as
+    // for `odds`.
+    pub fn set_win_range(&mut self, low: u8, high: u8) {
+        assert!(low <= high, "Low end of the range can't be above the high end!");
+        assert!(high - low < u8::MAX, "Range must leave at least one losing roll!");
+        self.set_config(GameConfigUpdate {
+            odds: Some(high - low + 1 - self.config.win_boundary_inclusive as u8),
+            win_low: Some(low),
+            ..Default::default()
+        });
+    }
+
+    // Promises players the game parameters won't change before `until_timestamp`. A lock
+    // can be extended but never shortened.
+    pub fn lock_config(&mut self, until_timestamp: u64) {
+        self.assert_owner();
+        assert!(until_timestamp > env::block_timestamp(), "Lock must end in the future!");
+        assert!(
+            until_timestamp > self.config.config_locked_until,
+            "Config is already locked until {}", self.config.config_locked_until
+        );
+        assert!(
+            self.pending_config.as_ref().is_none_or(|pending| pending.effective_at >= until_timestamp),
+            "A config change is scheduled before the lock ends!"
+        );
+        self.config.config_locked_until = until_timestamp;
+
+        env::log(format!("Config locked until {}", until_timestamp).as_bytes());
+    }
+
+    // Credits players out of the house bankroll in proportion to their share of all wagering.
+    // One page of the stats map is paid per call; the first call for an `airdrop_id` fixes
+    // the amount and page size, and each page can only be paid once.
+    pub fn airdrop_by_activity(&mut self, airdrop_id: u64, total_amount: U128, from_index: u64, limit: u64) {
+        self.assert_owner();
+        assert!(limit > 0 && limit <= MAX_AIRDROP_PAGE, "Page size must be between 1 and {}", MAX_AIRDROP_PAGE);
+
+        let mut airdrop = self.airdrops.get(&airdrop_id).unwrap_or(Airdrop {
+            total_amount: total_amount.0,
+            total_wagered: self.total_wagered,
+            page_size: limit,
+            distributed: 0,
+            processed_pages: vec![],
+        });
+        assert!(airdrop.total_wagered > 0, "Nobody has wagered yet!");
+        assert_eq!(airdrop.total_amount, total_amount.0, "Amount doesn't match this airdrop!");
+        assert!(
+            limit == airdrop.page_size && from_index.is_multiple_of(limit),
+            "Pages must be aligned to this airdrop's page size of {}", airdrop.page_size
+        );
+        let page = from_index / limit;
+        assert!(!airdrop.processed_pages.contains(&page), "Page already processed!");
+
+        let end = (from_index + limit).min(self.stats.len());
+        let mut paid = 0;
+        for index in from_index..end {
+            let account_id = self.stats.keys_as_vector().get(index).unwrap();
+            let wagered = self.stats.values_as_vector().get(index).unwrap().total_wagered;
+            let share = pro_rata(airdrop.total_amount, wagered, airdrop.total_wagered)
+                .min(airdrop.total_amount - airdrop.distributed - paid);
+            if share > 0 {
+                self.add_credits(&account_id, share);
+                paid += share;
+            }
+        }
+        airdrop.distributed += paid;
+        airdrop.processed_pages.push(page);
+        self.airdrops.insert(&airdrop_id, &airdrop);
+
+        self.emit_event("airdrop", json!({
+            "airdrop_id": airdrop_id,
+            "page": page,
+            "amount": U128::from(paid),
+        }));
+
+        self.assert_solvent();
+    }
+
+    // Moves credits into the house bankroll for newly minted shares of it, priced at the
+    // bankroll's current value. Returns the shares minted.
+    pub fn provide_liquidity(&mut self, amount: U128) -> U128 {
+        let account_id = env::signer_account_id();
+        let amount: Balance = amount.into();
+        self.assert_not_blacklisted(&account_id);
+        assert!(self.config.allow_liquidity, "Liquidity provision is disabled!");
+        assert!(amount > 0, "Nothing to provide!");
+        let credits = self.credits.get(&account_id).unwrap_or(0);
+        assert!(credits >= amount, "Not enough credits to provide!");
+
+        let pool_value = self.pool_value();
+        if self.total_lp_shares == 0 && pool_value > 0 {
+            let owner_id = self.owner_id.clone();
+            self.mint_lp_shares(&owner_id, pool_value);
+        }
+        let shares = match self.total_lp_shares {
+            0 => amount,
+            total => {
+                assert!(pool_value > 0, "The bankroll is empty!");
+                pro_rata(amount, total, pool_value)
+            }
+        };
+        assert!(shares > 0, "Amount is too small to mint a share!");
+        self.remove_credits(&account_id, amount);
+        self.mint_lp_shares(&account_id, shares);
+
+        self.emit_player_event("liquidity_provided", json!({
+            "account_id": account_id,
+            "amount": U128::from(amount),
+            "shares": U128::from(shares),
+        }));
+        self.assert_solvent();
+        shares.into()
+    }
+
+    // Burns shares for their slice of the house bankroll, paid out as credits. House
+    // profits and losses since providing make that more or less than was put in.
+    pub fn remove_liquidity(&mut self, shares: U128) -> U128 {
+        let account_id = env::signer_account_id();
+        let shares: Balance = shares.into();
+        self.assert_not_blacklisted(&account_id);
+        let held = self.lp_shares.get(&account_id).unwrap_or(0);
+        assert!(shares > 0 && shares <= held, "Only {} shares can be redeemed", held);
+
+        let amount = pro_rata(self.pool_value(), shares, self.total_lp_shares);
+        if shares == held {
+            self.lp_shares.remove(&account_id);
+        } else {
+            self.lp_shares.insert(&account_id, &(held - shares));
+        }
+        self.total_lp_shares -= shares;
+        self.add_credits(&account_id, amount);
+
+        self.emit_player_event("liquidity_removed", json!({
+            "account_id": account_id,
+            "amount": U128::from(amount),
+            "shares": U128::from(shares),
+        }));
+        self.assert_solvent();
+        amount.into()
+    }
+
+    pub fn get_lp_shares(&self, account_id: AccountId) -> U128 {
+        self.lp_shares.get(&account_id).unwrap_or(0).into()
+    }
+
+    // Current value of the account's shares
+    pub fn get_lp_value(&self, account_id: AccountId) -> U128 {
+        match self.total_lp_shares {
+            0 => U128::from(0),
+            total => pro_rata(self.pool_value(), self.lp_shares.get(&account_id).unwrap_or(0), total).into(),
+        }
+    }
+
+    fn mint_lp_shares(&mut self, account_id: &AccountId, shares: Balance) {
+        let held = self.lp_shares.get(account_id).unwrap_or(0);
+        self.lp_shares.insert(account_id, &(held + shares));
+        self.total_lp_shares += shares;
+    }
+
+    // The house bankroll the shares are claims on: everything not owed to players or
+    // waiting to be collected as fees
+    fn pool_value(&self) -> Balance {
+        self.free_balance().saturating_sub(self.liabilities()).saturating_sub(self.accumulated_fees)
+    }
+
+    // Moves credits to one of the owner-approved tip destinations
+    pub fn tip(&mut self, recipient: AccountId, amount: U128) {
+        let account_id = env::signer_account_id();
+        let amount: Balance = amount.into();
+        self.assert_not_blacklisted(&account_id);
+        assert!(self.is_tip_destination(recipient.clone()), "Recipient doesn't accept tips!");
+        assert_ne!(account_id, recipient, "Can't tip yourself!");
+        assert!(amount > 0, "Nothing to tip!");
+        let credits = self.credits.get(&account_id).unwrap_or(0);
+        assert!(credits >= amount, "Not enough credits to tip!");
+
+        self.remove_credits(&account_id, amount);
+        self.add_credits(&recipient, amount);
+        let total = self.total_tips.get(&recipient).unwrap_or(0);
+        self.total_tips.insert(&recipient, &(total + amount));
+        self.emit_player_event("tip", json!({
+            "account_id": account_id,
+            "recipient": recipient,
+            "amount": U128::from(amount),
+        }));
+
+        self.assert_solvent();
+    }
+
+    pub fn get_total_tips(&self, recipient: AccountId) -> U128 {
+        self.total_tips.get(&recipient).unwrap_or(0).into()
+    }
+
+    // Pays back the cashback tier's share of the net losses in the current window from the
+    // house bankroll, and starts a new window
+    pub fn claim_cashback(&mut self) -> U128 {
+        let account_id = env::signer_account_id();
+        self.assert_not_blacklisted(&account_id);
+        let amount = self.cashback_available(&account_id);
+        assert!(amount > 0, "No cashback available!");
+
+        self.cashback.insert(&account_id, &CashbackWindow { started_at: env::block_timestamp(), net_loss: 0 });
+        self.add_credits(&account_id, amount);
+        self.emit_player_event("cashback", json!({
+            "account_id": account_id,
+            "amount": U128::from(amount),
+        }));
+
+        self.assert_solvent();
+        amount.into()
+    }
+
+    pub fn get_trials_remaining(&self, account_id: AccountId) -> u8 {
+        self.trials_remaining(&account_id)
+    }
+
+    pub fn get_bonus_credits(&self, account_id: AccountId) -> Option<BonusCredits> {
+        self.bonus_credits.get(&account_id)
+    }
+
+    pub fn get_reward_points(&self, account_id: AccountId) -> U128 {
+        self.reward_points.get(&account_id).unwrap_or(0).into()
+    }
+
+    pub fn get_cashback_available(&self, account_id: AccountId) -> U128 {
+        self.cashback_available(&account_id).into()
+    }
+
+    // Adds one page of the credits map to a recount of `total_player_credits`. Pages are
+    // aligned to the first call's page size and each is counted once. The game has to stay
+    // paused until `finalize_total_credits`, so no balance moves between pages.
+    pub fn recompute_total_credits(&mut self, from_index: u64, limit: u64) {
+        self.assert_owner();
+        assert!(self.paused, "Pause the game before recounting credits!");
+        assert!(limit > 0 && limit <= MAX_EXPORT_PAGE, "Page size must be between 1 and {}", MAX_EXPORT_PAGE);
+
+        let mut recount = self.credits_recount.clone().unwrap_or(CreditsRecount { page_size: limit, sum: 0, processed_pages: vec![] });
+        assert!(
+            limit == recount.page_size && from_index.is_multiple_of(limit),
+            "Pages must be aligned to this recount's page size of {}", recount.page_size
+        );
+        let page = from_index / limit;
+        assert!(!recount.processed_pages.contains(&page), "Page already counted!");
+
+        let values = self.credits.values_as_vector();
+        let end = (from_index + limit).min(values.len());
+        recount.sum += (from_index..end).map(|index| values.get(index).unwrap()).sum::<Balance>();
+        recount.processed_pages.push(page);
+        self.credits_recount = Some(recount);
+    }
+
+    // Replaces `total_player_credits` with the recounted sum once every page is counted
+    pub fn finalize_total_credits(&mut self) -> U128 {
+        self.assert_owner();
+        assert!(self.paused, "Pause the game before recounting credits!");
+        let recount = self.credits_recount.as_ref().expect("No recount in progress!");
+        let pages = self.credits.len().div_ceil(recount.page_size);
+        assert!(
+            (0..pages).all(|page| recount.processed_pages.contains(&page)),
+            "Recount has {} of {} pages", recount.processed_pages.len(), pages
+        );
+        let recount = self.credits_recount.take().unwrap();
+
+        let previous = self.total_player_credits;
+        self.total_player_credits = recount.sum;
+        self.emit_event("credits_reconciled", json!({
+            "previous": U128::from(previous),
+            "total": U128::from(recount.sum),
+        }));
+        recount.sum.into()
+    }
+
+    // Returns winnings that have been claimable for longer than `stuck_reclaim_timeout`,
+    // e.g. after a payout to a deleted account kept failing, to the house bankroll
+    pub fn reclaim_stuck(&mut self, account_id: AccountId) -> U128 {
+        self.assert_owner();
+        assert!(self.claimable.get(&account_id).unwrap_or(0) > 0, "Nothing claimable for this account!");
+        let since = self.claimable_since.get(&account_id).expect("No claim time recorded!");
+        let reclaimable_at = since.saturating_add(self.config.stuck_reclaim_timeout);
+        assert!(env::block_timestamp() >= reclaimable_at, "Entry can't be reclaimed before {}", reclaimable_at);
+
+        let amount = self.remove_claimable(&account_id);
+        self.emit_event("stuck_reclaimed", json!({
+            "account_id": account_id,
+            "amount": U128::from(amount),
+            "claimable_since": since,
+        }));
+
+        self.assert_solvent();
+        amount.into()
+    }
+
+    // Anyone can sweep the credits of an account that has been inactive for
+    // `credit_expiry_ns` into the house bankroll. Accounts without recorded activity are
+    // never expired.
+    pub fn expire_credits(&mut self, account_id: AccountId) -> U128 {
+        assert!(self.config.credit_expiry_ns > 0, "Credits never expire!");
+        let amount = self.credits.get(&account_id).unwrap_or(0);
+        assert!(amount > 0, "No credits to expire!");
+        let last_activity = self.last_activity.get(&account_id).expect("No activity recorded for this account!");
+        let expires_at = last_activity.saturating_add(self.config.credit_expiry_ns);
+        assert!(env::block_timestamp() >= expires_at, "Credits don't expire before {}", expires_at);
+
+        self.remove_credits(&account_id, amount);
+        self.emit_event("credits_expired", json!({
+            "account_id": account_id,
+            "amount": U128::from(amount),
+            "last_activity": last_activity,
+        }));
+
+        self.assert_solvent();
+        amount.into()
+    }
+
+    // Starts (or replaces) a promotion matching deposits by `match_bps` until the timestamp
+    pub fn set_deposit_match(&mut self, match_bps: u16, until_timestamp: u64) {
+        self.assert_owner();
+        self.assert_config_unlocked();
+        self.record_config_change();
+        assert!(match_bps <= MAX_BPS, "Deposit match can't exceed 100%!");
+        assert!(until_timestamp > env::block_timestamp(), "Promotion must end in the future!");
+        self.config.deposit_match_bps = match_bps;
+        self.config.deposit_match_until = until_timestamp;
+
+        env::log(format!("Deposits matched at {} bps until {}", match_bps, until_timestamp).as_bytes());
+    }
+
+    // The match rate deposits get right now; zero once the promotion has ended
+    pub fn get_deposit_match_bps(&self) -> u16 {
+        self.active_deposit_match_bps()
+    }
+
+    pub fn get_network(&self) -> NetworkMode {
+        self.network
+    }
+
+    // Makes the next play roll `roll`, so frontends can exercise wins and losses on testnet
+    pub fn set_forced_roll(&mut self, roll: u8) {
+        self.assert_owner();
+        self.assert_testnet();
+        self.forced_roll = Some(roll);
+    }
+
+    // Testnet only: reverts the caller's most recent play within `undo_window_ns` of it, once.
+    // The stake and fee come back and any winnings, refunds and points are taken away, and the
+    // play stops counting towards streaks, block caps, window stats and the next milestone.
+    // The roll itself still counts towards the nonce and roll histogram.
+    pub fn undo_last_play(&mut self) {
+        self.assert_testnet();
+        assert!(self.config.undo_window_ns > 0, "Undo is disabled!");
+        let account_id = env::signer_account_id();
+        let mut detail = self.last_rolls.get(&account_id).expect("No play to undo!");
+        assert!(detail.undoable, "This play can't be undone!");
+        assert!(
+            env::block_timestamp() <= detail.played_at.saturating_add(self.config.undo_window_ns),
+            "Undo window has passed!"
+        );
+        let (stake, fee, payout, refund) = (detail.stake.0, detail.fee.0, detail.payout.0, detail.refund.0);
+        let credits = self.credits.get(&account_id).unwrap_or(0);
+        assert!(credits + stake + fee >= payout + refund, "Winnings already spent, can't undo!");
+        assert!(self.accumulated_fees >= fee, "Fees already collected, can't undo!");
+
+        self.add_credits(&account_id, stake + fee);
+        self.remove_credits(&account_id, payout + refund);
+        self.accumulated_fees -= fee;
+        self.total_wagered -= stake;
+        self.house_pnl -= stake as i128 - (payout + refund) as i128;
+        // only what's still in the pool can come back
+        let donation = detail.donation.0.min(self.charity_pool);
+        self.charity_pool -= donation;
+        self.house_pnl += donation as i128;
+        let points = self.reward_points.get(&account_id).unwrap_or(0);
+        self.reward_points.insert(&account_id, &points.saturating_sub(detail.points.0));
+        let mut stats = self.stats.get(&account_id).unwrap_or_default();
+        stats.total_wagered -= stake;
+        stats.total_plays -= 1;
+        (stats.win_streak, stats.loss_streak) = detail.streaks_before;
+        self.stats.insert(&account_id, &stats);
+        self.unrecord_play(&detail);
+        let mut window = self.cashback_window(&account_id);
+        window.net_loss -= (stake + fee) as i128 - (payout + refund) as i128;
+        self.cashback.insert(&account_id, &window);
+        if !detail.won {
+            self.reinvested_profit = self.reinvested_profit.saturating_sub(self.reinvested_share(stake.saturating_sub(refund)));
+        }
+        if detail.won && self.config.daily_win_limit.0 > 0 {
+            let (day_started_at, won) = self.current_daily_wins(&account_id);
+            self.daily_wins.insert(&account_id, &(day_started_at, won.saturating_sub(payout.saturating_sub(stake))));
+        }
+        let mut history = self.history.get(&account_id).unwrap_or_default();
+        history.pop();
+        self.history.insert(&account_id, &history);
+
+        detail.undoable = false;
+        self.last_rolls.insert(&account_id, &detail);
+        self.emit_player_event("undo_play", json!({
+            "account_id": account_id,
+            "nonce": detail.nonce,
+            "stake": detail.stake,
+            "payout": detail.payout,
+        }));
+    }
+
+    // Takes an undone play back out of the block caps, hourly window and milestone count
+    fn unrecord_play(&mut self, detail: &LastRollDetail) {
+        let (stake, payout) = (detail.stake.0, detail.payout.0);
+        if detail.won && self.block_payout.0 == detail.block_index {
+            self.block_payout.1 = self.block_payout.1.saturating_sub(payout);
+        }
+        if detail.won && self.block_winners.0 == detail.block_index {
+            self.block_winners.1 = self.block_winners.1.saturating_sub(1);
+        }
+        let hour = detail.played_at / NS_PER_HOUR;
+        let slot = hour % WINDOW_BUCKETS;
+        if let Some((bucket_hour, mut stats)) = self.window_buckets.get(&slot) {
+            if bucket_hour == hour {
+                stats.plays = stats.plays.saturating_sub(1);
+                if payout > 0 {
+                    stats.wins = stats.wins.saturating_sub(1);
+                }
+                stats.wagered = stats.wagered.0.saturating_sub(stake).into();
+                stats.paid = stats.paid.0.saturating_sub(payout).into();
+                self.window_buckets.insert(&slot, &(hour, stats));
+            }
+        }
+        if self.config.milestone_interval > 0 {
+            self.plays_since_milestone = self.plays_since_milestone.saturating_sub(1);
+        }
+    }
+
+    // Moves house surplus to a successor contract. Only allowed while paused, so no play can
+    // change the numbers mid-migration, and never reaches into funds owed to players.
+    #[payable]
+    pub fn migrate_bankroll(&mut self, new_contract: AccountId, amount: U128) -> Promise {
+        assert_one_yocto();
+        self.assert_owner();
+        self.assert_no_signers();
+        self.migrate_bankroll_internal(new_contract, amount)
+    }
+
+    // Replaces the signer set directly. Once signers are set, changing them takes a
+    // `SetSigners` proposal instead.
+    #[payable]
+    pub fn set_signers(&mut self, signers: Vec<AccountId>, required_approvals: u8) {
+        assert_one_yocto();
+        self.assert_owner();
+        self.assert_no_signers();
+        self.set_signers_internal(signers, required_approvals);
+    }
+
+    pub fn get_signers(&self) -> Vec<AccountId> {
+        self.signers.clone()
+    }
+
+    pub fn get_required_approvals(&self) -> u8 {
+        self.required_approvals
+    }
+
+    // Records a proposal, counting the proposer's approval, and returns its id. Runs the
+    // action straight away if that's already enough approvals.
+    #[payable]
+    pub fn propose_action(&mut self, action: OwnerAction) -> u64 {
+        assert_one_yocto();
+        assert!(self.required_approvals > 0, "No signers are set!");
+        self.assert_signer();
+        Self::assert_valid_action(&action);
+
+        let id = self.next_proposal_id;
+        self.next_proposal_id += 1;
+        let proposal = Proposal {
+            action,
+            proposer: env::predecessor_account_id(),
+            approvals: vec![env::predecessor_account_id()],
+            created_at: env::block_timestamp(),
+        };
+        self.emit_event("action_proposed", json!({
+            "proposal_id": id,
+            "proposer": proposal.proposer,
+            "action": proposal.action,
+        }));
+        self.proposals.insert(&id, &proposal);
+        self.execute_if_approved(id);
+        id
+    }
+
+    // Adds the caller's approval and returns whether that got the proposal executed
+    #[payable]
+    pub fn approve_action(&mut self, proposal_id: u64) -> bool {
+        assert_one_yocto();
+        self.assert_signer();
+        let mut proposal = self.proposals.get(&proposal_id).expect("Proposal not found!");
+        let signer = env::predecessor_account_id();
+        assert!(!proposal.approvals.contains(&signer), "Already approved!");
+        proposal.approvals.push(signer.clone());
+        self.proposals.insert(&proposal_id, &proposal);
+        self.emit_event("action_approved", json!({
+            "proposal_id": proposal_id,
+            "signer": signer,
+        }));
+        self.execute_if_approved(proposal_id)
+    }
+
+    pub fn get_proposal(&self, proposal_id: u64) -> Option<Proposal> {
+        self.proposals.get(&proposal_id)
+    }
+
+    fn migrate_bankroll_internal(&mut self, new_contract: AccountId, amount: U128) -> Promise {
+        assert!(self.paused, "Pause the game before migrating the bankroll!");
+        assert!(
+            self.total_lp_shares == self.lp_shares.get(&self.owner_id).unwrap_or(0),
+            "Liquidity providers still hold shares of the bankroll!"
+        );
+        assert!(env::is_valid_account_id(new_contract.as_bytes()), "Invalid contract account!");
+        let amount: Balance = amount.into();
+        let surplus = self.withdrawable_surplus();
+        assert!(amount > 0 && amount <= surplus, "Only {} of house surplus can be migrated", surplus);
+
+        self.emit_event("bankroll_migrated", json!({
+            "new_contract": new_contract,
+            "amount": U128::from(amount),
+        }));
+
+        Promise::new(new_contract).transfer(amount)
+    }
+
+    pub fn blacklist_account(&mut self, account_id: AccountId) {
+        self.assert_owner();
+        self.blacklist.insert(&account_id, &true);
+        env::log(format!("Blacklisted '{}'", &account_id).as_bytes());
+    }
+
+    pub fn unblacklist_account(&mut self, account_id: AccountId) {
+        self.assert_owner();
+        self.blacklist.remove(&account_id);
+        env::log(format!("Removed '{}' from the blacklist", &account_id).as_bytes());
+    }
+
+    pub fn add_tip_destination(&mut self, account_id: AccountId) {
+        self.assert_owner();
+        assert!(env::is_valid_account_id(account_id.as_bytes()), "Invalid account!");
+        self.tip_destinations.insert(&account_id, &true);
+        env::log(format!("'{}' can now receive tips", &account_id).as_bytes());
+    }
+
+    pub fn remove_tip_destination(&mut self, account_id: AccountId) {
+        self.assert_owner();
+        self.tip_destinations.remove(&account_id);
+        env::log(format!("'{}' can no longer receive tips", &account_id).as_bytes());
+    }
+
+    pub fn is_tip_destination(&self, account_id: AccountId) -> bool {
+        self.tip_destinations.get(&account_id).unwrap_or(false)
+    }
+
+    pub fn is_blacklisted(&self, account_id: AccountId) -> bool {
+        self.blacklist.get(&account_id).unwrap_or(false)
+    }
+
+    // Escape hatch for dispute resolution: sets a player's balance directly. Requires one
+    // yoctoNEAR so it can only be called with a full access key, and records the reason
+    // alongside the old and new values in an event.
+    #[payable]
+    pub fn adjust_credits(&mut self, account_id: AccountId, new_balance: U128, reason: String) {
+        assert_one_yocto();
+        self.assert_owner();
+        self.assert_no_signers();
+        self.adjust_credits_internal(account_id, new_balance, reason);
+    }
+
+    fn adjust_credits_internal(&mut self, account_id: AccountId, new_balance: U128, reason: String) {
+        assert!(env::is_valid_account_id(account_id.as_bytes()), "Invalid account!");
+        assert!(!reason.trim().is_empty(), "A reason is required!");
+        assert!(reason.len() <= MAX_REASON_LENGTH, "Reason is too long!");
+
+        let old_balance = self.credits.get(&account_id).unwrap_or(0);
+        let new_balance: Balance = new_balance.into();
+        if new_balance > old_balance {
+            self.add_credits(&account_id, new_balance - old_balance);
+        } else {
+            self.remove_credits(&account_id, old_balance - new_balance);
+        }
+
+        self.emit_event("admin_adjustment", json!({
+            "owner_id": env::predecessor_account_id(),
+            "account_id": account_id,
+            "old_balance": U128::from(old_balance),
+            "new_balance": U128::from(new_balance),
+            "reason": reason,
+        }));
+
+        self.assert_solvent();
+    }
+
+    pub fn get_metadata(&self) -> GameMetadata {
+        self.metadata.clone()
+    }
+
+    pub fn set_name(&mut self, name: String) {
+        self.assert_owner();
+        let metadata = GameMetadata { name, ..self.metadata.clone() };
+        metadata.assert_valid();
+        self.metadata = metadata;
+    }
+
+    pub fn set_description(&mut self, description: String) {
+        self.assert_owner();
+        let metadata = GameMetadata { description, ..self.metadata.clone() };
+        metadata.assert_valid();
+        self.metadata = metadata;
+    }
+
+    pub fn set_paused(&mut self, paused: bool) {
+        self.assert_owner();
+        self.paused = paused;
+        env::log(format!("Paused: {}", paused).as_bytes());
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    pub fn set_vault_apr_bps(&mut self, vault_apr_bps: u16) {
+        self.assert_owner();
+        self.assert_config_unlocked();
+        self.record_config_change();
+        assert!(vault_apr_bps <= MAX_BPS, "APR can't exceed 100%!");
+        self.vault_apr_bps = vault_apr_bps;
+    }
+
+    pub fn set_treasury_splits(&mut self, splits: Vec<(AccountId, u16)>) {
+        self.assert_owner();
+        assert!(!splits.is_empty(), "At least one beneficiary is required!");
+        for (account_id, _) in &splits {
+            assert!(env::is_valid_account_id(account_id.as_bytes()), "Invalid beneficiary account!");
+        }
+        let total: u32 = splits.iter().map(|(_, bps)| *bps as u32).sum();
+        assert!(total == MAX_BPS as u32, "Treasury splits must sum to 10000 basis points!");
+
+        self.treasury_splits = splits;
+        env::log(format!("Treasury splits updated: {:?}", &self.treasury_splits).as_bytes());
+    }
+
+    pub fn get_treasury_splits(&self) -> Vec<(AccountId, u16)> {
+        self.treasury_splits.clone()
+    }
+
+    // Pays out all accumulated fees according to the treasury splits. Shares are rounded
+    // down and the leftover yoctoNEAR goes to the first beneficiary.
+    pub fn collect_fees(&mut self) {
+        self.assert_owner();
+        let amount = self.accumulated_fees;
+        assert!(amount > 0, "No fees to collect!");
+        assert!(amount <= self.free_balance(), "Temporarily insufficient contract liquidity");
+        self.accumulated_fees = 0;
+        self.fees_collected += amount;
+
+        let mut shares: Vec<(AccountId, Balance)> = self.treasury_splits
+            .iter()
+            .map(|(account_id, bps)| (account_id.clone(), amount * *bps as u128 / MAX_BPS as u128))
+            .collect();
+        let distributed: Balance = shares.iter().map(|(_, share)| share).sum();
+        shares[0].1 += amount - distributed;
+
+        self.emit_event("fees_collected", json!({
+            "amount": U128::from(amount),
+            "shares": shares.iter().map(|(account_id, share)| json!({
+                "account_id": account_id,
+                "amount": U128::from(*share),
+            })).collect::<Vec<_>>(),
+        }));
+
+        for (account_id, share) in shares {
+            if share > 0 {
+                Promise::new(account_id).transfer(share);
+            }
+        }
+    }
+}
+
+impl SlotMachine {
+    fn deposit_internal(&mut self, account_id: &AccountId, amount: Balance) {
+        self.admit_account(account_id);
+        let bonus = self.deposit_bonus(account_id, amount);
+        // a deposit match only ever goes to credits
+        let saved = amount * self.savings_split_bps.get(account_id).unwrap_or(0) as u128 / MAX_BPS as u128;
+        if saved > 0 {
+            let savings = self.savings.get(account_id).unwrap_or(0);
+            self.savings.insert(account_id, &(savings + saved));
+            self.total_savings += saved;
+        }
+        self.add_credits(account_id, amount - saved + bonus);
+
+        let mut stats = self.stats.get(account_id).unwrap_or_default();
+        stats.total_deposited += amount;
+        self.stats.insert(account_id, &stats);
+
+        let mut history = self.deposit_history.get(account_id).unwrap_or_default();
+        if history.len() == MAX_DEPOSIT_HISTORY {
+            history.remove(0);
+        }
+        history.push(DepositRecord { amount: amount.into(), bonus: bonus.into(), timestamp: env::block_timestamp() });
+        self.deposit_history.insert(account_id, &history);
+
+        self.emit_player_event("deposit", json!({
+            "account_id": account_id,
+            "amount": U128::from(amount),
+            "bonus": U128::from(bonus),
+            "saved": U128::from(saved),
+        }));
+    }
+
+    // Every way of crediting an account from outside goes through this, so new accounts
+    // respect `max_credit_accounts` and have their activity recorded
+    fn admit_account(&mut self, account_id: &AccountId) {
+        if self.credits.get(account_id).is_none() {
+            self.make_room_for_account();
+        }
+        self.record_activity(account_id);
+    }
+
+    // Only the first MAX_EVICTION_SCAN entries are looked at, so eviction costs bounded gas;
+    // accounts without recorded activity count as the least recently active, and accounts
+    // with funds held anywhere but their credits are never picked. An evicted account's dust
+    // is sent back to it and its per-account records are cleared, except what keeps it from
+    // earning its trial spins again or shaking off the blacklist.
+    fn make_room_for_account(&mut self) {
+        if self.credits.len() < self.config.max_credit_accounts {
+            return;
+        }
+        assert!(
+            self.config.credits_cap_policy == CreditsCapPolicy::EvictDust,
+            "Credits are full at {} accounts!", self.config.max_credit_accounts
+        );
+
+        let keys = self.credits.keys_as_vector();
+        let (account_id, amount, last_activity) = (0..keys.len().min(MAX_EVICTION_SCAN))
+            .map(|index| keys.get(index).unwrap())
+            .map(|account_id| {
+                let amount = self.credits.get(&account_id).unwrap_or(0);
+                let last_activity = self.last_activity.get(&account_id).unwrap_or(0);
+                (account_id, amount, last_activity)
+            })
+            .filter(|(account_id, amount, _)| {
+                *amount <= self.config.eviction_dust_threshold.0 && !self.holds_other_funds(account_id)
+            })
+            .min_by_key(|(_, _, last_activity)| *last_activity)
+            .expect("Credits are full and no dust account can be evicted!");
+
+        self.remove_credits(&account_id, amount);
+        self.credits.remove(&account_id);
+        self.clear_account_records(&account_id);
+        if amount > 0 {
+            Promise::new(account_id.clone()).transfer(amount);
+        }
+        self.emit_event("account_evicted", json!({
+            "account_id": account_id,
+            "amount": U128::from(amount),
+            "last_activity": last_activity,
+        }));
+    }
+
+    fn holds_other_funds(&self, account_id: &AccountId) -> bool {
+        self.vaults.get(account_id).is_some()
+            || self.claimable.get(account_id).unwrap_or(0) > 0
+            || self.savings.get(account_id).unwrap_or(0) > 0
+            || self.bonus_credits.get(account_id).is_some()
+            || self.pending_bets.get(account_id).is_some()
+            || self.recurring_deposits.get(account_id).is_some()
+            || self.lp_shares.get(account_id).unwrap_or(0) > 0
+    }
+
+    fn clear_account_records(&mut self, account_id: &AccountId) {
+        self.stats.remove(account_id);
+        self.total_withdrawn.remove(account_id);
+        self.play_proofs.remove(account_id);
+        self.claimable.remove(account_id);
+        self.claimable_since.remove(account_id);
+        self.history.remove(account_id);
+        self.deposit_history.remove(account_id);
+        self.cashback.remove(account_id);
+        self.auto_payout.remove(account_id);
+        self.reward_points.remove(account_id);
+        self.daily_wins.remove(account_id);
+        self.last_rolls.remove(account_id);
+        self.last_activity.remove(account_id);
+        self.last_consolation.remove(account_id);
+        self.tip_destinations.remove(account_id);
+        self.total_tips.remove(account_id);
+        self.play_keys.remove(account_id);
+        self.savings_split_bps.remove(account_id);
+        self.savings.remove(account_id);
+        self.fairness_seeds.remove(account_id);
+        self.rapid_plays.remove(account_id);
+        self.lp_shares.remove(account_id);
+    }
+
+    // Like `play_rejection`, but also covers what the public play methods check before it
+    fn full_play_rejection(&self, account_id: &AccountId, stake: Balance) -> Option<String> {
+        if self.blacklist.get(account_id).unwrap_or(false) {
+            Some(String::from("Account is blacklisted!"))
+        } else if self.paused {
+            Some(String::from("Game is paused!"))
+        } else if self.seed_is_degenerate() {
+            Some(String::from("Random seed looks degenerate!"))
+        } else if let Some(reason) = self.cooldown_rejection(account_id) {
+            Some(reason)
+        } else {
+            self.play_rejection(account_id, stake)
+        }
+    }
+
+    // Why a play of `stake` can't go ahead, checked without changing any state
+    fn play_rejection(&self, account_id: &AccountId, stake: Balance) -> Option<String> {
+        let stake = self.config.round_to_tick(stake);
+        let tick = self.config.tick_size.0;
+        if tick > 0 && !stake.is_multiple_of(tick) {
+            let nearest = (stake + tick / 2) / tick * tick;
+            return Some(format!("Stake must be a multiple of {}; the nearest valid stake is {}", tick, nearest));
+        }
+        if stake < self.config.min_bet.0 || stake > self.config.max_bet.0 {
+            return Some(format!("Stake must be between {} and {}", self.config.min_bet.0, self.config.max_bet.0));
+        }
+        if !self.config.is_viable_stake(stake) {
+            return Some(String::from("Stake too small to produce a winning payout"));
+        }
+        if let Some(reason) = self.pacing_rejection(account_id) {
+            return Some(reason);
+        }
+        let fee = self.account_fee(account_id, stake);
+        let credits = self.credits.get(account_id).unwrap_or(0);
+        if credits < stake + fee {
+            return Some(format!("Insufficient credits: have {}, need {}", credits, stake + fee));
+        }
+        if stake > self.max_stake(account_id) {
+            return Some(format!("Stake can't exceed {} of your credits", self.max_stake(account_id)));
+        }
+        self.winnings_rejection(account_id, stake)
+    }
+
+    // The parts of `play_rejection` about how often the account plays, which trial spins
+    // are held to as well
+    fn pacing_rejection(&self, account_id: &AccountId) -> Option<String> {
+        if self.remaining_plays(account_id) == 0 {
+            return Some(String::from("Lifetime play limit reached"));
+        }
+        if let Some(until) = self.enforced_break(account_id) {
+            return Some(format!("Take a break: play resumes at {}", until));
+        }
+        None
+    }
+
+    // The parts of `play_rejection` about what a win would pay, which trial spins are held
+    // to as well
+    fn winnings_rejection(&self, account_id: &AccountId, stake: Balance) -> Option<String> {
+        if self.current_payout(stake).saturating_sub(stake) > self.remaining_daily_winnings(account_id) {
+            return Some(String::from("Daily win limit reached"));
+        }
+        if self.config.max_player_exposure_bps > 0 {
+            let house_balance = self.free_balance().saturating_sub(self.liabilities());
+            let cap = pro_rata(house_balance, self.config.max_player_exposure_bps as u128, MAX_BPS as u128);
+            if self.player_exposure(account_id) + self.current_payout(stake) > cap {
+                return Some(String::from("Player exposure cap reached"));
+            }
+        }
+        self.house_rejection(stake)
+    }
+
+    // The largest stake the account can play right now, counting `max_stake_pct_bps`
+    fn max_stake(&self, account_id: &AccountId) -> Balance {
+        let max_bet = self.config.max_bet.0;
+        if self.config.max_stake_pct_bps == 0 {
+            return max_bet;
+        }
+        let credits = self.credits.get(account_id).unwrap_or(0);
+        max_bet.min(pro_rata(credits, self.config.max_stake_pct_bps as u128, MAX_BPS as u128))
+    }
+
+    // The fee on `stake` after the account's reward points discount
+    fn account_fee(&self, account_id: &AccountId, stake: Balance) -> Balance {
+        let points = self.reward_points.get(account_id).unwrap_or(0);
+        self.config.rounding.apply_bps(stake, self.config.fee_bps_for(points) as u128)
+    }
+
+    // Winnings the house owes the account but hasn't paid out yet. Plays settle as they roll,
+    // so these are the only outstanding ones.
+    fn player_exposure(&self, account_id: &AccountId) -> Balance {
+        self.claimable.get(account_id).unwrap_or(0) + self.bonus_credits.get(account_id).map_or(0, |bonus| bonus.amount.0)
+    }
+
+    // The part of `play_rejection` that is down to the house rather than the player
+    fn house_rejection(&self, stake: Balance) -> Option<String> {
+        // the house's worst case is paying the full payout for a stake and fee it already holds
+        let house_balance = self.free_balance() as i128 - self.liabilities() as i128;
+        let max_loss = self.current_payout(stake).saturating_sub(stake + self.config.min_fee(stake));
+        if house_balance - (max_loss as i128) < self.config.min_house_balance.0 as i128 {
+            return Some(String::from("House bankroll too low"));
+        }
+        // checked before rolling, so a play is either allowed to win in full or not made
+        if self.current_payout(stake) > self.block_payout_allowance() {
+            return Some(String::from("Block payout cap reached, try again in the next block"));
+        }
+        if self.winners_this_block() >= self.config.max_winners_per_block {
+            return Some(String::from("Block winner cap reached, try again in the next block"));
+        }
+        None
+    }
+
+    // Whether `reason` turned the account away through no fault of its own. An owner pause
+    // doesn't count: it can last indefinitely, and every retry would be paid for.
+    fn is_house_rejection(&self, account_id: &AccountId, stake: Balance, reason: &str) -> bool {
+        if self.paused || self.blacklist.get(account_id).unwrap_or(false) {
+            return false;
+        }
+        self.seed_is_degenerate()
+            || self.house_rejection(self.config.round_to_tick(stake)).as_deref() == Some(reason)
+    }
+
+    // Only accounts that have deposited at least a minimum bet and actually played qualify,
+    // so fresh or dust-funded accounts can't farm it, and only while the house can spare it
+    fn pay_consolation(&mut self, account_id: &AccountId) {
+        let amount = self.config.consolation_amount.0;
+        let stats = self.stats.get(account_id).unwrap_or_default();
+        if amount == 0 || stats.total_plays == 0 || stats.total_deposited < self.config.min_bet.0 {
+            return;
+        }
+        let now = env::block_timestamp();
+        if let Some(last) = self.last_consolation.get(account_id) {
+            if now < last.saturating_add(self.config.consolation_interval_ns) {
+                return;
+            }
+        }
+        if self.free_balance().saturating_sub(self.liabilities()) < amount {
+            return;
+        }
+
+        self.last_consolation.insert(account_id, &now);
+        self.add_credits(account_id, amount);
+        self.emit_player_event("consolation", json!({
+            "account_id": account_id,
+            "amount": U128::from(amount),
+        }));
+    }
+
+    // Factor `payout_bps` is scaled by, in basis points around MAX_BPS: the share of the
+    // window's stakes paid out beyond what the odds predict (or short of it), clamped to
+    // `max_payout_adjust_bps` and taken off (or added to) MAX_BPS
+    fn payout_adjustment_bps(&self) -> u32 {
+        if self.config.dynamic_payout_window_ns == 0 {
+            return MAX_BPS as u32;
+        }
+        let window = self.get_window_stats(self.config.dynamic_payout_window_ns);
+        if window.wagered.0 == 0 {
+            return MAX_BPS as u32;
+        }
+        let paid_bps = pro_rata(window.paid.0, MAX_BPS as u128, window.wagered.0) as i128;
+        let max_adjust = self.config.max_payout_adjust_bps as i128;
+        let excess = (paid_bps - self.config.expected_paid_bps() as i128).clamp(-max_adjust, max_adjust);
+        (MAX_BPS as i128 - excess) as u32
+    }
+
+    // The payout multiplier after the dynamic adjustment and soft cap. Within `soft_cap_margin` of the floor, the
+    // winnings on top of the stake shrink linearly with the house's headroom:
+    //   payout_bps' = 10000 + (payout_bps - 10000) * headroom / soft_cap_margin
+    // so a win pays the full multiplier at the top of the band and only the stake back at
+    // the floor. Only the house's edge grows, so the validated config stays safe.
+    fn current_payout_bps(&self) -> u32 {
+        let payout_bps = (self.config.payout_bps as u64 * self.payout_adjustment_bps() as u64 / MAX_BPS as u64)
+            .max(MAX_BPS as u64) as u32;
+        let margin = self.config.soft_cap_margin.0;
+        if margin == 0 {
+            return payout_bps;
+        }
+        let house_balance = self.free_balance() as i128 - self.liabilities() as i128;
+        let headroom = (house_balance - self.config.min_house_balance.0 as i128).clamp(0, margin as i128) as u128;
+        let bonus_bps = (payout_bps - MAX_BPS as u32) as u128;
+        MAX_BPS as u32 + pro_rata(bonus_bps, headroom, margin) as u32
+    }
+
+    fn current_payout(&self, stake: Balance) -> Balance {
+        self.config.rounding.apply_bps(stake, self.current_payout_bps() as u128)
+    }
+
+    // Escrows the stake and fee for a later block's `resolve`. The returned roll is only a
+    // placeholder, since nothing has been rolled yet.
+    fn place_pending_bet(&mut self, account_id: &AccountId, stake: Balance) -> PlayResult {
+        assert!(self.pending_bets.get(account_id).is_none(), "Resolve your pending bet first!");
+        let stake = self.config.round_to_tick(stake);
+        if let Some(reason) = self.play_rejection(account_id, stake) {
+            panic!("{}", reason);
+        }
+        let fee = self.account_fee(account_id, stake);
+        self.remove_credits(account_id, stake + fee);
+        self.total_locked_credits += stake + fee;
+        let bet = PendingBet { stake: stake.into(), fee: fee.into(), block_index: env::block_index(), commitment: None };
+        self.pending_bets.insert(account_id, &bet);
+
+        self.emit_player_event("bet_placed", json!({
+            "account_id": account_id,
+            "stake": bet.stake,
+            "block_index": bet.block_index,
+        }));
+        self.unplayed_result(account_id)
+    }
+
+    // Storage is charged for the whole resolution, so the pending bet's freed entry offsets
+    // what the play adds
+    fn resolve_internal(&mut self, account_id: &AccountId, reveal: Option<BetReveal>) -> PlayResult {
+        let storage_before = env::storage_usage();
+        let result = self.settle_pending_bet(account_id, reveal);
+        self.settle_play_storage(account_id, storage_before);
+        result
+    }
+
+    fn settle_pending_bet(&mut self, account_id: &AccountId, reveal: Option<BetReveal>) -> PlayResult {
+        let bet = self.pending_bets.get(account_id).expect("No pending bet!");
+        assert!(env::block_index() > bet.block_index, "A bet can't be resolved in the block it was placed in!");
+        let (escrow, fee) = (bet.stake.0, bet.fee.0);
+        let stake = match &bet.commitment {
+            Some(commitment) => {
+                let reveal = reveal.expect("Hidden bets resolve with their reveal!");
+                assert!(
+                    bet_commitment(account_id, reveal.stake.0, &reveal.salt.0) == commitment.0,
+                    "Reveal doesn't match the committed bet!"
+                );
+                let stake = self.config.round_to_tick(reveal.stake.0);
+                assert!(stake + self.account_fee(account_id, stake) <= escrow, "Revealed bet exceeds the escrow!");
+                stake
+            }
+            None => escrow,
+        };
+        self.pending_bets.remove(account_id);
+        self.total_locked_credits -= escrow + fee;
+        self.add_credits(account_id, escrow + fee);
+
+        if let Some(reason) = self.full_play_rejection(account_id, stake) {
+            self.emit_player_event("bet_refunded", json!({
+                "account_id": account_id,
+                "stake": bet.stake,
+                "reason": reason,
+            }));
+            return self.unplayed_result(account_id);
+        }
+        self.play_internal(account_id, stake, false)
+    }
+
+    // Returned instead of playing when a degenerate seed pauses the game
+    fn unplayed_result(&self, account_id: &AccountId) -> PlayResult {
+        PlayResult {
+            roll: u8::MAX,
+            won: false,
+            stake: 0.into(),
+            payout: 0.into(),
+            credits: self.credits.get(account_id).unwrap_or(0).into(),
+            insurance_refund: 0.into(),
+        }
+    }
+
+    // Shared by every way of playing; callers check the blacklist and solvency, and take
+    // the premium for an `insured` play
+    fn play_internal(&mut self, account_id: &AccountId, stake: Balance, insured: bool) -> PlayResult {
+        let result = self.play_round(account_id, stake, insured);
+        self.emit_player_event("play", json!({
+            "account_id": account_id,
+            "roll": result.roll,
+            "won": result.won,
+            "stake": result.stake,
+            "payout": result.payout,
+            "insurance_refund": result.insurance_refund,
+        }));
+        result
+    }
+
+    // `play_internal` without the play event, for callers that log their rounds together
+    fn play_round(&mut self, account_id: &AccountId, stake: Balance, insured: bool) -> PlayResult {
+        let stake = self.config.round_to_tick(stake);
+        if let Some(reason) = self.play_rejection(account_id, stake) {
+            panic!("{}", reason);
+        }
+        // checked before rolling, so running out of gas can't depend on the outcome
+        if self.get_auto_payout(account_id.clone()) {
+            assert_prepaid_gas(MIN_GAS_FOR_AUTO_PAYOUT_PLAY);
+        }
+        self.record_activity(account_id);
+        let fee = self.account_fee(account_id, stake);
+        let payout_if_won = self.current_payout(stake);
+
+        let odds = self.effective_odds(account_id);
+        self.remove_credits(account_id, stake + fee);
+        self.accumulated_fees += fee;
+        self.wager_bonus(account_id, stake);
+        let forced_roll = self.forced_roll.take();
+        let random_number = forced_roll.unwrap_or_else(|| self.derive_roll(account_id, self.nonce));
+        // a forced roll is scaled onto the denominator, so 0 still wins and 255 loses
+        let exact_roll = self.config.exact_odds().map(|(_, denominator)| match forced_roll {
+            Some(roll) => (roll as u64 * denominator as u64 / 256) as u32,
+            None => self.derive_exact_roll(account_id, self.nonce, denominator),
+        });
+        let seeds = self.advance_seed_nonce(account_id);
+        self.play_proofs.insert(account_id, &PlayProof {
+            random_seed: env::random_seed().into(),
+            entropy_pool: self.entropy_pool.clone().into(),
+            account_id: account_id.clone(),
+            nonce: self.nonce,
+            block_index: env::block_index(),
+            roll: random_number,
+            client_seed: seeds.as_ref().map(|(client_seed, _)| client_seed.clone()),
+            seed_nonce: seeds.map(|(_, nonce)| nonce),
+        });
+        self.nonce += 1;
+        let count = self.roll_histogram.get(&random_number).unwrap_or(0);
+        self.roll_histogram.insert(&random_number, &(count + 1));
+        // roll the pool forward so each contribution is mixed in once and then diluted
+        if !self.entropy_pool.is_empty() {
+            let mut input = self.entropy_pool.clone();
+            input.extend_from_slice(&env::random_seed());
+            self.entropy_pool = env::sha256(&input);
+        }
+
+        let mut stats = self.stats.get(account_id).unwrap_or_default();
+        let streaks_before = (stats.win_streak, stats.loss_streak);
+        stats.total_wagered += stake;
+        stats.total_plays += 1;
+        stats.last_played_at = env::block_timestamp();
+        stats.last_stake = stake;
+        self.total_wagered += stake;
+        let mut payout = 0;
+        let mut rebate = 0;
+        let mut insurance_refund = 0;
+        let won = match exact_roll {
+            Some(exact_roll) => exact_roll < self.config.odds_numerator,
+            None => self.config.wins(random_number, odds),
+        };
+        let mut undoable = true;
+        if won {
+            payout = payout_if_won;
+            self.record_block_win(payout);
+            if self.config.daily_win_limit.0 > 0 {
+                let (day_started_at, won) = self.current_daily_wins(account_id);
+                self.daily_wins.insert(account_id, &(day_started_at, won + payout.saturating_sub(stake)));
+            }
+            if self.config.pull_payouts {
+                self.add_claimable(account_id, payout);
+                undoable = false;
+            } else if self.get_auto_payout(account_id.clone()) {
+                self.send_auto_payout(account_id, payout);
+                undoable = false;
+            } else {
+                self.add_credits(account_id, payout);
+            }
+            stats.win_streak += 1;
+            stats.loss_streak = 0;
+        } else {
+            rebate = self.config.rebate(stake);
+            if rebate > 0 {
+                self.add_credits(account_id, rebate);
+            }
+            if insured {
+                insurance_refund = self.config.rounding.apply_bps(stake, self.config.insurance_refund_bps as u128);
+                self.add_credits(account_id, insurance_refund);
+            }
+            // rounding up can refund a yocto or two past the stake
+            self.reinvested_profit += self.reinvested_share(stake.saturating_sub(rebate + insurance_refund));
+            stats.win_streak = 0;
+            // a comeback spin is used up whether or not it wins
+            stats.loss_streak = if self.config.is_comeback(stats.loss_streak) { 0 } else { stats.loss_streak + 1 };
+        }
+        self.stats.insert(account_id, &stats);
+        self.record_rapid_play(account_id);
+        self.record_window_play(stake, payout);
+        if self.count_milestone_play(account_id) {
+            undoable = false;
+        }
+        let mut window = self.cashback_window(account_id);
+        window.net_loss += (stake + fee) as i128 - (payout + rebate + insurance_refund) as i128;
+        self.house_pnl += stake as i128 - (payout + rebate + insurance_refund) as i128;
+        // the donation comes out of the house's result, never the player's stake
+        let donation = stake * self.config.charity_bps as u128 / MAX_BPS as u128;
+        self.charity_pool += donation;
+        self.house_pnl -= donation as i128;
+        let mut points = 0;
+        if won || !self.config.reward_points_wins_only {
+            points = stake * self.config.reward_points_bps as u128 / MAX_BPS as u128;
+            if points > 0 {
+                let total = self.reward_points.get(account_id).unwrap_or(0);
+                self.reward_points.insert(account_id, &(total + points));
+            }
+        }
+        self.cashback.insert(account_id, &window);
+        self.last_rolls.insert(account_id, &LastRollDetail {
+            seed_hash: env::sha256(&env::random_seed()).into(),
+            nonce: self.nonce - 1,
+            win_low: self.config.win_low,
+            threshold: odds,
+            roll: random_number,
+            exact_roll,
+            won,
+            played_at: env::block_timestamp(),
+            stake: stake.into(),
+            fee: fee.into(),
+            payout: payout.into(),
+            refund: (rebate + insurance_refund).into(),
+            points: points.into(),
+            donation: donation.into(),
+            undoable,
+            block_index: env::block_index(),
+            streaks_before,
+        });
+
+        let result = PlayResult {
+            roll: random_number,
+            won,
+            stake: stake.into(),
+            payout: payout.into(),
+            credits: self.credits.get(account_id).unwrap_or(0).into(),
+            insurance_refund: insurance_refund.into(),
+        };
+        let mut history = self.history.get(account_id).unwrap_or_default();
+        if history.len() == MAX_HISTORY {
+            history.remove(0);
+        }
+        history.push(result.clone());
+        self.history.insert(account_id, &history);
+        result
+    }
+
+    fn send_auto_payout(&mut self, account_id: &AccountId, amount: Balance) {
+        self.emit_player_event("auto_payout", json!({
+            "account_id": account_id,
+            "amount": U128::from(amount),
+        }));
+
+        Promise::new(account_id.clone()).transfer(amount).then(ext_self::on_auto_payout_transfer(
+            account_id.clone(),
+            amount.into(),
+            &env::current_account_id(),
+            0,
+            GAS_FOR_AUTO_PAYOUT_CALLBACK,
+        ));
+    }
+
+    // Like credits, `claimable` only changes through these so `total_claimable` and
+    // `claimable_since` stay in sync
+    fn add_claimable(&mut self, account_id: &AccountId, amount: Balance) {
+        let claimable = self.claimable.get(account_id).unwrap_or(0);
+        if claimable == 0 {
+            self.claimable_since.insert(account_id, &env::block_timestamp());
+        }
+        self.claimable.insert(account_id, &(claimable + amount));
+        self.total_claimable += amount;
+    }
+
+    fn remove_claimable(&mut self, account_id: &AccountId) -> Balance {
+        let amount = self.claimable.remove(account_id).unwrap_or(0);
+        self.claimable_since.remove(account_id);
+        self.total_claimable -= amount;
+        amount
+    }
+
+    // All changes to `credits` go through these two so `total_player_credits` stays in sync
+    fn add_credits(&mut self, account_id: &AccountId, amount: Balance) {
+        let credits = self.credits.get(account_id).unwrap_or(0);
+        self.credits.insert(account_id, &(credits + amount));
+        self.total_player_credits += amount;
+    }
+
+    fn remove_credits(&mut self, account_id: &AccountId, amount: Balance) {
+        let credits = self.credits.get(account_id).unwrap_or(0);
+        self.credits.insert(account_id, &(credits - amount));
+        self.total_player_credits -= amount;
+    }
+
+    // The account's cashback window, or a fresh one once the stored one is over
+    fn cashback_window(&self, account_id: &AccountId) -> CashbackWindow {
+        let now = env::block_timestamp();
+        match self.cashback.get(account_id) {
+            Some(window) if now < window.started_at.saturating_add(CASHBACK_WINDOW) => window,
+            _ => CashbackWindow { started_at: now, net_loss: 0 },
+        }
+    }
+
+    fn cashback_available(&self, account_id: &AccountId) -> Balance {
+        let net_loss = self.cashback_window(account_id).net_loss.max(0) as Balance;
+        net_loss * self.config.cashback_bps(net_loss) as u128 / MAX_BPS as u128
+    }
+
+    fn record_first_seen(&mut self, account_id: &AccountId) {
+        if self.first_seen.get(account_id).is_none() {
+            self.first_seen.insert(account_id, &env::block_timestamp());
+            if self.config.trial_spins > 0 {
+                self.trials_remaining.insert(account_id, &self.config.trial_spins);
+            }
+        }
+    }
+
+    fn keyed_play(&self, account_id: &AccountId, key: &str) -> Option<PlayResult> {
+        self.play_keys
+            .get(account_id)
+            .and_then(|keys| keys.into_iter().find(|(seen, _)| seen == key))
+            .map(|(_, result)| result)
+    }
+
+    fn remember_play_key(&mut self, account_id: &AccountId, key: String, result: &PlayResult) {
+        let mut keys = self.play_keys.get(account_id).unwrap_or_default();
+        if keys.len() == MAX_IDEMPOTENCY_KEYS {
+            keys.remove(0);
+        }
+        keys.push((key, result.clone()));
+        self.play_keys.insert(account_id, &keys);
+    }
+
+    // Accounts not seen yet get their trials on first interaction
+    fn trials_remaining(&self, account_id: &AccountId) -> u8 {
+        if self.first_seen.get(account_id).is_none() {
+            return self.config.trial_spins;
+        }
+        self.trials_remaining.get(account_id).unwrap_or(0)
+    }
+
+    // A free spin at `min_bet`: a loss costs nothing and a win's payout becomes bonus credits
+    fn trial_play(&mut self, account_id: &AccountId) -> PlayResult {
+        let stake = self.config.min_bet.0;
+        if let Some(reason) = self.pacing_rejection(account_id).or_else(|| self.winnings_rejection(account_id, stake)) {
+            panic!("{}", reason);
+        }
+        self.record_activity(account_id);
+        let trials = self.trials_remaining(account_id) - 1;
+        if trials == 0 {
+            self.trials_remaining.remove(account_id);
+        } else {
+            self.trials_remaining.insert(account_id, &trials);
+        }
+
+        let random_number = self.forced_roll.take().unwrap_or_else(|| self.derive_roll(account_id, self.nonce));
+        self.advance_seed_nonce(account_id);
+        self.nonce += 1;
+        let won = self.config.wins(random_number, self.effective_odds(account_id));
+        let payout = if won { self.current_payout(stake) } else { 0 };
+        if won {
+            self.record_block_win(payout);
+            let mut bonus = self.bonus_credits.get(account_id).unwrap_or(BonusCredits { amount: 0.into(), wagering_remaining: 0.into() });
+            bonus.amount = (bonus.amount.0 + payout).into();
+            bonus.wagering_remaining = (bonus.wagering_remaining.0 + payout * self.config.bonus_wagering_multiplier as u128).into();
+            self.bonus_credits.insert(account_id, &bonus);
+            self.total_bonus_credits += payout;
+        }
+        self.house_pnl -= payout as i128;
+
+        self.emit_player_event("trial_play", json!({
+            "account_id": account_id,
+            "roll": random_number,
+            "won": won,
+            "bonus": U128::from(payout),
+            "trials_remaining": trials,
+        }));
+        PlayResult {
+            roll: random_number,
+            won,
+            stake: 0.into(),
+            payout: payout.into(),
+            credits: self.credits.get(account_id).unwrap_or(0).into(),
+            insurance_refund: 0.into(),
+        }
+    }
+
+    // Counts a real stake towards unlocking the account's bonus credits
+    fn wager_bonus(&mut self, account_id: &AccountId, stake: Balance) {
+        let mut bonus = match self.bonus_credits.get(account_id) {
+            Some(bonus) => bonus,
+            None => return,
+        };
+        if stake < bonus.wagering_remaining.0 {
+            bonus.wagering_remaining = (bonus.wagering_remaining.0 - stake).into();
+            self.bonus_credits.insert(account_id, &bonus);
+            return;
+        }
+        self.bonus_credits.remove(account_id);
+        self.total_bonus_credits -= bonus.amount.0;
+        self.add_credits(account_id, bonus.amount.0);
+        self.emit_player_event("bonus_unlocked", json!({
+            "account_id": account_id,
+            "amount": bonus.amount,
+        }));
+    }
+
+    fn record_activity(&mut self, account_id: &AccountId) {
+        self.record_first_seen(account_id);
+        self.last_activity.insert(account_id, &env::block_timestamp());
+    }
+
+    fn is_bonus_eligible(&self, account_id: &AccountId) -> bool {
+        let first_seen = self.first_seen.get(account_id).unwrap_or_else(env::block_timestamp);
+        env::block_timestamp() - first_seen >= self.config.min_account_age_ns
+    }
+
+    fn deposit_bonus(&self, account_id: &AccountId, amount: Balance) -> Balance {
+        if self.is_bonus_eligible(account_id) {
+            amount * self.active_deposit_match_bps() as u128 / MAX_BPS as u128
+        } else {
+            0
+        }
+    }
+
+    fn active_deposit_match_bps(&self) -> u16 {
+        if env::block_timestamp() < self.config.deposit_match_until { self.config.deposit_match_bps } else { 0 }
+    }
+
+    fn remaining_plays(&self, account_id: &AccountId) -> u64 {
+        if self.config.max_lifetime_plays == 0 {
+            return u64::MAX;
+        }
+        let total_plays = self.stats.get(account_id).unwrap_or_default().total_plays;
+        self.config.max_lifetime_plays.saturating_sub(total_plays)
+    }
+
+    // The tracked total resets as soon as the block index moves on
+    fn paid_out_this_block(&self) -> Balance {
+        let (block_index, amount) = self.block_payout;
+        if block_index == env::block_index() { amount } else { 0 }
+    }
+
+    fn block_payout_allowance(&self) -> Balance {
+        self.config.max_block_payout.0.saturating_sub(self.paid_out_this_block())
+    }
+
+    fn winners_this_block(&self) -> u32 {
+        let (block_index, winners) = self.block_winners;
+        if block_index == env::block_index() { winners } else { 0 }
+    }
+
+    fn record_block_win(&mut self, payout: Balance) {
+        self.block_payout = (env::block_index(), self.paid_out_this_block() + payout);
+        self.block_winners = (env::block_index(), self.winners_this_block() + 1);
+    }
+
+    // The account's current day, starting a new one once the stored one is over
+    fn current_daily_wins(&self, account_id: &AccountId) -> (u64, Balance) {
+        let now = env::block_timestamp();
+        match self.daily_wins.get(account_id) {
+            Some((started_at, won)) if now < started_at.saturating_add(NS_PER_DAY) => (started_at, won),
+            _ => (now, 0),
+        }
+    }
+
+    fn remaining_daily_winnings(&self, account_id: &AccountId) -> Balance {
+        if self.config.daily_win_limit.0 == 0 {
+            return u128::MAX;
+        }
+        self.config.daily_win_limit.0.saturating_sub(self.current_daily_wins(account_id).1)
+    }
+
+    // The play that completes an interval triggers the milestone and starts the count over,
+    // whether or not the pool could pay for it. Returns whether this play completed one.
+    fn count_milestone_play(&mut self, account_id: &AccountId) -> bool {
+        if self.config.milestone_interval == 0 {
+            return false;
+        }
+        self.plays_since_milestone += 1;
+        if self.plays_since_milestone < self.config.milestone_interval {
+            return false;
+        }
+        self.plays_since_milestone = 0;
+        let bonus = self.config.milestone_bonus.0;
+        if bonus == 0 || self.milestone_pool < bonus {
+            return true;
+        }
+        self.milestone_pool -= bonus;
+        self.add_credits(account_id, bonus);
+        self.emit_player_event("milestone", json!({
+            "account_id": account_id,
+            "bonus": U128::from(bonus),
+        }));
+        true
+    }
+
+    // Adds a play to the current hour's bucket, clearing whatever older hour the slot held
+    fn record_window_play(&mut self, stake: Balance, payout: Balance) {
+        let hour = env::block_timestamp() / NS_PER_HOUR;
+        let slot = hour % WINDOW_BUCKETS;
+        let mut stats = match self.window_buckets.get(&slot) {
+            Some((bucket_hour, stats)) if bucket_hour == hour => stats,
+            _ => WindowStats { plays: 0, wins: 0, wagered: 0.into(), paid: 0.into() },
+        };
+        stats.plays += 1;
+        if payout > 0 {
+            stats.wins += 1;
+        }
+        stats.wagered = (stats.wagered.0 + stake).into();
+        stats.paid = (stats.paid.0 + payout).into();
+        self.window_buckets.insert(&slot, &(hour, stats));
+    }
+
+    fn reinvested_share(&self, house_take: Balance) -> Balance {
+        pro_rata(house_take, self.config.reinvest_bps as u128, MAX_BPS as u128)
+    }
+
+    // House equity the owner can still take out, after uncollected fees and what's been reinvested
+    fn withdrawable_surplus(&self) -> Balance {
+        self.pool_value().saturating_sub(self.reinvested_profit)
+    }
+
+    fn storage_cost(&self) -> Balance {
+        env::storage_usage() as u128 * env::storage_byte_cost()
+    }
+
+    // Takes the cost of the storage a play added since `storage_before` out of the player's
+    // credits, or gives back the cost of what it freed
+    fn settle_play_storage(&mut self, account_id: &AccountId, storage_before: u64) {
+        if !self.config.charge_play_storage {
+            return;
+        }
+        let storage_after = env::storage_usage();
+        if storage_after > storage_before {
+            let cost = (storage_after - storage_before) as u128 * env::storage_byte_cost();
+            let credits = self.credits.get(account_id).unwrap_or(0);
+            assert!(credits >= cost, "Insufficient credits for {} of storage", cost);
+            self.remove_credits(account_id, cost);
+        } else if storage_after < storage_before {
+            self.add_credits(account_id, (storage_before - storage_after) as u128 * env::storage_byte_cost());
+        }
+    }
+
+    // Everything owed to players, whether playable, locked, saved, promised as vault interest,
+    // waiting to be claimed or still to be unlocked by wagering, plus donations not yet sent to the charity
+    fn liabilities(&self) -> Balance {
+        self.total_player_credits + self.total_locked_credits + self.total_claimable + self.total_bonus_credits + self.total_savings
+            + self.total_recurring_allowance + self.milestone_pool + self.charity_pool + self.total_vault_interest
+    }
+
+    // Called at the end of every method that moves credits, so an accounting bug reverts
+    // the transaction instead of leaving the contract owing more than it holds. Compiled
+    // out when the default `solvency-check` feature is disabled.
+    fn assert_solvent(&self) {
+        if cfg!(feature = "solvency-check") {
+            assert!(self.liabilities() <= self.free_balance(), "Contract would become insolvent!");
+        }
+    }
+
+    // Contract balance minus what's locked to pay for storage
+    fn free_balance(&self) -> Balance {
+        env::account_balance().saturating_sub(self.storage_cost())
+    }
+
+    // The roll is a pure function of (random_seed, entropy_pool, account played, nonce): the
+    // first byte of sha256(random_seed || entropy_pool || account_id || nonce as little-endian
+    // u64), followed by server_seed || client_seed || seed nonce once the account has set a
+    // client seed. A keeper or delegate playing for an account rolls with that account's id
+    // and seeds, exactly as recorded in its `PlayProof`.
+    fn derive_roll(&self, account_id: &AccountId, nonce: u64) -> u8 {
+        self.roll_hash(account_id, nonce)[0]
+    }
+
+    // Exact-odds roll: the first four bytes of the same hash as a little-endian u32, modulo
+    // the denominator
+    fn derive_exact_roll(&self, account_id: &AccountId, nonce: u64, denominator: u32) -> u32 {
+        let hash = self.roll_hash(account_id, nonce);
+        u32::from_le_bytes([hash[0], hash[1], hash[2], hash[3]]) % denominator
+    }
+
+    fn roll_hash(&self, account_id: &AccountId, nonce: u64) -> Vec<u8> {
+        let mut input = env::random_seed();
+        input.extend_from_slice(&self.entropy_pool);
+        input.extend_from_slice(account_id.as_bytes());
+        input.extend_from_slice(&nonce.to_le_bytes());
+        if let Some(seeds) = self.account_seeds(account_id) {
+            input.extend_from_slice(&seeds.server_seed);
+            input.extend_from_slice(seeds.client_seed.as_bytes());
+            input.extend_from_slice(&seeds.nonce.to_le_bytes());
+        }
+        env::sha256(&input)
+    }
+
+    // Moves the account's seed nonce on after a roll, returning the client seed and nonce the
+    // roll used
+    fn advance_seed_nonce(&mut self, account_id: &AccountId) -> Option<(String, u64)> {
+        let mut seeds = self.account_seeds(account_id)?;
+        let used = (seeds.client_seed.clone(), seeds.nonce);
+        seeds.nonce += 1;
+        self.fairness_seeds.insert(account_id, &seeds);
+        Some(used)
+    }
+
+    fn account_seeds(&self, account_id: &AccountId) -> Option<FairnessSeeds> {
+        if self.seeded_accounts == 0 {
+            return None;
+        }
+        self.fairness_seeds.get(account_id)
+    }
+
+    // Base odds plus the account's VIP bonus (and the comeback bonus after a long enough
+    // loss streak), reduced by `odds_decay_per_win` for every win in the current streak
+    fn effective_odds(&self, account_id: &AccountId) -> u8 {
+        if let Some((numerator, denominator)) = self.config.exact_odds() {
+            return GameConfig::approximate_exact_odds(numerator, denominator);
+        }
+        let stats = self.stats.get(account_id).unwrap_or_default();
+        let mut bonus = self.config.vip_bonus(stats.total_wagered);
+        if self.config.is_comeback(stats.loss_streak) {
+            bonus = bonus.saturating_add(self.config.comeback_bonus);
+        }
+        let odds = self.config.boosted_odds(bonus);
+        let decay = (self.config.odds_decay_per_win as u32).saturating_mul(stats.win_streak);
+        let odds = (odds as u32).saturating_sub(decay);
+        odds.max(MIN_EFFECTIVE_ODDS as u32) as u8
+    }
+
+    // For events about a player's own activity, which `emit_events` can turn off. Owner
+    // actions are always logged.
+    fn emit_player_event(&mut self, event: &str, data: serde_json::Value) {
+        if self.config.emit_events {
+            self.emit_event(event, data);
+        }
+    }
+
+    // Logs a NEP-297 style event. `event_seq` increases by one per event, so an indexer
+    // that sees it jump knows it missed some and needs to backfill.
+    fn emit_event(&mut self, event: &str, data: serde_json::Value) {
+        self.event_seq += 1;
+        let payload = json!({
+            "standard": EVENT_STANDARD,
+            "version": EVENT_VERSION,
+            "event": event,
+            "event_seq": self.event_seq,
+            "data": data,
+        });
+        env::log(format!("EVENT_JSON:{}", payload).as_bytes());
+    }
+
+    // An all-zero (or otherwise constant) seed means the randomness source is broken, and
+    // rolls derived from it can't be trusted. Returns true if the game was paused, which
+    // has to happen without panicking so the pause isn't rolled back.
+    fn pause_on_degenerate_seed(&mut self) -> bool {
+        if !self.seed_is_degenerate() {
+            return false;
+        }
+        assert!(self.config.auto_pause_on_degenerate_seed, "Random seed looks degenerate!");
+
+        self.paused = true;
+        self.emit_event("auto_paused", json!({
+            "reason": "degenerate_random_seed",
+            "block_index": env::block_index(),
+        }));
+        true
+    }
+
+    fn seed_is_degenerate(&self) -> bool {
+        env::random_seed().windows(2).all(|pair| pair[0] == pair[1])
+    }
+
+    fn assert_testnet(&self) {
+        assert_eq!(self.network, NetworkMode::Testnet, "Only available on testnet!");
+    }
+
+    // Appends the base odds to the timeline if they changed, shifting the oldest entry out
+    // into `odds_since` once it's full
+    fn record_odds(&mut self) {
+        let len = self.odds_timeline.len();
+        let latest = if len > 0 { self.odds_timeline.get(len - 1).unwrap() } else { self.odds_since };
+        if latest.1 == self.config.odds {
+            return;
+        }
+        if len == MAX_ODDS_TIMELINE {
+            self.odds_since = self.odds_timeline.get(0).unwrap();
+            for index in 1..len {
+                let entry = self.odds_timeline.get(index).unwrap();
+                self.odds_timeline.replace(index - 1, &entry);
+            }
+            self.odds_timeline.pop();
+        }
+        self.odds_timeline.push(&(env::block_timestamp(), self.config.odds));
+    }
+
+    // Validated when it was scheduled and on every `set_config` since, so it applies as is
+    fn apply_due_config_change(&mut self) {
+        if self.pending_config.as_ref().is_some_and(|pending| env::block_timestamp() >= pending.effective_at) {
+            let pending = self.pending_config.take().unwrap();
+            self.config.apply(pending.update);
+            self.record_odds();
+            env::log(format!("Config updated: {:?}", &self.config).as_bytes());
+        }
+    }
+
+    // Rejects an owner config change within `config_change_min_interval_ns` of the last one
+    fn record_config_change(&mut self) {
+        let now = env::block_timestamp();
+        if let Some(last) = self.last_config_change {
+            let next = last.saturating_add(self.config.config_change_min_interval_ns);
+            assert!(now >= next, "Config can't change again until {}", next);
+        }
+        self.last_config_change = Some(now);
+    }
+
+    fn assert_config_unlocked(&self) {
+        assert!(
+            env::block_timestamp() >= self.config.config_locked_until,
+            "Config is locked until {}", self.config.config_locked_until
+        );
+    }
+
+    fn next_play_at(&self, account_id: &AccountId) -> u64 {
+        let stats = self.stats.get(account_id).unwrap_or_default();
+        if stats.total_plays == 0 {
+            return 0;
+        }
+        stats.last_played_at.saturating_add(self.config.cooldown_for(stats.last_stake))
+    }
+
+    fn enforced_break(&self, account_id: &AccountId) -> Option<u64> {
+        self.rapid_plays
+            .get(account_id)
+            .map(|burst| burst.break_until)
+            .filter(|until| env::block_timestamp() < *until)
+    }
+
+    // Counts the play towards the account's burst window, which starts with its first play
+    // and lasts `rapid_play_window_ns`. Reaching the limit starts a break and a new window.
+    fn record_rapid_play(&mut self, account_id: &AccountId) {
+        if self.config.rapid_play_limit == 0 {
+            return;
+        }
+        let now = env::block_timestamp();
+        let mut burst = self.rapid_plays.get(account_id).unwrap_or_default();
+        if burst.plays == 0 || now >= burst.window_started_at.saturating_add(self.config.rapid_play_window_ns) {
+            burst.window_started_at = now;
+            burst.plays = 0;
+        }
+        burst.plays += 1;
+        if burst.plays >= self.config.rapid_play_limit {
+            burst.break_until = now.saturating_add(self.config.break_duration_ns);
+            burst.plays = 0;
+            self.emit_player_event("break_enforced", json!({
+                "account_id": account_id,
+                "until": burst.break_until,
+            }));
+        }
+        self.rapid_plays.insert(account_id, &burst);
+    }
+
+    fn cooldown_rejection(&self, account_id: &AccountId) -> Option<String> {
+        let next_play_at = self.next_play_at(account_id);
+        if env::block_timestamp() < next_play_at {
+            return Some(format!("Cooling down until {}; use play_priority to play now", next_play_at));
+        }
+        None
+    }
+
+    fn assert_cooldown_passed(&self, account_id: &AccountId) {
+        if let Some(reason) = self.cooldown_rejection(account_id) {
+            panic!("{}", reason);
+        }
+    }
+
+    // Under `delayed_resolution` only `play` (or `request_play`) may bet, since every other
+    // way of playing would roll on the seed of the block it's called in
+    fn assert_immediate_play(&self) {
+        assert!(!self.config.delayed_resolution, "{}", DELAYED_PLAY_REJECTION);
+    }
+
+    fn assert_not_paused(&self) {
+        assert!(!self.paused, "Game is paused!");
+    }
+
+    fn assert_not_blacklisted(&self, account_id: &AccountId) {
+        assert!(!self.blacklist.get(account_id).unwrap_or(false), "Account is blacklisted!");
+    }
+
+    fn assert_owner(&self) {
+        assert_eq!(env::predecessor_account_id(), self.owner_id, "Only the owner can call this method!");
+    }
+
+    fn assert_no_signers(&self) {
+        assert!(self.required_approvals == 0, "This action needs signer approval, use propose_action!");
+    }
+
+    fn assert_signer(&self) {
+        assert!(self.signers.contains(&env::predecessor_account_id()), "Only a signer can call this method!");
+    }
+
+    fn set_signers_internal(&mut self, signers: Vec<AccountId>, required_approvals: u8) {
+        for (i, signer) in signers.iter().enumerate() {
+            assert!(env::is_valid_account_id(signer.as_bytes()), "Invalid signer account!");
+            assert!(!signers[..i].contains(signer), "Duplicate signer!");
+        }
+        assert!((required_approvals as usize) <= signers.len(), "Not enough signers to reach the threshold!");
+        assert!(required_approvals > 0 || signers.is_empty(), "Signers need a non-zero threshold!");
+
+        self.emit_event("signers_updated", json!({
+            "signers": signers,
+            "required_approvals": required_approvals,
+        }));
+        self.signers = signers;
+        self.required_approvals = required_approvals;
+    }
+
+    // Catches malformed proposals up front; the rest is checked when the action runs
+    fn assert_valid_action(action: &OwnerAction) {
+        match action {
+            OwnerAction::AdjustCredits { account_id, reason, .. } => {
+                assert!(env::is_valid_account_id(account_id.as_bytes()), "Invalid account!");
+                assert!(!reason.trim().is_empty(), "A reason is required!");
+            }
+            OwnerAction::MigrateBankroll { new_contract, .. } => {
+                assert!(env::is_valid_account_id(new_contract.as_bytes()), "Invalid contract account!");
+            }
+            OwnerAction::SetSigners { .. } => {}
+        }
+    }
+
+    // Only approvals from current signers count, so removing a signer also drops its
+    // approvals on open proposals
+    fn execute_if_approved(&mut self, proposal_id: u64) -> bool {
+        let proposal = self.proposals.get(&proposal_id).unwrap();
+        let approvals = proposal.approvals.iter().filter(|signer| self.signers.contains(signer)).count();
+        if approvals < self.required_approvals as usize {
+            return false;
+        }
+        self.proposals.remove(&proposal_id);
+        self.emit_event("action_executed", json!({ "proposal_id": proposal_id }));
+        match proposal.action {
+            OwnerAction::AdjustCredits { account_id, new_balance, reason } => {
+                self.adjust_credits_internal(account_id, new_balance, reason);
+            }
+            OwnerAction::MigrateBankroll { new_contract, amount } => {
+                self.migrate_bankroll_internal(new_contract, amount);
+            }
+            OwnerAction::SetSigners { signers, required_approvals } => {
+                self.set_signers_internal(signers, required_approvals);
+            }
+        }
+        true
+    }
+}
+
+#[cfg(feature = "debug")]
+#[near_bindgen]
+impl SlotMachine {
+    // Borsh-serialized bytes, keys included, of every per-account entry stored for the
+    // account. Matches and airdrops aren't keyed by account and aren't counted.
+    pub fn account_state_size(&self, account_id: AccountId) -> u64 {
+        fn entry_size<V: BorshSerialize>(account_id: &AccountId, value: Option<V>) -> u64 {
+            value.map_or(0, |value| (account_id.try_to_vec().unwrap().len() + value.try_to_vec().unwrap().len()) as u64)
+        }
+
+        entry_size(&account_id, self.credits.get(&account_id))
+            + entry_size(&account_id, self.vaults.get(&account_id))
+            + entry_size(&account_id, self.blacklist.get(&account_id))
+            + entry_size(&account_id, self.stats.get(&account_id))
+            + entry_size(&account_id, self.total_withdrawn.get(&account_id))
+            + entry_size(&account_id, self.play_proofs.get(&account_id))
+            + entry_size(&account_id, self.claimable.get(&account_id))
+            + entry_size(&account_id, self.claimable_since.get(&account_id))
+            + entry_size(&account_id, self.history.get(&account_id))
+            + entry_size(&account_id, self.deposit_history.get(&account_id))
+            + entry_size(&account_id, self.first_seen.get(&account_id))
+            + entry_size(&account_id, self.cashback.get(&account_id))
+            + entry_size(&account_id, self.auto_payout.get(&account_id))
+            + entry_size(&account_id, self.reward_points.get(&account_id))
+            + entry_size(&account_id, self.last_rolls.get(&account_id))
+            + entry_size(&account_id, self.trials_remaining.get(&account_id))
+            + entry_size(&account_id, self.bonus_credits.get(&account_id))
+    }
+}
+
+/*
+ * The rest of this file holds the inline tests for the code above
+ * Learn more about Rust tests: https://doc.rust-lang.org/book/ch11-01-writing-tests.html
+ *
+ * To run from contract directory:
+ * cargo test -- --nocapture
+ *
+ * From project root, to run in combination with frontend tests:
+ * yarn test
+ *
+ */
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use near_sdk::MockedBlockchain;
+    use near_sdk::test_utils::{get_created_receipts, get_logs, testing_env_with_promise_results};
+    use near_sdk::{testing_env, VMContext};
+
+    const DEPOSIT_AMOUNT: u128 = 10 * ONE_NEAR;
+
+    // mock the context for testing, notice "signer_account_id" that was accessed above from env::
+    fn get_context(input: Vec<u8>, is_view: bool) -> VMContext {
+        VMContext {
+            current_account_id: "alice_near".to_string(),
+            signer_account_id: "bob_near".to_string(),
+            signer_account_pk: vec![0, 1, 2],
+            predecessor_account_id: "carol_near".to_string(),
+            input,
+            block_index: 0,
+            block_timestamp: 0,
+            account_balance: 100 * ONE_NEAR,
+            account_locked_balance: 0,
+            storage_usage: 0,
+            attached_deposit: DEPOSIT_AMOUNT,
+            prepaid_gas: 10u64.pow(18),
+            random_seed: vec![0, 1, 2],
+            is_view,
+            output_data_receivers: vec![],
+            epoch_height: 19,
+        }
+    }
+
+    // swap in a new context mid-test, carrying over the storage usage recorded so far
+    fn update_context(mut context: VMContext) {
+        context.storage_usage = env::storage_usage();
+        testing_env!(context);
+    }
+
+    // the default context attaches a deposit for `deposit`, which `play` refuses. What was
+    // attached so far stays in the balance.
+    fn without_deposit(context: &mut VMContext) {
+        context.account_balance = env::account_balance();
+        context.attached_deposit = 0;
+        update_context(context.clone());
+    }
+
+    #[test]
+    fn deposit() {
+        let context = get_context(vec![], false);
+        testing_env!(context);
+        
+        let mut contract = SlotMachine::new(String::from("carol_near"));
+
+        assert_eq!(U128::from(DEPOSIT_AMOUNT), contract.deposit());
+
+        assert_eq!(
+            U128::from(DEPOSIT_AMOUNT),
+            contract.get_credits(String::from("bob_near"))
+        );
+
+        assert_eq!(
+            U128::from(0),
+            contract.get_credits(String::from("carol_near"))
+        );
+    }
+
+    #[test]
+    fn play() {
+        let mut context = get_context(vec![], false);
+        testing_env!(context.clone());
+        let mut contract = SlotMachine::new(String::from("carol_near"));
+        // the original 10x payout, which fails the house-edge check the defaults now pass
+        contract.config.payout_bps = 100_000;
+        
+        // Deposit 10 NEAR to 'bob_near'
+        contract.deposit();
+
+        without_deposit(&mut context);
+        let number = contract.play(None, None);
+        let mut credits = DEPOSIT_AMOUNT;
+        
+        if number < 128 {
+            credits += 10 * ONE_NEAR;
+        }
+
+        credits -= ONE_NEAR;
+
+        assert_eq!(
+            U128::from(credits),
+            contract.get_credits(String::from("bob_near"))
+        );
+    }
+
+    #[test]
+    fn get_initial_credits() {
+        let context = get_context(vec![], true);
+        testing_env!(context);
+        let contract = SlotMachine::new(String::from("carol_near"));
+        // this test did not call set_greeting so should return the default "Hello" greeting
+        assert_eq!(
+            U128::from(0),
+            contract.get_credits(String::from("bob_near"))
+        );
+    }
+
+    #[test]
+    fn new_uses_default_config() {
+        let context = get_context(vec![], false);
+        testing_env!(context);
+        let contract = SlotMachine::new(String::from("carol_near"));
+
+        assert_eq!(GameConfig::default(), contract.get_config());
+    }
+
+    #[test]
+    fn new_with_config() {
+        let context = get_context(vec![], false);
+        testing_env!(context);
+        let config = GameConfig {
+            odds: 100,
+            payout_bps: 25_000,
+            fee_bps: 50,
+            ..Default::default()
+        };
+        let contract = SlotMachine::new_with_config(String::from("carol_near"), config.clone());
+
+        assert_eq!(config, contract.get_config());
+        assert_eq!(GameMetadata::default(), contract.get_metadata());
+        assert_eq!(NetworkMode::Mainnet, contract.get_network());
+    }
+
+    #[test]
+    fn new_with_options() {
+        let context = get_context(vec![], false);
+        testing_env!(context);
+        let metadata = GameMetadata { name: String::from("High Roller"), description: String::from("2.5x payouts") };
+        let contract = SlotMachine::new_with_options(
+            String::from("carol_near"), GameConfig::default(), metadata.clone(), NetworkMode::Testnet
+        );
+
+        assert_eq!(metadata, contract.get_metadata());
+        assert_eq!(NetworkMode::Testnet, contract.get_network());
+    }
+
+    #[test]
+    #[should_panic(expected = "Configuration gives the house a negative edge!")]
+    fn new_with_invalid_config() {
+        let context = get_context(vec![], false);
+        testing_env!(context);
+        let config = GameConfig {
+            payout_bps: 25_000,
+            ..Default::default()
+        };
+        SlotMachine::new_with_config(String::from("carol_near"), config);
+    }
+
+    #[test]
+    fn event_seq_increments() {
+        let mut context = get_context(vec![], false);
+        testing_env!(context.clone());
+        let mut contract = SlotMachine::new(String::from("carol_near"));
+        assert_eq!(0, contract.get_event_seq());
+
+        contract.deposit();
+        assert_eq!(1, contract.get_event_seq());
+        without_deposit(&mut context);
+        contract.play(None, None);
+        contract.withdraw(U128::from(ONE_NEAR));
+        assert_eq!(3, contract.get_event_seq());
+
+        let events: Vec<serde_json::Value> = get_logs()
+            .iter()
+            .filter_map(|log| log.strip_prefix("EVENT_JSON:"))
+            .map(|log| serde_json::from_str(log).unwrap())
+            .collect();
+        let names: Vec<&str> = events.iter().map(|event| event["event"].as_str().unwrap()).collect();
+        let seqs: Vec<u64> = events.iter().map(|event| event["event_seq"].as_u64().unwrap()).collect();
+        assert_eq!(vec!["play", "withdraw"], names);
+        assert_eq!(vec![2, 3], seqs);
+    }
+
+    #[test]
+    fn outcome_for_roll() {
+        let mut context = get_context(vec![], false);
+        testing_env!(context.clone());
+        let mut contract = SlotMachine::new(String::from("carol_near"));
+        contract.deposit();
+
+        assert!(contract.outcome_for_roll(PROB - 1, None));
+        assert!(!contract.outcome_for_roll(PROB, None));
+
+        // matches what play actually does with the upcoming roll
+        let roll = contract.derive_roll(&String::from("bob_near"), contract.nonce);
+        let expected = contract.outcome_for_roll(roll, Some(String::from("bob_near")));
+        let credits = contract.get_credits(String::from("bob_near")).0;
+        without_deposit(&mut context);
+        contract.play(None, None);
+        assert_eq!(expected, contract.get_credits(String::from("bob_near")).0 > credits);
+
+        // VIP bonuses move the boundary for that account only
+        contract.config.vip_tiers = vec![(U128::from(0), 10)];
+        assert!(contract.outcome_for_roll(PROB + 9, Some(String::from("bob_near"))));
+        assert!(!contract.outcome_for_roll(PROB + 10, Some(String::from("bob_near"))));
+    }
+
+    #[test]
+    fn roll_distribution() {
+        let context = get_context(vec![], false);
+        testing_env!(context);
+        let mut contract = SlotMachine::new(String::from("carol_near"));
+        contract.deposit();
+        assert_eq!(vec![0; 256], contract.get_roll_distribution());
+
+        let mut expected = vec![0; 256];
+        for seed in 0..5u8 {
+            let mut context = get_context(vec![], false);
+            context.random_seed = vec![seed, 1, 2, 3];
+            without_deposit(&mut context);
+            contract.play(None, None);
+            expected[contract.get_play_proof(String::from("bob_near")).unwrap().roll as usize] += 1;
+        }
+        assert_eq!(expected, contract.get_roll_distribution());
+        assert_eq!(5, contract.get_roll_distribution().iter().sum::<u64>());
+    }
+
+    #[test]
+    fn migrate_bankroll() {
+        let context = get_context(vec![], false);
+        testing_env!(context);
+        let mut contract = SlotMachine::new(String::from("carol_near"));
+        contract.deposit();
+        contract.set_paused(true);
+
+        let mut context = get_context(vec![], false);
+        context.attached_deposit = 1;
+        update_context(context);
+        let surplus = contract.get_solvency().house_equity.0 as u128;
+        contract.migrate_bankroll(String::from("successor_near"), U128::from(surplus));
+
+        assert_eq!(1, get_created_receipts().len());
+        assert!(get_logs().iter().any(|log| log.contains("\"event\":\"bankroll_migrated\"")));
+        assert_eq!(DEPOSIT_AMOUNT, contract.total_player_credits);
+    }
+
+    #[test]
+    #[should_panic(expected = "of house surplus can be migrated")]
+    fn migrate_bankroll_with_player_funds() {
+        let context = get_context(vec![], false);
+        testing_env!(context);
+        let mut contract = SlotMachine::new(String::from("carol_near"));
+        contract.deposit();
+        contract.set_paused(true);
+
+        let mut context = get_context(vec![], false);
+        context.attached_deposit = 1;
+        update_context(context);
+        let surplus = contract.get_solvency().house_equity.0 as u128;
+        contract.migrate_bankroll(String::from("successor_near"), U128::from(surplus + 1));
+    }
+
+    #[test]
+    fn migrate_bankroll_leaves_uncollected_fees() {
+        let mut context = get_context(vec![], false);
+        testing_env!(context.clone());
+        let mut contract = SlotMachine::new(String::from("carol_near"));
+        contract.set_config(GameConfigUpdate { fee_bps: Some(100), ..Default::default() });
+        contract.deposit();
+        contract.forced_roll = Some(255);
+        without_deposit(&mut context);
+        contract.play(None, None);
+        contract.set_paused(true);
+
+        let fees = contract.accumulated_fees;
+        assert_eq!(ONE_NEAR / 100, fees);
+        let surplus = contract.get_solvency().house_equity.0 as u128 - fees;
+        assert_eq!(U128::from(surplus), contract.get_owner_earnings().withdrawable_surplus);
+
+        without_deposit(&mut context);
+        context.attached_deposit = 1;
+        update_context(context.clone());
+        contract.migrate_bankroll(String::from("successor_near"), U128::from(surplus));
+
+        // the transfer has already left the balance, and what's left still covers the owner's fees
+        without_deposit(&mut context);
+        contract.collect_fees();
+        assert_eq!(fees, contract.fees_collected);
+    }
+
+    #[test]
+    #[should_panic(expected = "of house surplus can be migrated")]
+    fn migrate_bankroll_with_uncollected_fees() {
+        let mut context = get_context(vec![], false);
+        testing_env!(context.clone());
+        let mut contract = SlotMachine::new(String::from("carol_near"));
+        contract.set_config(GameConfigUpdate { fee_bps: Some(100), ..Default::default() });
+        contract.deposit();
+        contract.forced_roll = Some(255);
+        without_deposit(&mut context);
+        contract.play(None, None);
+        contract.set_paused(true);
+
+        let mut context = get_context(vec![], false);
+        context.attached_deposit = 1;
+        update_context(context);
+        let house_equity = contract.get_solvency().house_equity.0 as u128;
+        contract.migrate_bankroll(String::from("successor_near"), U128::from(house_equity));
+    }
+
+    #[test]
+    #[should_panic(expected = "Pause the game before migrating the bankroll!")]
+    fn migrate_bankroll_while_running() {
+        let mut context = get_context(vec![], false);
+        context.attached_deposit = 1;
+        testing_env!(context);
+        let mut contract = SlotMachine::new(String::from("carol_near"));
+
+        contract.migrate_bankroll(String::from("successor_near"), U128::from(ONE_NEAR));
+    }
+
+    #[test]
+    fn win_range() {
+        let context = get_context(vec![], false);
+        testing_env!(context);
+        let mut contract = SlotMachine::new(String::from("carol_near"));
+
+        // the middle of the distribution wins and both extremes lose
+        contract.set_win_range(64, 191);
+        assert_eq!((64, 191), contract.get_win_range());
+        assert_eq!(128, contract.get_config().odds);
+        let wins: Vec<bool> = [0, 63, 64, 191, 192, 255].iter().map(|roll| contract.outcome_for_roll(*roll, None)).collect();
+        assert_eq!(vec![false, false, true, true, false, false], wins);
+
+        contract.set_win_range(255, 255);
+        assert!(contract.outcome_for_roll(255, None));
+        assert!(!contract.outcome_for_roll(254, None));
+
+        contract.set_win_range(0, 0);
+        assert!(contract.outcome_for_roll(0, None));
+        assert!(!contract.outcome_for_roll(1, None));
+
+        // a bonus can't stretch the range past the last roll
+        contract.set_win_range(250, 250);
+        contract.config.vip_tiers = vec![(U128::from(0), 100)];
+        assert_eq!(101, contract.get_effective_odds(String::from("bob_near")));
+        assert!(contract.outcome_for_roll(255, Some(String::from("bob_near"))));
+        assert!(!contract.outcome_for_roll(249, Some(String::from("bob_near"))));
+    }
+
+    #[test]
+    fn win_range_decides_play() {
+        let context = get_context(vec![], false);
+        testing_env!(context);
+        let mut contract = SlotMachine::new(String::from("carol_near"));
+        contract.deposit();
+
+        // a one-roll range on exactly the upcoming roll wins, and only that roll
+        let roll = contract.derive_roll(&String::from("bob_near"), contract.nonce);
+        contract.config.odds = 1;
+        contract.config.win_low = roll;
+        match contract.try_play(U128::from(ONE_NEAR)) {
+            PlayOutcome::Ok(result) => assert!(result.won),
+            PlayOutcome::Rejected(reason) => panic!("rejected: {}", reason),
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "Range must leave at least one losing roll!")]
+    fn win_range_covering_every_roll() {
+        let context = get_context(vec![], false);
+        testing_env!(context);
+        let mut contract = SlotMachine::new(String::from("carol_near"));
+
+        contract.set_win_range(0, 255);
+    }
+
+    #[test]
+    fn delayed_resolution() {
+        let mut context = get_context(vec![], false);
+        testing_env!(context.clone());
+        let mut contract = SlotMachine::new(String::from("carol_near"));
+        contract.set_config(GameConfigUpdate { delayed_resolution: Some(true), ..Default::default() });
+        contract.deposit();
+        let bob = String::from("bob_near");
+
+        without_deposit(&mut context);
+        // the bet only moves the stake into escrow
+        assert_eq!(u8::MAX, contract.play(None, None));
+        assert_eq!(U128::from(DEPOSIT_AMOUNT - ONE_NEAR), contract.get_credits(bob.clone()));
+        assert_eq!(Some(context.block_index), contract.get_pending_bet(bob.clone()).map(|bet| bet.block_index));
+        assert_eq!(DEPOSIT_AMOUNT, contract.liabilities());
+        assert!(contract.get_player_history(bob.clone(), 10).is_empty());
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| contract.resolve(None)));
+        assert!(result.is_err());
+
+        context.block_index += 1;
+        update_context(context);
+        contract.forced_roll = Some(0);
+        let result = contract.resolve(None);
+        assert!(result.won);
+        assert_eq!(U128::from(DEPOSIT_AMOUNT + ONE_NEAR), result.credits);
+        assert!(contract.get_pending_bet(bob).is_none());
+        assert_eq!(DEPOSIT_AMOUNT + ONE_NEAR, contract.liabilities());
+    }
+
+    fn delayed_contract() -> SlotMachine {
+        let context = get_context(vec![], false);
+        testing_env!(context);
+        let mut contract = SlotMachine::new(String::from("carol_near"));
+        contract.set_config(GameConfigUpdate { delayed_resolution: Some(true), ..Default::default() });
+        contract.deposit();
+        contract
+    }
+
+    #[test]
+    fn delayed_resolution_holds_back_trial_spins() {
+        let mut context = get_context(vec![], false);
+        testing_env!(context.clone());
+        let mut contract = SlotMachine::new(String::from("carol_near"));
+        contract.set_config(GameConfigUpdate { delayed_resolution: Some(true), trial_spins: Some(2), ..Default::default() });
+        contract.deposit();
+        let bob = String::from("bob_near");
+
+        // a real bet is placed for a later block instead of a trial rolling now
+        without_deposit(&mut context);
+        contract.forced_roll = Some(0);
+        contract.play(None, None);
+        assert!(contract.get_pending_bet(bob.clone()).is_some());
+        assert_eq!(2, contract.get_trials_remaining(bob.clone()));
+        assert!(contract.bonus_credits.get(&bob).is_none());
+    }
+
+    #[test]
+    fn delayed_resolution_rejects_try_play() {
+        let mut contract = delayed_contract();
+        assert_eq!(
+            PlayOutcome::Rejected(String::from(DELAYED_PLAY_REJECTION)),
+            contract.try_play(U128::from(ONE_NEAR))
+        );
+        assert!(contract.get_player_history(String::from("bob_near"), 10).is_empty());
+    }
+
+    #[test]
+    #[should_panic(expected = "Plays resolve a block later, use play/resolve")]
+    fn delayed_resolution_rejects_play_batch() {
+        delayed_contract().play_batch(2, None);
+    }
+
+    #[test]
+    #[should_panic(expected = "Plays resolve a block later, use play/resolve")]
+    fn delayed_resolution_rejects_play_until_loss() {
+        delayed_contract().play_until_loss(U128::from(ONE_NEAR), U128::from(2 * ONE_NEAR));
+    }
+
+    #[test]
+    #[should_panic(expected = "Plays resolve a block later, use play/resolve")]
+    fn delayed_resolution_rejects_play_with_insurance() {
+        delayed_contract().play_with_insurance(U128::from(ONE_NEAR), 100);
+    }
+
+    #[test]
+    #[should_panic(expected = "Plays resolve a block later, use play/resolve")]
+    fn delayed_resolution_rejects_play_priority() {
+        delayed_contract().play_priority(U128::from(ONE_NEAR));
+    }
+
+    #[test]
+    #[should_panic(expected = "Plays resolve a block later, use play/resolve")]
+    fn delayed_resolution_rejects_play_for() {
+        delayed_contract().play_for(String::from("bob_near"), U128::from(ONE_NEAR));
+    }
+
+    #[test]
+    #[should_panic(expected = "Plays resolve a block later, use play/resolve")]
+    fn delayed_resolution_rejects_deposit_and_play() {
+        delayed_contract().deposit_and_play(U128::from(ONE_NEAR));
+    }
+
+    #[test]
+    fn hidden_bets_resolve_with_a_matching_reveal() {
+        let mut context = get_context(vec![], false);
+        testing_env!(context.clone());
+        let mut contract = SlotMachine::new(String::from("carol_near"));
+        contract.set_config(GameConfigUpdate {
+            hidden_bets: Some(true),
+            max_bet: Some(U128::from(2 * ONE_NEAR)),
+            ..Default::default()
+        });
+        contract.deposit();
+        let bob = String::from("bob_near");
+        let salt = vec![7; 32];
+        let stake = ONE_NEAR;
+
+        // only the escrow for the largest bet shows, not the stake
+        let commitment = bet_commitment(&bob, stake, &salt);
+        assert_eq!(U128::from(2 * ONE_NEAR), contract.request_play(commitment.clone().into()));
+        assert_eq!(Some(Base64VecU8::from(commitment)), contract.get_pending_bet(bob.clone()).unwrap().commitment);
+
+        context.block_index += 1;
+        update_context(context);
+        contract.forced_roll = Some(0);
+        let tampered = BetReveal { stake: U128::from(2 * ONE_NEAR), salt: salt.clone().into() };
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| contract.resolve(Some(tampered.clone()))));
+        assert!(result.is_err());
+        assert!(std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| contract.resolve_for(bob.clone()))).is_err());
+
+        let result = contract.resolve(Some(BetReveal { stake: U128::from(stake), salt: salt.into() }));
+        assert!(result.won);
+        assert_eq!(U128::from(stake), result.stake);
+        assert_eq!(U128::from(DEPOSIT_AMOUNT + stake), result.credits);
+        assert!(contract.get_pending_bet(bob).is_none());
+    }
+
+    #[test]
+    fn unrevealed_hidden_bets_are_refunded() {
+        let mut context = get_context(vec![], false);
+        testing_env!(context.clone());
+        let mut contract = SlotMachine::new(String::from("carol_near"));
+        contract.set_config(GameConfigUpdate {
+            hidden_bets: Some(true),
+            reveal_timeout_blocks: Some(10),
+            ..Default::default()
+        });
+        contract.deposit();
+        let bob = String::from("bob_near");
+        let placed_at = context.block_index;
+        contract.request_play(bet_commitment(&bob, ONE_NEAR, &[7; 32]).into());
+        assert_eq!(U128::from(DEPOSIT_AMOUNT - ONE_NEAR), contract.get_credits(bob.clone()));
+
+        context.block_index = placed_at + 9;
+        update_context(context.clone());
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| contract.refund_hidden_bet(bob.clone())));
+        assert!(result.is_err());
+
+        // anyone can refund it once the reveal has timed out
+        context.block_index = placed_at + 10;
+        context.signer_account_id = String::from("dave_near");
+        context.predecessor_account_id = String::from("dave_near");
+        update_context(context);
+        assert_eq!(U128::from(ONE_NEAR), contract.refund_hidden_bet(bob.clone()));
+        assert_eq!(U128::from(DEPOSIT_AMOUNT), contract.get_credits(bob.clone()));
+        assert!(contract.get_pending_bet(bob).is_none());
+        assert_eq!(0, contract.total_locked_credits);
+    }
+
+    #[test]
+    #[should_panic(expected = "Only hidden bets can be refunded!")]
+    fn open_pending_bets_cant_be_refunded() {
+        let mut contract = delayed_contract();
+        let mut context = get_context(vec![], false);
+        without_deposit(&mut context);
+        contract.play(None, None);
+
+        context.block_index += 86_400;
+        update_context(context);
+        contract.refund_hidden_bet(String::from("bob_near"));
+    }
+
+    #[test]
+    fn keepers_resolve_pending_bets() {
+        let mut context = get_context(vec![], false);
+        testing_env!(context.clone());
+        let mut contract = SlotMachine::new(String::from("carol_near"));
+        contract.set_config(GameConfigUpdate { delayed_resolution: Some(true), hidden_bets: Some(true), ..Default::default() });
+        let start = context.block_index;
+        for (signer, block_index) in [("bob_near", start), ("frank_near", start), ("dave_near", start + 1), ("erin_near", start + 2)] {
+            context.signer_account_id = signer.to_string();
+            context.block_index = block_index;
+            context.attached_deposit = DEPOSIT_AMOUNT;
+            update_context(context.clone());
+            contract.deposit();
+            if signer == "frank_near" {
+                contract.request_play(bet_commitment(&signer.to_string(), ONE_NEAR, &[7; 32]).into());
+            } else {
+                without_deposit(&mut context);
+                contract.play(None, None);
+            }
+        }
+
+        // only the open bets placed before the current block are listed
+        assert_eq!(
+            vec![(String::from("bob_near"), start), (String::from("dave_near"), start + 1)],
+            contract.get_resolvable_bets(0, 10)
+        );
+        assert_eq!(vec![(String::from("dave_near"), start + 1)], contract.get_resolvable_bets(2, 1));
+
+        context.signer_account_id = "keeper_near".to_string();
+        update_context(context);
+        contract.resolve_for(String::from("bob_near"));
+        assert!(contract.get_pending_bet(String::from("bob_near")).is_none());
+        assert_eq!(1, contract.stats.get(&String::from("bob_near")).unwrap().total_plays);
+        assert!(std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| contract.resolve_for(String::from("erin_near")))).is_err());
+    }
+
+    #[test]
+    fn block_winner_cap() {
+        let mut context = get_context(vec![], false);
+        testing_env!(context.clone());
+        let mut contract = SlotMachine::new(String::from("carol_near"));
+        contract.deposit();
+        contract.set_config(GameConfigUpdate { max_winners_per_block: Some(2), ..Default::default() });
+        // win every play
+        contract.config.odds = u8::MAX;
+
+        without_deposit(&mut context);
+        contract.play(None, None);
+        contract.play(None, None);
+        assert_eq!(0, contract.get_remaining_winner_slots());
+        assert_eq!(
+            PlayOutcome::Rejected(String::from("Block winner cap reached, try again in the next block")),
+            contract.try_play(U128::from(ONE_NEAR))
+        );
+
+        context.block_index += 1;
+        update_context(context);
+        assert_eq!(2, contract.get_remaining_winner_slots());
+        contract.play(None, None);
+        assert_eq!(1, contract.get_remaining_winner_slots());
+    }
+
+    #[test]
+    fn block_payout_cap() {
+        let mut context = get_context(vec![], false);
+        testing_env!(context.clone());
+        let mut contract = SlotMachine::new(String::from("carol_near"));
+        contract.deposit();
+        contract.set_config(GameConfigUpdate { max_block_payout: Some(U128::from(5 * ONE_NEAR)), ..Default::default() });
+        // win every play
+        contract.config.odds = u8::MAX;
+
+        without_deposit(&mut context);
+        contract.play(None, None);
+        contract.play(None, None);
+        assert_eq!(U128::from(ONE_NEAR), contract.get_block_payout_allowance());
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| contract.play(None, None)));
+        assert!(result.is_err());
+
+        // the allowance resets in the next block
+        context.block_index += 1;
+        update_context(context);
+        assert_eq!(U128::from(5 * ONE_NEAR), contract.get_block_payout_allowance());
+        contract.play(None, None);
+        assert_eq!(U128::from(3 * ONE_NEAR), contract.get_block_payout_allowance());
+    }
+
+    #[test]
+    fn min_viable_stake() {
+        let context = get_context(vec![], false);
+        testing_env!(context);
+        let mut contract = SlotMachine::new(String::from("carol_near"));
+        assert_eq!(U128::from(1), contract.min_viable_stake());
+
+        // at 1.01x with a 0.5% fee a win nets 0.5% of the stake, which floors to zero below 200
+        contract.config.payout_bps = 10_100;
+        contract.config.fee_bps = 50;
+        let min = contract.min_viable_stake().0;
+        assert!(!contract.config.is_viable_stake(min - 1));
+        assert!((min..min + 1_000).all(|stake| contract.config.is_viable_stake(stake)));
+
+        contract.config.payout_bps = 10_050;
+        assert_eq!(U128::from(0), contract.min_viable_stake());
+    }
+
+    #[test]
+    #[should_panic(expected = "Stake too small to produce a winning payout")]
+    fn play_below_min_viable_stake() {
+        let mut context = get_context(vec![], false);
+        testing_env!(context.clone());
+        let mut contract = SlotMachine::new(String::from("carol_near"));
+        contract.deposit();
+        contract.set_config(GameConfigUpdate {
+            payout_bps: Some(10_100),
+            fee_bps: Some(50),
+            min_bet: Some(U128::from(1)),
+            ..Default::default()
+        });
+
+        let min = contract.min_viable_stake().0;
+        without_deposit(&mut context);
+        contract.play(Some(U128::from(min)), None);
+        contract.play(Some(U128::from(min - 1)), None);
+    }
+
+    #[test]
+    fn try_play() {
+        let context = get_context(vec![], false);
+        testing_env!(context);
+        let mut contract = SlotMachine::new(String::from("carol_near"));
+        contract.deposit();
+
+        let outcome = contract.try_play(U128::from(ONE_NEAR));
+        let result = match &outcome {
+            PlayOutcome::Ok(result) => result.clone(),
+            PlayOutcome::Rejected(reason) => panic!("rejected: {}", reason),
+        };
+        assert_eq!(result.credits, contract.get_credits(String::from("bob_near")));
+        let json = serde_json::to_value(&outcome).unwrap();
+        assert_eq!("Ok", json["status"]);
+        assert_eq!(result.roll, json["data"]["roll"]);
+    }
+
+    #[test]
+    #[should_panic(expected = "play doesn't take a deposit, use deposit_and_play!")]
+    fn play_with_attached_deposit() {
+        let mut context = get_context(vec![], false);
+        testing_env!(context.clone());
+        let mut contract = SlotMachine::new(String::from("carol_near"));
+        contract.deposit();
+
+        context.attached_deposit = 1;
+        update_context(context);
+        contract.play(None, None);
+    }
+
+    #[test]
+    #[should_panic(expected = "Insufficient credits: have 0, need 1000000000000000000000000")]
+    fn play_without_credits() {
+        let mut context = get_context(vec![], false);
+        testing_env!(context.clone());
+        let mut contract = SlotMachine::new(String::from("carol_near"));
+        without_deposit(&mut context);
+        contract.play(None, None);
+    }
+
+    #[test]
+    #[should_panic(expected = "Insufficient credits: have 300000000000000000000000, need 1010000000000000000000000")]
+    fn play_with_too_few_credits() {
+        let mut context = get_context(vec![], false);
+        context.attached_deposit = ONE_NEAR * 3 / 10;
+        testing_env!(context.clone());
+        let mut contract = SlotMachine::new(String::from("carol_near"));
+        contract.set_config(GameConfigUpdate { fee_bps: Some(100), ..Default::default() });
+        contract.deposit();
+
+        without_deposit(&mut context);
+        // the shortfall counts the fee on top of the stake
+        contract.play(None, None);
+    }
+
+    #[test]
+    fn format_balance() {
+        let context = get_context(vec![], false);
+        testing_env!(context);
+        let mut contract = SlotMachine::new(String::from("carol_near"));
+        let format = |contract: &SlotMachine, amount: Balance| contract.format_balance(U128::from(amount));
+
+        assert_eq!("0", format(&contract, 0));
+        assert_eq!("1.5", format(&contract, ONE_NEAR * 3 / 2));
+        assert_eq!("0.000001", format(&contract, ONE_NEAR / 1_000_000));
+        assert_eq!("12", format(&contract, 12 * ONE_NEAR));
+        // digits past `display_decimals` are cut, never rounded up
+        assert_eq!("0.999999", format(&contract, ONE_NEAR - 1));
+        assert_eq!("0", format(&contract, ONE_NEAR / 10_000_000));
+        assert_eq!("340282366920938.463463", format(&contract, u128::MAX));
+
+        contract.set_config(GameConfigUpdate { display_decimals: Some(TOKEN_DECIMALS), ..Default::default() });
+        assert_eq!("0.000000000000000000000001", format(&contract, 1));
+        contract.set_config(GameConfigUpdate { display_decimals: Some(0), ..Default::default() });
+        assert_eq!("1", format(&contract, ONE_NEAR * 3 / 2));
+    }
+
+    #[test]
+    fn can_play() {
+        let mut context = get_context(vec![], false);
+        context.block_timestamp = 1_000;
+        testing_env!(context.clone());
+        let mut contract = SlotMachine::new(String::from("carol_near"));
+        let bob = String::from("bob_near");
+        assert!(!contract.can_play(bob.clone()));
+
+        contract.deposit();
+        assert!(contract.can_play(bob.clone()));
+
+        contract.paused = true;
+        assert!(!contract.can_play(bob.clone()));
+        contract.paused = false;
+
+        contract.blacklist.insert(&bob, &true);
+        assert!(!contract.can_play(bob.clone()));
+        contract.blacklist.remove(&bob);
+
+        contract.set_config(GameConfigUpdate { cooldown_ns: Some(500), ..Default::default() });
+        without_deposit(&mut context);
+        contract.play(None, None);
+        assert!(!contract.can_play(bob.clone()));
+        context.block_timestamp = 1_500;
+        update_context(context);
+        assert!(contract.can_play(bob.clone()));
+
+        // not enough credits left for the minimum bet
+        let credits = contract.get_credits(bob.clone()).0;
+        contract.remove_credits(&bob, credits - (ONE_NEAR - 1));
+        assert!(!contract.can_play(bob));
+    }
+
+    #[test]
+    fn try_play_rejections() {
+        let context = get_context(vec![], false);
+        testing_env!(context);
+        let mut contract = SlotMachine::new(String::from("carol_near"));
+        let rejected = |contract: &mut SlotMachine, stake: Balance| match contract.try_play(U128::from(stake)) {
+            PlayOutcome::Rejected(reason) => reason,
+            PlayOutcome::Ok(_) => panic!("expected a rejection"),
+        };
+
+        assert_eq!(format!("Insufficient credits: have 0, need {}", ONE_NEAR), rejected(&mut contract, ONE_NEAR));
+        contract.deposit();
+        let credits = contract.get_credits(String::from("bob_near"));
+        let event_seq = contract.get_event_seq();
+        assert_eq!(format!("Stake must be between {} and {}", ONE_NEAR, ONE_NEAR), rejected(&mut contract, 2 * ONE_NEAR));
+
+        contract.set_config(GameConfigUpdate { max_lifetime_plays: Some(1), ..Default::default() });
+        contract.stats.insert(&String::from("bob_near"), &PlayerStats { total_plays: 1, ..Default::default() });
+        assert_eq!("Lifetime play limit reached", rejected(&mut contract, ONE_NEAR));
+        contract.set_config(GameConfigUpdate { max_lifetime_plays: Some(0), ..Default::default() });
+
+        contract.set_config(GameConfigUpdate { max_block_payout: Some(U128::from(ONE_NEAR)), ..Default::default() });
+        assert_eq!("Block payout cap reached, try again in the next block", rejected(&mut contract, ONE_NEAR));
+        contract.set_config(GameConfigUpdate { max_block_payout: Some(U128::from(u128::MAX)), ..Default::default() });
+
+        contract.set_config(GameConfigUpdate { min_house_balance: Some(U128::from(u128::MAX / 2)), ..Default::default() });
+        assert_eq!("House bankroll too low", rejected(&mut contract, ONE_NEAR));
+        contract.set_config(GameConfigUpdate { min_house_balance: Some(U128::from(0)), ..Default::default() });
+
+        contract.config.payout_bps = MAX_BPS as u32;
+        assert_eq!("Stake too small to produce a winning payout", rejected(&mut contract, ONE_NEAR));
+        contract.config.payout_bps = 20_000;
+
+        contract.set_paused(true);
+        assert_eq!("Game is paused!", rejected(&mut contract, ONE_NEAR));
+        contract.set_paused(false);
+
+        contract.blacklist_account(String::from("bob_near"));
+        assert_eq!("Account is blacklisted!", rejected(&mut contract, ONE_NEAR));
+        contract.unblacklist_account(String::from("bob_near"));
+
+        let mut context = get_context(vec![], false);
+        context.random_seed = vec![0; 32];
+        update_context(context);
+        assert_eq!("Random seed looks degenerate!", rejected(&mut contract, ONE_NEAR));
+        assert!(!contract.is_paused());
+
+        // none of the rejections touched the player's state
+        assert_eq!(credits, contract.get_credits(String::from("bob_near")));
+        assert_eq!(event_seq, contract.get_event_seq());
+        assert!(contract.get_play_proof(String::from("bob_near")).is_none());
+    }
+
+    #[test]
+    fn airdrop_by_activity() {
+        let context = get_context(vec![], false);
+        testing_env!(context);
+        let mut contract = SlotMachine::new(String::from("carol_near"));
+        let players = ["bob_near", "dave_near", "erin_near"];
+        for (player, wagered) in players.iter().zip(&[5 * ONE_NEAR, 3 * ONE_NEAR, 2 * ONE_NEAR]) {
+            contract.stats.insert(&player.to_string(), &PlayerStats { total_wagered: *wagered, ..Default::default() });
+            contract.total_wagered += wagered;
+        }
+
+        contract.airdrop_by_activity(1, U128::from(ONE_NEAR), 0, 2);
+        contract.airdrop_by_activity(1, U128::from(ONE_NEAR), 2, 2);
+
+        let credits: Vec<U128> = players.iter().map(|player| contract.get_credits(player.to_string())).collect();
+        assert_eq!(vec![U128::from(ONE_NEAR / 2), U128::from(3 * ONE_NEAR / 10), U128::from(ONE_NEAR / 5)], credits);
+        assert_eq!(ONE_NEAR, contract.airdrops.get(&1).unwrap().distributed);
+
+        // replaying a page is rejected
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            contract.airdrop_by_activity(1, U128::from(ONE_NEAR), 0, 2);
+        }));
+        assert!(result.is_err());
+        assert_eq!(U128::from(ONE_NEAR / 2), contract.get_credits(String::from("bob_near")));
+    }
+
+    #[test]
+    fn pro_rata_does_not_overflow() {
+        assert_eq!(ONE_NEAR / 4, pro_rata(ONE_NEAR, 1, 4));
+        assert_eq!(1_000 * ONE_NEAR, pro_rata(4_000 * ONE_NEAR, 500_000 * ONE_NEAR, 2_000_000 * ONE_NEAR));
+        assert_eq!(u128::MAX / 2, pro_rata(u128::MAX, u128::MAX / 2, u128::MAX));
+        assert_eq!(u128::MAX / 3, pro_rata(u128::MAX, 1, 3));
+    }
+
+    #[test]
+    fn backtest() {
+        let context = get_context(vec![], false);
+        testing_env!(context);
+        let contract = SlotMachine::new(String::from("carol_near"));
+
+        // a fair coin at 2x: no edge, sd = sqrt(100 * 0.25) * 2 NEAR, and P(wins < 50) = 46.02%
+        let fair = contract.backtest(U128::from(ONE_NEAR), 100, 128, 20_000, 0);
+        assert_eq!(I128::from(0), fair.expected_house_profit);
+        assert!((fair.house_profit_std_dev.0 as f64 / ONE_NEAR as f64 - 10.0).abs() < 1e-9);
+        assert_eq!(4_602, fair.player_loss_probability_bps);
+
+        // a 1% fee is the house's whole edge, and 50 wins no longer break even: P(wins <= 50) = 53.98%
+        let with_fee = contract.backtest(U128::from(ONE_NEAR), 100, 128, 20_000, 100);
+        assert_eq!(I128::from(ONE_NEAR as i128), with_fee.expected_house_profit);
+        assert_eq!(5_398, with_fee.player_loss_probability_bps);
+
+        // a game the player can't win is a certain loss with no variance
+        let hopeless = contract.backtest(U128::from(ONE_NEAR), 10, 0, 20_000, 0);
+        assert_eq!(I128::from(10 * ONE_NEAR as i128), hopeless.expected_house_profit);
+        assert_eq!(U128::from(0), hopeless.house_profit_std_dev);
+        assert_eq!(MAX_BPS, hopeless.player_loss_probability_bps);
+    }
+
+    #[test]
+    fn deposit_match() {
+        let mut context = get_context(vec![], false);
+        context.block_timestamp = 0;
+        testing_env!(context);
+        let mut contract = SlotMachine::new(String::from("carol_near"));
+        contract.set_deposit_match(5_000, 1_000);
+        assert_eq!(5_000, contract.get_deposit_match_bps());
+        assert_eq!((5_000, 1_000), (contract.get_config().deposit_match_bps, contract.get_config().deposit_match_until));
+
+        // inside the window a 10 NEAR deposit earns a 5 NEAR bonus
+        assert_eq!(U128::from(15 * ONE_NEAR), contract.deposit());
+        assert_eq!(DEPOSIT_AMOUNT, contract.stats.get(&String::from("bob_near")).unwrap().total_deposited);
+
+        // once it has ended the same deposit earns nothing extra
+        let mut context = get_context(vec![], false);
+        context.block_timestamp = 1_000;
+        context.account_balance += DEPOSIT_AMOUNT;
+        update_context(context);
+        assert_eq!(0, contract.get_deposit_match_bps());
+        assert_eq!(U128::from(25 * ONE_NEAR), contract.deposit());
+    }
+
+    #[test]
+    fn deposit_match_requires_account_age() {
+        let mut context = get_context(vec![], false);
+        context.block_timestamp = 0;
+        testing_env!(context.clone());
+        let mut contract = SlotMachine::new(String::from("carol_near"));
+        contract.set_config(GameConfigUpdate { min_account_age_ns: Some(500), ..Default::default() });
+        contract.set_deposit_match(5_000, 1_000);
+
+        // a brand-new account gets no bonus on its first deposit
+        assert_eq!(U128::from(DEPOSIT_AMOUNT), contract.deposit());
+        assert_eq!(U128::from(0), contract.get_deposit_history(String::from("bob_near"), 0, 1)[0].bonus);
+
+        // nor until it has been around for `min_account_age_ns`
+        context.block_timestamp = 499;
+        update_context(context.clone());
+        assert_eq!(U128::from(2 * DEPOSIT_AMOUNT), contract.deposit());
+
+        context.block_timestamp = 500;
+        update_context(context);
+        assert_eq!(U128::from(3 * DEPOSIT_AMOUNT + DEPOSIT_AMOUNT / 2), contract.deposit());
+    }
+
+    #[test]
+    fn cashback_on_net_losses() {
+        let mut context = get_context(vec![], false);
+        context.block_timestamp = 0;
+        testing_env!(context.clone());
+        let mut contract = SlotMachine::new(String::from("carol_near"));
+        contract.set_config(GameConfigUpdate {
+            cashback_tiers: Some(vec![(U128::from(ONE_NEAR), 1_000), (U128::from(3 * ONE_NEAR), 2_000)]),
+            ..Default::default()
+        });
+        contract.deposit();
+        let bob = String::from("bob_near");
+
+        // a win and a loss cancel out
+        contract.forced_roll = Some(0);
+        without_deposit(&mut context);
+        contract.play(None, None);
+        contract.forced_roll = Some(255);
+        contract.play(None, None);
+        assert_eq!(U128::from(0), contract.get_cashback_available(bob.clone()));
+
+        for _ in 0..3 {
+            contract.forced_roll = Some(255);
+            contract.play(None, None);
+        }
+        assert_eq!(U128::from(3 * ONE_NEAR / 5), contract.get_cashback_available(bob.clone()));
+
+        let credits = contract.get_credits(bob.clone()).0;
+        assert_eq!(U128::from(3 * ONE_NEAR / 5), contract.claim_cashback());
+        assert_eq!(U128::from(credits + 3 * ONE_NEAR / 5), contract.get_credits(bob.clone()));
+
+        // claiming starts a new window
+        assert_eq!(U128::from(0), contract.get_cashback_available(bob.clone()));
+        contract.forced_roll = Some(255);
+        contract.play(None, None);
+        assert_eq!(U128::from(ONE_NEAR / 10), contract.get_cashback_available(bob.clone()));
+
+        // and losses older than the window no longer count
+        context.block_timestamp = CASHBACK_WINDOW;
+        update_context(context);
+        assert_eq!(U128::from(0), contract.get_cashback_available(bob));
+    }
+
+    #[test]
+    #[should_panic(expected = "No cashback available!")]
+    fn claim_cashback_without_losses() {
+        let context = get_context(vec![], false);
+        testing_env!(context);
+        let mut contract = SlotMachine::new(String::from("carol_near"));
+        contract.claim_cashback();
+    }
+
+    #[test]
+    fn required_deposit_for() {
+        let mut context = get_context(vec![], false);
+        context.attached_deposit = ONE_NEAR / 4;
+        context.block_timestamp = 0;
+        testing_env!(context);
+        let mut contract = SlotMachine::new(String::from("carol_near"));
+        contract.set_config(GameConfigUpdate { fee_bps: Some(100), max_bet: Some(U128::from(2 * ONE_NEAR)), ..Default::default() });
+        let bob = String::from("bob_near");
+
+        // a new account needs the stake plus fee
+        assert_eq!(U128::from(ONE_NEAR + ONE_NEAR / 100), contract.required_deposit_for(bob.clone(), U128::from(ONE_NEAR)));
+
+        // a partially funded one only the rest
+        contract.deposit();
+        assert_eq!(U128::from(ONE_NEAR * 3 / 4 + ONE_NEAR / 100), contract.required_deposit_for(bob.clone(), U128::from(ONE_NEAR)));
+
+        // a deposit match shrinks what has to be sent
+        contract.set_deposit_match(MAX_BPS, 1_000);
+        let required = contract.required_deposit_for(bob.clone(), U128::from(2 * ONE_NEAR)).0;
+        assert_eq!(2 * ONE_NEAR + 2 * ONE_NEAR / 100 - ONE_NEAR / 4, 2 * required);
+
+        contract.add_credits(&bob, ONE_NEAR);
+        assert_eq!(U128::from(0), contract.required_deposit_for(bob, U128::from(ONE_NEAR)));
+    }
+
+    #[test]
+    fn scheduled_config_change() {
+        let mut context = get_context(vec![], false);
+        context.block_timestamp = 0;
+        testing_env!(context.clone());
+        let mut contract = SlotMachine::new(String::from("carol_near"));
+        contract.schedule_config_change(GameConfigUpdate { payout_bps: Some(15_000), ..Default::default() }, 1_000);
+        assert_eq!(1_000, contract.get_pending_config_change().unwrap().effective_at);
+        contract.deposit();
+        let bob = String::from("bob_near");
+
+        // still the old 2x payout before the effective time
+        context.block_timestamp = 999;
+        update_context(context.clone());
+        contract.forced_roll = Some(0);
+        without_deposit(&mut context);
+        contract.play(None, None);
+        assert_eq!(U128::from(DEPOSIT_AMOUNT + ONE_NEAR), contract.get_credits(bob.clone()));
+        assert_eq!(20_000, contract.get_config().payout_bps);
+
+        context.block_timestamp = 1_000;
+        update_context(context);
+        contract.forced_roll = Some(0);
+        contract.play(None, None);
+        assert_eq!(U128::from(DEPOSIT_AMOUNT + ONE_NEAR + ONE_NEAR / 2), contract.get_credits(bob));
+        assert_eq!(15_000, contract.get_config().payout_bps);
+        assert!(contract.get_pending_config_change().is_none());
+    }
+
+    #[test]
+    #[should_panic(expected = "Configuration gives the house a negative edge!")]
+    fn scheduled_config_change_validated_up_front() {
+        let context = get_context(vec![], false);
+        testing_env!(context);
+        let mut contract = SlotMachine::new(String::from("carol_near"));
+
+        contract.schedule_config_change(GameConfigUpdate { payout_bps: Some(30_000), ..Default::default() }, 1_000);
+    }
+
+    #[test]
+    #[should_panic(expected = "Invalid caller account!")]
+    fn empty_caller_rejected() {
+        let mut context = get_context(vec![], false);
+        context.signer_account_id = String::new();
+        testing_env!(context);
+        let mut contract = SlotMachine::new(String::from("carol_near"));
+
+        contract.deposit();
+    }
+
+    #[test]
+    fn tip_allowed_recipient() {
+        let context = get_context(vec![], false);
+        testing_env!(context);
+        let mut contract = SlotMachine::new(String::from("carol_near"));
+        contract.add_tip_destination(String::from("charity_near"));
+        contract.deposit();
+
+        contract.tip(String::from("charity_near"), U128::from(ONE_NEAR));
+        contract.tip(String::from("charity_near"), U128::from(ONE_NEAR));
+        assert_eq!(U128::from(DEPOSIT_AMOUNT - 2 * ONE_NEAR), contract.get_credits(String::from("bob_near")));
+        assert_eq!(U128::from(2 * ONE_NEAR), contract.get_credits(String::from("charity_near")));
+        assert_eq!(U128::from(2 * ONE_NEAR), contract.get_total_tips(String::from("charity_near")));
+        assert_eq!(DEPOSIT_AMOUNT, contract.total_player_credits);
+
+        let event: serde_json::Value = serde_json::from_str(get_logs().last().unwrap().strip_prefix("EVENT_JSON:").unwrap()).unwrap();
+        assert_eq!("tip", event["event"]);
+        assert_eq!("charity_near", event["data"]["recipient"]);
+    }
+
+    #[test]
+    #[should_panic(expected = "Recipient doesn't accept tips!")]
+    fn tip_disallowed_recipient() {
+        let context = get_context(vec![], false);
+        testing_env!(context);
+        let mut contract = SlotMachine::new(String::from("carol_near"));
+        contract.deposit();
+
+        contract.tip(String::from("dave_near"), U128::from(ONE_NEAR));
+    }
+
+    #[test]
+    fn consolation_is_rate_limited() {
+        let mut context = get_context(vec![], false);
+        context.block_timestamp = 0;
+        testing_env!(context.clone());
+        let mut contract = SlotMachine::new(String::from("carol_near"));
+        contract.set_config(GameConfigUpdate { consolation_amount: Some(U128::from(ONE_NEAR / 100)), ..Default::default() });
+        contract.deposit();
+        let bob = String::from("bob_near");
+        let max_winners = contract.config.max_winners_per_block;
+
+        // an account that has yet to play doesn't qualify
+        contract.config.max_winners_per_block = 0;
+        assert_eq!(
+            PlayOutcome::Rejected(String::from("Block winner cap reached, try again in the next block")),
+            contract.try_play(U128::from(ONE_NEAR))
+        );
+        assert_eq!(U128::from(DEPOSIT_AMOUNT), contract.get_credits(bob.clone()));
+
+        contract.config.max_winners_per_block = max_winners;
+        contract.forced_roll = Some(255);
+        without_deposit(&mut context);
+        contract.play(None, None);
+        let credits = DEPOSIT_AMOUNT - ONE_NEAR;
+
+        contract.config.max_winners_per_block = 0;
+        contract.try_play(U128::from(ONE_NEAR));
+        assert_eq!(U128::from(credits + ONE_NEAR / 100), contract.get_credits(bob.clone()));
+        contract.try_play(U128::from(ONE_NEAR));
+        assert_eq!(U128::from(credits + ONE_NEAR / 100), contract.get_credits(bob.clone()));
+
+        // neither the player's own mistakes nor an owner pause ever earn one
+        context.block_timestamp = NS_PER_DAY;
+        update_context(context);
+        contract.try_play(U128::from(100 * ONE_NEAR));
+        assert_eq!(U128::from(credits + ONE_NEAR / 100), contract.get_credits(bob.clone()));
+        contract.paused = true;
+        assert_eq!(PlayOutcome::Rejected(String::from("Game is paused!")), contract.try_play(U128::from(ONE_NEAR)));
+        assert_eq!(U128::from(credits + ONE_NEAR / 100), contract.get_credits(bob.clone()));
+
+        contract.paused = false;
+        contract.try_play(U128::from(ONE_NEAR));
+        assert_eq!(U128::from(credits + ONE_NEAR / 50), contract.get_credits(bob));
+    }
+
+    #[test]
+    fn dust_deposit_earns_no_consolation() {
+        let mut context = get_context(vec![], false);
+        testing_env!(context.clone());
+        let mut contract = SlotMachine::new(String::from("carol_near"));
+        contract.set_config(GameConfigUpdate { consolation_amount: Some(U128::from(ONE_NEAR / 100)), ..Default::default() });
+        context.signer_account_id = String::from("dave_near");
+        context.attached_deposit = 0;
+        update_context(context);
+        contract.deposit();
+        // funded and with a play behind it, but never by a real deposit
+        let dave = String::from("dave_near");
+        contract.add_credits(&dave, ONE_NEAR);
+        contract.stats.insert(&dave, &PlayerStats { total_plays: 1, ..Default::default() });
+
+        contract.config.max_winners_per_block = 0;
+        assert_eq!(
+            PlayOutcome::Rejected(String::from("Block winner cap reached, try again in the next block")),
+            contract.try_play(U128::from(ONE_NEAR))
+        );
+        assert_eq!(U128::from(ONE_NEAR), contract.get_credits(dave));
+    }
+
+    #[test]
+    fn odds_timeline() {
+        let mut context = get_context(vec![], false);
+        context.block_timestamp = 100;
+        testing_env!(context.clone());
+        let mut contract = SlotMachine::new(String::from("carol_near"));
+
+        context.block_timestamp = 200;
+        update_context(context.clone());
+        contract.set_config(GameConfigUpdate { odds: Some(120), ..Default::default() });
+        // changes that leave the odds alone aren't recorded
+        contract.set_config(GameConfigUpdate { fee_bps: Some(10), ..Default::default() });
+        context.block_timestamp = 300;
+        update_context(context);
+        contract.set_config(GameConfigUpdate { odds: Some(100), ..Default::default() });
+        assert_eq!(2, contract.odds_timeline.len());
+
+        assert_eq!(PROB, contract.odds_at(100));
+        assert_eq!(PROB, contract.odds_at(199));
+        assert_eq!(120, contract.odds_at(200));
+        assert_eq!(120, contract.odds_at(299));
+        assert_eq!(100, contract.odds_at(300));
+        assert_eq!(100, contract.odds_at(u64::MAX));
+    }
+
+    #[test]
+    fn odds_timeline_evicts_oldest() {
+        let mut context = get_context(vec![], false);
+        context.block_timestamp = 0;
+        testing_env!(context.clone());
+        let mut contract = SlotMachine::new(String::from("carol_near"));
+
+        for i in 1..=MAX_ODDS_TIMELINE {
+            context.block_timestamp = i;
+            update_context(context.clone());
+            contract.set_config(GameConfigUpdate { odds: Some(if i % 2 == 0 { PROB } else { 100 }), ..Default::default() });
+        }
+        context.block_timestamp += 1;
+        update_context(context);
+        contract.set_config(GameConfigUpdate { odds: Some(100), ..Default::default() });
+        assert_eq!(MAX_ODDS_TIMELINE, contract.odds_timeline.len());
+        assert_eq!((1, 100), contract.odds_since);
+        assert_eq!(100, contract.odds_at(1));
+        assert_eq!(PROB, contract.odds_at(2));
+        let evicted = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| contract.odds_at(0)));
+        assert!(evicted.is_err());
+    }
+
+    #[test]
+    #[should_panic(expected = "Daily win limit reached")]
+    fn trial_spins_respect_the_daily_win_limit() {
+        let mut context = get_context(vec![], false);
+        context.attached_deposit = 0;
+        testing_env!(context);
+        let mut contract = SlotMachine::new(String::from("carol_near"));
+        contract.set_config(GameConfigUpdate {
+            trial_spins: Some(2),
+            daily_win_limit: Some(U128::from(ONE_NEAR / 2)),
+            ..Default::default()
+        });
+
+        contract.play(None, None);
+    }
+
+    #[test]
+    fn trial_spins() {
+        let mut context = get_context(vec![], false);
+        context.attached_deposit = 0;
+        testing_env!(context);
+        let mut contract = SlotMachine::new(String::from("carol_near"));
+        contract.set_config(GameConfigUpdate { trial_spins: Some(2), bonus_wagering_multiplier: Some(2), ..Default::default() });
+        let bob = String::from("bob_near");
+        assert_eq!(2, contract.get_trials_remaining(bob.clone()));
+
+        // neither spin needs or costs credits; the win is locked as bonus
+        contract.forced_roll = Some(0);
+        contract.play(None, None);
+        contract.forced_roll = Some(255);
+        contract.play(None, None);
+        assert_eq!(0, contract.get_trials_remaining(bob.clone()));
+        assert_eq!(U128::from(0), contract.get_credits(bob.clone()));
+        assert_eq!(
+            Some(BonusCredits { amount: U128::from(2 * ONE_NEAR), wagering_remaining: U128::from(4 * ONE_NEAR) }),
+            contract.get_bonus_credits(bob.clone())
+        );
+        assert_eq!(2 * ONE_NEAR, contract.liabilities());
+
+        // real plays now, which wager the bonus free
+        contract.add_credits(&bob, 4 * ONE_NEAR);
+        for _ in 0..3 {
+            contract.forced_roll = Some(255);
+            contract.play(None, None);
+        }
+        assert_eq!(U128::from(ONE_NEAR), contract.get_bonus_credits(bob.clone()).unwrap().wagering_remaining);
+        contract.forced_roll = Some(255);
+        contract.play(None, None);
+        assert!(contract.get_bonus_credits(bob.clone()).is_none());
+        assert_eq!(U128::from(2 * ONE_NEAR), contract.get_credits(bob));
+    }
+
+    #[test]
+    fn idempotent_play() {
+        let mut context = get_context(vec![], false);
+        testing_env!(context.clone());
+        let mut contract = SlotMachine::new(String::from("carol_near"));
+        contract.deposit();
+        let bob = String::from("bob_near");
+
+        contract.forced_roll = Some(255);
+        without_deposit(&mut context);
+        assert_eq!(255, contract.play(None, Some(String::from("a"))));
+        // the retry returns the same roll without staking again
+        contract.forced_roll = Some(0);
+        assert_eq!(255, contract.play(None, Some(String::from("a"))));
+        assert_eq!(U128::from(DEPOSIT_AMOUNT - ONE_NEAR), contract.get_credits(bob.clone()));
+        assert_eq!(1, contract.stats.get(&bob).unwrap().total_plays);
+
+        assert_eq!(0, contract.play(None, Some(String::from("b"))));
+        assert_eq!(2, contract.stats.get(&bob).unwrap().total_plays);
+
+        // only the most recent keys are remembered
+        for key in 0..MAX_IDEMPOTENCY_KEYS {
+            contract.play(None, Some(key.to_string()));
+        }
+        contract.forced_roll = Some(0);
+        assert_eq!(0, contract.play(None, Some(String::from("a"))));
+    }
+
+    #[test]
+    fn savings_split() {
+        let context = get_context(vec![], false);
+        testing_env!(context);
+        let mut contract = SlotMachine::new(String::from("carol_near"));
+        let bob = String::from("bob_near");
+        contract.set_savings_split(2_500);
+        contract.deposit();
+        assert_eq!(U128::from(DEPOSIT_AMOUNT / 4), contract.get_savings(bob.clone()));
+        assert_eq!(U128::from(DEPOSIT_AMOUNT * 3 / 4), contract.get_credits(bob.clone()));
+        assert_eq!(DEPOSIT_AMOUNT, contract.liabilities());
+
+        let result = contract.withdraw_savings(U128::from(ONE_NEAR));
+        assert_eq!(U128::from(DEPOSIT_AMOUNT / 4 - ONE_NEAR), result.remaining);
+        assert_eq!(1, get_created_receipts().len());
+    }
+
+    #[test]
+    fn savings_cant_be_played() {
+        let mut context = get_context(vec![], false);
+        context.attached_deposit = 2 * ONE_NEAR;
+        testing_env!(context);
+        let mut contract = SlotMachine::new(String::from("carol_near"));
+        contract.set_config(GameConfigUpdate { max_bet: Some(U128::from(2 * ONE_NEAR)), ..Default::default() });
+        contract.set_savings_split(5_000);
+        contract.deposit();
+
+        // 2 NEAR deposited, but only the unsaved half can be staked
+        assert_eq!(
+            PlayOutcome::Rejected(format!("Insufficient credits: have {}, need {}", ONE_NEAR, 2 * ONE_NEAR)),
+            contract.try_play(U128::from(2 * ONE_NEAR))
+        );
+        assert!(matches!(contract.try_play(U128::from(ONE_NEAR)), PlayOutcome::Ok(_)));
+        assert_eq!(U128::from(ONE_NEAR), contract.get_savings(String::from("bob_near")));
+    }
+
+    #[test]
+    fn recurring_deposit() {
+        let mut context = get_context(vec![], false);
+        context.attached_deposit = 3 * ONE_NEAR;
+        context.block_timestamp = 1_000;
+        testing_env!(context.clone());
+        let mut contract = SlotMachine::new(String::from("carol_near"));
+        let bob = String::from("bob_near");
+        contract.set_recurring_deposit(U128::from(2 * ONE_NEAR), 500);
+        assert_eq!(3 * ONE_NEAR, contract.liabilities());
+
+        assert_eq!(U128::from(2 * ONE_NEAR), contract.execute_recurring(bob.clone()));
+        let recurring = contract.get_recurring_deposit(bob.clone()).unwrap();
+        assert_eq!((1_500, U128::from(ONE_NEAR)), (recurring.next_due, recurring.allowance));
+        assert_eq!(3 * ONE_NEAR, contract.liabilities());
+
+        // too early for the next one
+        context.block_timestamp = 1_499;
+        update_context(context.clone());
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| contract.execute_recurring(bob.clone())));
+        assert!(result.is_err());
+
+        // due, but the allowance no longer covers it until topped up
+        context.block_timestamp = 1_500;
+        update_context(context);
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| contract.execute_recurring(bob.clone())));
+        assert!(result.is_err());
+        contract.set_recurring_deposit(U128::from(2 * ONE_NEAR), 500);
+        assert_eq!(U128::from(4 * ONE_NEAR), contract.execute_recurring(bob));
+    }
+
+    #[test]
+    fn window_stats_roll_over_hourly_buckets() {
+        let mut context = get_context(vec![], false);
+        context.block_timestamp = 10 * NS_PER_HOUR + 1;
+        testing_env!(context.clone());
+        let mut contract = SlotMachine::new(String::from("carol_near"));
+        contract.deposit();
+        contract.forced_roll = Some(0);
+        without_deposit(&mut context);
+        contract.play(None, None);
+        contract.forced_roll = Some(255);
+        contract.play(None, None);
+
+        // the next hour gets a bucket of its own
+        context.block_timestamp = 11 * NS_PER_HOUR;
+        update_context(context.clone());
+        contract.forced_roll = Some(255);
+        contract.play(None, None);
+        let last_hour = contract.get_window_stats(NS_PER_HOUR);
+        assert_eq!((1, 0, U128::from(ONE_NEAR), U128::from(0)), (last_hour.plays, last_hour.wins, last_hour.wagered, last_hour.paid));
+        let stats = contract.get_window_stats(2 * NS_PER_HOUR);
+        assert_eq!((3, 1, U128::from(3 * ONE_NEAR), U128::from(2 * ONE_NEAR)), (stats.plays, stats.wins, stats.wagered, stats.paid));
+        assert_eq!(stats, contract.get_window_stats(NS_PER_DAY * 7));
+
+        // a day later the first hour's slot comes round again and its plays drop out
+        context.block_timestamp = 34 * NS_PER_HOUR;
+        update_context(context);
+        contract.forced_roll = Some(255);
+        contract.play(None, None);
+        let day = contract.get_window_stats(NS_PER_DAY);
+        assert_eq!((2, 0), (day.plays, day.wins));
+    }
+
+    #[test]
+    fn milestone_pool_pays_every_nth_play() {
+        let mut context = get_context(vec![], false);
+        testing_env!(context.clone());
+        let mut contract = SlotMachine::new(String::from("carol_near"));
+        contract.set_config(GameConfigUpdate {
+            milestone_interval: Some(3),
+            milestone_bonus: Some(U128::from(ONE_NEAR)),
+            ..Default::default()
+        });
+        contract.deposit();
+        let bob = String::from("bob_near");
+        context.attached_deposit = ONE_NEAR;
+        update_context(context.clone());
+        assert_eq!(U128::from(ONE_NEAR), contract.fund_milestone_pool());
+
+        contract.config.odds = 0;
+        without_deposit(&mut context);
+        contract.play(None, None);
+        contract.play(None, None);
+        assert_eq!(2, contract.get_milestone_progress().plays_since_milestone);
+        contract.play(None, None);
+        assert_eq!(U128::from(DEPOSIT_AMOUNT - 2 * ONE_NEAR), contract.get_credits(bob.clone()));
+        let progress = contract.get_milestone_progress();
+        assert_eq!((0, U128::from(0)), (progress.plays_since_milestone, progress.pool));
+
+        // the pool is empty now, so the next milestone passes without a bonus
+        for _ in 0..3 {
+            contract.play(None, None);
+        }
+        assert_eq!(U128::from(DEPOSIT_AMOUNT - 5 * ONE_NEAR), contract.get_credits(bob));
+        assert_eq!(0, contract.get_milestone_progress().plays_since_milestone);
+    }
+
+    #[test]
+    fn all_balances() {
+        let mut context = get_context(vec![], false);
+        testing_env!(context.clone());
+        let mut contract = SlotMachine::new(String::from("carol_near"));
+        let bob = String::from("bob_near");
+        let empty = contract.get_all_balances(bob.clone());
+        assert_eq!((U128::from(0), U128::from(0), U128::from(0)), (empty.credits, empty.savings, empty.lp_value));
+
+        contract.set_config(GameConfigUpdate { pull_payouts: Some(true), reward_points_bps: Some(1_000), ..Default::default() });
+        contract.set_savings_split(1_000);
+        contract.deposit();
+        contract.forced_roll = Some(0);
+        without_deposit(&mut context);
+        contract.play(None, None);
+        contract.stake_vault(U128::from(2 * ONE_NEAR), 100);
+        contract.create_match(U128::from(ONE_NEAR));
+
+        let balances = contract.get_all_balances(bob);
+        assert_eq!(U128::from(DEPOSIT_AMOUNT * 9 / 10 - 4 * ONE_NEAR), balances.credits);
+        assert_eq!(U128::from(2 * ONE_NEAR), balances.vault);
+        assert_eq!(U128::from(ONE_NEAR), balances.match_escrow);
+        assert_eq!(U128::from(2 * ONE_NEAR), balances.claimable);
+        assert_eq!(U128::from(DEPOSIT_AMOUNT / 10), balances.savings);
+        assert_eq!(U128::from(ONE_NEAR / 10), balances.reward_points);
+        assert_eq!((U128::from(0), U128::from(0)), (balances.bonus_credits, balances.lp_shares));
+    }
+
+    #[test]
+    fn breakeven_odds() {
+        let context = get_context(vec![], false);
+        testing_env!(context);
+        let mut contract = SlotMachine::new(String::from("carol_near"));
+        // a fair coin at 2x
+        assert_eq!(5_000, contract.breakeven_odds());
+
+        contract.set_config(GameConfigUpdate { payout_bps: Some(19_000), fee_bps: Some(100), ..Default::default() });
+        assert_eq!(5_315, contract.breakeven_odds());
+
+        contract.set_config(GameConfigUpdate { payout_bps: Some(20_000), fee_bps: Some(0), rebate_bps: Some(1_000), odds: Some(100), ..Default::default() });
+        assert_eq!(4_736, contract.breakeven_odds());
+    }
+
+    #[test]
+    fn plays_remaining() {
+        let context = get_context(vec![], false);
+        testing_env!(context);
+        let mut contract = SlotMachine::new(String::from("carol_near"));
+        let bob = String::from("bob_near");
+        contract.add_credits(&bob, 10 * ONE_NEAR);
+        assert_eq!(5, contract.plays_remaining(bob.clone(), U128::from(2 * ONE_NEAR)));
+        assert_eq!(3, contract.plays_remaining(bob.clone(), U128::from(3 * ONE_NEAR)));
+
+        // with a 1% fee each play costs 1.01 NEAR, so the tenth no longer fits
+        contract.set_config(GameConfigUpdate { fee_bps: Some(100), ..Default::default() });
+        assert_eq!(9, contract.plays_remaining(bob.clone(), U128::from(ONE_NEAR)));
+        contract.add_credits(&bob, ONE_NEAR / 10);
+        assert_eq!(10, contract.plays_remaining(bob, U128::from(ONE_NEAR)));
+    }
+
+    #[test]
+    fn reward_points_accrue_on_wagers() {
+        let mut context = get_context(vec![], false);
+        testing_env!(context.clone());
+        let mut contract = SlotMachine::new(String::from("carol_near"));
+        contract.set_config(GameConfigUpdate { reward_points_bps: Some(500), ..Default::default() });
+        contract.deposit();
+        let bob = String::from("bob_near");
+        let liabilities = contract.liabilities();
+
+        // a win and a loss earn the same
+        contract.forced_roll = Some(0);
+        without_deposit(&mut context);
+        contract.play(None, None);
+        assert_eq!(U128::from(ONE_NEAR / 20), contract.get_reward_points(bob.clone()));
+        contract.forced_roll = Some(255);
+        contract.play(None, None);
+        assert_eq!(U128::from(ONE_NEAR / 10), contract.get_reward_points(bob.clone()));
+        assert_eq!(liabilities, contract.liabilities());
+
+        // unless only wins earn points
+        contract.config.reward_points_wins_only = true;
+        contract.forced_roll = Some(255);
+        contract.play(None, None);
+        assert_eq!(U128::from(ONE_NEAR / 10), contract.get_reward_points(bob));
+    }
+
+    #[test]
+    fn lifetime_play_limit() {
+        let mut context = get_context(vec![], false);
+        testing_env!(context.clone());
+        let mut contract = SlotMachine::new(String::from("carol_near"));
+        contract.deposit();
+        let bob = String::from("bob_near");
+        assert_eq!(u64::MAX, contract.get_remaining_plays(bob.clone()));
+
+        contract.set_config(GameConfigUpdate { max_lifetime_plays: Some(3), ..Default::default() });
+        without_deposit(&mut context);
+        for remaining in (0..3).rev() {
+            contract.play(None, None);
+            assert_eq!(remaining, contract.get_remaining_plays(bob.clone()));
+        }
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| contract.play(None, None)));
+        assert!(result.is_err());
+        assert_eq!(3, contract.stats.get(&bob).unwrap().total_plays);
+
+        // raising the cap lets the account play again
+        contract.set_config(GameConfigUpdate { max_lifetime_plays: Some(4), ..Default::default() });
+        contract.play(None, None);
+    }
+
+    #[test]
+    #[should_panic(expected = "Lifetime play limit reached")]
+    fn play_past_lifetime_limit() {
+        let mut context = get_context(vec![], false);
+        testing_env!(context.clone());
+        let mut contract = SlotMachine::new(String::from("carol_near"));
+        contract.deposit();
+        contract.set_config(GameConfigUpdate { max_lifetime_plays: Some(1), ..Default::default() });
+
+        without_deposit(&mut context);
+        contract.play(None, None);
+        contract.play(None, None);
+    }
+
+    #[test]
+    fn contract_age() {
+        let mut context = get_context(vec![], false);
+        context.block_timestamp = 1_000;
+        testing_env!(context);
+        let contract = SlotMachine::new(String::from("carol_near"));
+        assert_eq!(0, contract.get_contract_age());
+
+        let mut context = get_context(vec![], false);
+        context.block_timestamp = 1_000 + NS_PER_YEAR;
+        update_context(context);
+        assert_eq!(NS_PER_YEAR, contract.get_contract_age());
+    }
+
+    #[test]
+    fn config_change_rate_limit() {
+        let mut context = get_context(vec![], false);
+        context.block_timestamp = 1_000;
+        testing_env!(context.clone());
+        let mut contract = SlotMachine::new(String::from("carol_near"));
+        contract.set_config(GameConfigUpdate { config_change_min_interval_ns: Some(500), ..Default::default() });
+
+        context.block_timestamp = 1_499;
+        update_context(context.clone());
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            contract.set_config(GameConfigUpdate { odds: Some(100), ..Default::default() })
+        }));
+        assert!(result.is_err());
+        assert!(std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| contract.set_vault_apr_bps(500))).is_err());
+
+        context.block_timestamp = 1_500;
+        update_context(context);
+        contract.set_config(GameConfigUpdate { odds: Some(100), ..Default::default() });
+        assert_eq!(100, contract.get_config().odds);
+    }
+
+    #[test]
+    fn config_lock() {
+        let context = get_context(vec![], false);
+        testing_env!(context);
+        let mut contract = SlotMachine::new(String::from("carol_near"));
+        let until = env::block_timestamp() + 1_000;
+        contract.lock_config(until);
+        assert_eq!(until, contract.get_config().config_locked_until);
+
+        assert!(std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| contract.set_config(GameConfigUpdate::default()))).is_err());
+        assert!(std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| contract.set_vault_apr_bps(500))).is_err());
+        assert!(std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| contract.lock_config(until - 1))).is_err());
+
+        let mut context = get_context(vec![], false);
+        context.block_timestamp = until;
+        update_context(context);
+        contract.set_config(GameConfigUpdate { fee_bps: Some(100), ..Default::default() });
+        contract.set_vault_apr_bps(500);
+        assert_eq!(100, contract.get_config().fee_bps);
+    }
+
+    #[test]
+    #[should_panic(expected = "Config is locked until 1000")]
+    fn set_config_while_locked() {
+        let mut context = get_context(vec![], false);
+        context.block_timestamp = 0;
+        testing_env!(context);
+        let mut contract = SlotMachine::new(String::from("carol_near"));
+        contract.lock_config(1_000);
+
+        contract.set_config(GameConfigUpdate { fee_bps: Some(100), ..Default::default() });
+    }
+
+    #[test]
+    fn forced_roll_on_testnet() {
+        let mut context = get_context(vec![], false);
+        testing_env!(context.clone());
+        let mut contract = SlotMachine::new_with_options(
+            String::from("carol_near"), GameConfig::default(), GameMetadata::default(), NetworkMode::Testnet
+        );
+        contract.deposit();
+
+        contract.set_forced_roll(200);
+        without_deposit(&mut context);
+        assert_eq!(200, contract.play(None, None));
+        contract.set_forced_roll(3);
+        assert_eq!(3, contract.play(None, None));
+        assert!(contract.forced_roll.is_none());
+    }
+
+    #[test]
+    #[should_panic(expected = "Only available on testnet!")]
+    fn forced_roll_on_mainnet() {
+        let context = get_context(vec![], false);
+        testing_env!(context);
+        let mut contract = SlotMachine::new(String::from("carol_near"));
+        assert_eq!(NetworkMode::Mainnet, contract.get_network());
+
+        contract.set_forced_roll(0);
+    }
+
+    #[test]
+    fn set_metadata() {
+        let context = get_context(vec![], false);
+        testing_env!(context);
+        let mut contract = SlotMachine::new(String::from("carol_near"));
+        assert_eq!(GameMetadata::default(), contract.get_metadata());
+
+        contract.set_name(String::from("Lucky Flip"));
+        contract.set_description(String::from("Double or nothing"));
+        assert_eq!(
+            GameMetadata { name: String::from("Lucky Flip"), description: String::from("Double or nothing") },
+            contract.get_metadata()
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Name and description can't contain control characters!")]
+    fn set_description_with_control_characters() {
+        let context = get_context(vec![], false);
+        testing_env!(context);
+        let mut contract = SlotMachine::new(String::from("carol_near"));
+
+        contract.set_description(String::from("line one\nline two"));
+    }
+
+    #[test]
+    #[should_panic(expected = "Name is too long!")]
+    fn set_name_too_long() {
+        let context = get_context(vec![], false);
+        testing_env!(context);
+        let mut contract = SlotMachine::new(String::from("carol_near"));
+
+        contract.set_name("x".repeat(MAX_NAME_LENGTH + 1));
+    }
+
+    #[test]
+    fn set_config() {
+        let context = get_context(vec![], false);
+        testing_env!(context);
+        let mut contract = SlotMachine::new(String::from("carol_near"));
+
+        contract.set_config(GameConfigUpdate {
+            odds: Some(120),
+            payout_bps: Some(20_000),
+            fee_bps: Some(100),
+            min_bet: Some(U128::from(ONE_NEAR / 10)),
+            max_bet: Some(U128::from(5 * ONE_NEAR)),
+            rounding: Some(RoundingMode::Nearest),
+            ..Default::default()
+        });
+
+        let config = contract.get_config();
+        assert_eq!(120, config.odds);
+        assert_eq!(20_000, config.payout_bps);
+        assert_eq!(100, config.fee_bps);
+        assert_eq!(U128::from(ONE_NEAR / 10), config.min_bet);
+        assert_eq!(U128::from(5 * ONE_NEAR), config.max_bet);
+        assert_eq!(RoundingMode::Nearest, config.rounding);
+    }
+
+    #[test]
+    fn set_config_rejects_jointly_invalid_changes() {
+        let context = get_context(vec![], false);
+        testing_env!(context);
+        let mut contract = SlotMachine::new(String::from("carol_near"));
+        contract.set_config(GameConfigUpdate {
+            payout_bps: Some(19_000),
+            ..Default::default()
+        });
+        let before = contract.get_config();
+
+        // either change alone keeps a non-negative edge, but not both together
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            contract.set_config(GameConfigUpdate {
+                odds: Some(134),
+                payout_bps: Some(19_900),
+                ..Default::default()
+            });
+        }));
+        assert!(result.is_err());
+        assert_eq!(before, contract.get_config());
+
+        contract.set_config(GameConfigUpdate { odds: Some(134), ..Default::default() });
+        contract.set_config(GameConfigUpdate { odds: Some(128), payout_bps: Some(19_900), ..Default::default() });
+    }
+
+    #[test]
+    fn play_with_stake_and_fee() {
+        let mut context = get_context(vec![], false);
+        testing_env!(context.clone());
+        let mut contract = SlotMachine::new(String::from("carol_near"));
+        contract.set_config(GameConfigUpdate {
+            payout_bps: Some(19_000),
+            fee_bps: Some(100),
+            max_bet: Some(U128::from(5 * ONE_NEAR)),
+            ..Default::default()
+        });
+        contract.deposit();
+
+        let stake = 2 * ONE_NEAR;
+        without_deposit(&mut context);
+        let number = contract.play(Some(U128::from(stake)), None);
+
+        let mut credits = DEPOSIT_AMOUNT - stake - stake / 100;
+        if number < 128 {
+            credits += stake * 19 / 10;
+        }
+        assert_eq!(U128::from(credits), contract.get_credits(String::from("bob_near")));
+        assert_eq!(stake / 100, contract.accumulated_fees);
+    }
+
+    #[test]
+    #[should_panic(expected = "Stake must be between")]
+    fn play_above_max_bet() {
+        let mut context = get_context(vec![], false);
+        testing_env!(context.clone());
+        let mut contract = SlotMachine::new(String::from("carol_near"));
+        contract.deposit();
+
+        without_deposit(&mut context);
+        contract.play(Some(U128::from(2 * ONE_NEAR)), None);
+    }
+
+    #[test]
+    fn create_match() {
+        let context = get_context(vec![], false);
+        testing_env!(context);
+        let mut contract = SlotMachine::new(String::from("carol_near"));
+        contract.deposit();
+
+        let match_id = contract.create_match(U128::from(4 * ONE_NEAR));
+
+        assert_eq!(U128::from(6 * ONE_NEAR), contract.get_credits(String::from("bob_near")));
+        let game = contract.get_match(match_id).unwrap();
+        assert_eq!(String::from("bob_near"), game.creator);
+        assert_eq!(U128::from(4 * ONE_NEAR), game.stake);
+    }
+
+    #[test]
+    fn join_match() {
+        let context = get_context(vec![], false);
+        testing_env!(context);
+        let mut contract = SlotMachine::new(String::from("carol_near"));
+        contract.set_config(GameConfigUpdate {
+            payout_bps: Some(19_000),
+            fee_bps: Some(500),
+            ..Default::default()
+        });
+        contract.deposit();
+        let match_id = contract.create_match(U128::from(4 * ONE_NEAR));
+
+        let mut context = get_context(vec![], false);
+        context.signer_account_id = "dave_near".to_string();
+        update_context(context);
+        contract.deposit();
+        let winner = contract.join_match(match_id);
+
+        // the 8 NEAR pot minus the 5% fee goes to the winner
+        let bob = contract.get_credits(String::from("bob_near")).0;
+        let dave = contract.get_credits(String::from("dave_near")).0;
+        if winner == "bob_near" {
+            assert_eq!((6 * ONE_NEAR + 76 * ONE_NEAR / 10, 6 * ONE_NEAR), (bob, dave));
+        } else {
+            assert_eq!(String::from("dave_near"), winner);
+            assert_eq!((6 * ONE_NEAR, 6 * ONE_NEAR + 76 * ONE_NEAR / 10), (bob, dave));
+        }
+        assert_eq!(4 * ONE_NEAR / 10, contract.accumulated_fees);
+        assert!(contract.get_match(match_id).is_none());
+    }
+
+    #[test]
+    fn cancel_match() {
+        let context = get_context(vec![], false);
+        testing_env!(context);
+        let mut contract = SlotMachine::new(String::from("carol_near"));
+        contract.deposit();
+        let match_id = contract.create_match(U128::from(4 * ONE_NEAR));
+
+        contract.cancel_match(match_id);
+
+        assert_eq!(U128::from(DEPOSIT_AMOUNT), contract.get_credits(String::from("bob_near")));
+        assert!(contract.get_match(match_id).is_none());
+    }
+
+    #[test]
+    fn get_limits_reflects_config() {
+        let context = get_context(vec![], false);
+        testing_env!(context);
+        let mut contract = SlotMachine::new(String::from("carol_near"));
+        contract.set_config(GameConfigUpdate {
+            max_bet: Some(U128::from(3 * ONE_NEAR)),
+            daily_win_limit: Some(U128::from(50 * ONE_NEAR)),
+            cooldown_ns: Some(1_000),
+            max_lifetime_plays: Some(100),
+            max_winners_per_block: Some(5),
+            ..Default::default()
+        });
+
+        let limits = contract.get_limits();
+        assert_eq!(U128::from(3 * ONE_NEAR), limits.max_bet);
+        assert_eq!(U128::from(6 * ONE_NEAR), limits.max_payout);
+        assert_eq!(U128::from(50 * ONE_NEAR), limits.daily_win_limit);
+        assert_eq!((1_000, 100, 5), (limits.cooldown_ns, limits.max_lifetime_plays, limits.max_winners_per_block));
+        assert_eq!(contract.get_config().min_bet, limits.min_bet);
+        assert_eq!(u64::MAX, limits.max_credit_accounts);
+    }
+
+    #[test]
+    fn rtp_for_known_settings() {
+        let context = get_context(vec![], false);
+        testing_env!(context);
+        let mut contract = SlotMachine::new(String::from("carol_near"));
+        // an even coin paying 2x with no fee returns everything
+        assert_eq!((10_000, 0), (contract.get_rtp_bps(), contract.get_house_edge_bps()));
+
+        for (payout_bps, fee_bps, rebate_bps, rtp) in [(19_000, 0, 0, 9_500), (20_000, 500, 0, 9_523), (18_000, 0, 1_000, 9_500)] {
+            contract.set_config(GameConfigUpdate {
+                payout_bps: Some(payout_bps),
+                fee_bps: Some(fee_bps),
+                rebate_bps: Some(rebate_bps),
+                ..Default::default()
+            });
+            assert_eq!(rtp, contract.get_rtp_bps());
+            assert_eq!(MAX_BPS, contract.get_rtp_bps() + contract.get_house_edge_bps());
+        }
+    }
+
+    #[test]
+    fn refund_timed_out_match() {
+        let mut context = get_context(vec![], false);
+        testing_env!(context.clone());
+        let mut contract = SlotMachine::new(String::from("carol_near"));
+        contract.set_config(GameConfigUpdate { match_timeout_ns: Some(NS_PER_DAY), ..Default::default() });
+        contract.deposit();
+        let match_id = contract.create_match(U128::from(4 * ONE_NEAR));
+
+        // anyone can refund it once the timeout has passed
+        context.signer_account_id = "keeper_near".to_string();
+        context.block_timestamp = NS_PER_DAY;
+        update_context(context);
+        assert_eq!(U128::from(4 * ONE_NEAR), contract.refund_match(match_id));
+
+        assert_eq!(U128::from(DEPOSIT_AMOUNT), contract.get_credits(String::from("bob_near")));
+        assert!(contract.get_match(match_id).is_none());
+        assert_eq!(DEPOSIT_AMOUNT, contract.liabilities());
+    }
+
+    #[test]
+    #[should_panic(expected = "Match can't be refunded before")]
+    fn refund_match_before_timeout() {
+        let mut context = get_context(vec![], false);
+        testing_env!(context.clone());
+        let mut contract = SlotMachine::new(String::from("carol_near"));
+        contract.set_config(GameConfigUpdate { match_timeout_ns: Some(NS_PER_DAY), ..Default::default() });
+        contract.deposit();
+        let match_id = contract.create_match(U128::from(4 * ONE_NEAR));
+
+        context.block_timestamp = NS_PER_DAY - 1;
+        update_context(context);
+        contract.refund_match(match_id);
+    }
+
+    #[test]
+    fn get_solvency() {
+        let mut context = get_context(vec![], false);
+        context.account_balance = 50 * ONE_NEAR;
+        testing_env!(context);
+        let mut contract = SlotMachine::new(String::from("carol_near"));
+        contract.deposit();
+        contract.create_match(U128::from(4 * ONE_NEAR));
+
+        let storage_cost = env::storage_usage() as u128 * env::storage_byte_cost();
+        assert!(storage_cost > 0);
+
+        // the mocked runtime already counts the attached deposit in the balance
+        let report = contract.get_solvency();
+        assert_eq!(U128::from(60 * ONE_NEAR), report.account_balance);
+        assert_eq!(U128::from(storage_cost), report.storage_cost);
+        assert_eq!(U128::from(6 * ONE_NEAR), report.total_player_credits);
+        assert_eq!(U128::from(4 * ONE_NEAR), report.total_locked_credits);
+        assert_eq!(I128::from((50 * ONE_NEAR - storage_cost) as i128), report.house_equity);
+        assert!(report.is_solvent);
+
+        let mut context = get_context(vec![], false);
+        context.account_balance = 5 * ONE_NEAR;
+        context.attached_deposit = 0;
+        update_context(context);
+        let report = contract.get_solvency();
+        assert_eq!(I128::from(-5 * ONE_NEAR as i128 - storage_cost as i128), report.house_equity);
+        assert!(!report.is_solvent);
+    }
+
+    #[test]
+    fn withdraw() {
+        let context = get_context(vec![], false);
+        testing_env!(context);
+        let mut contract = SlotMachine::new(String::from("carol_near"));
+        contract.deposit();
+
+        let result = contract.withdraw(U128::from(4 * ONE_NEAR));
+
+        assert_eq!(WithdrawResult { amount: U128::from(4 * ONE_NEAR), remaining: U128::from(6 * ONE_NEAR) }, result);
+        assert_eq!(U128::from(6 * ONE_NEAR), contract.get_credits(String::from("bob_near")));
+        assert_eq!(6 * ONE_NEAR, contract.total_player_credits);
+        assert_eq!(1, get_created_receipts().len());
+    }
+
+    #[test]
+    #[should_panic(expected = "Attach at least 20000000000000 gas")]
+    fn withdraw_with_too_little_gas() {
+        let context = get_context(vec![], false);
+        testing_env!(context);
+        let mut contract = SlotMachine::new(String::from("carol_near"));
+        contract.deposit();
+
+        let mut context = get_context(vec![], false);
+        context.prepaid_gas = contract.get_gas_requirements().withdraw - 1;
+        update_context(context);
+        contract.withdraw(U128::from(ONE_NEAR));
+    }
+
+    #[test]
+    #[should_panic(expected = "Attach at least 40000000000000 gas")]
+    fn process_payouts_with_too_little_gas() {
+        let context = get_context(vec![], false);
+        testing_env!(context);
+        let mut contract = SlotMachine::new(String::from("carol_near"));
+
+        // the base amount plus one callback for each of the two accounts
+        let mut context = get_context(vec![], false);
+        context.prepaid_gas = MIN_GAS_FOR_PAYOUTS + GAS_FOR_PAYOUT_CALLBACK;
+        update_context(context);
+        contract.process_payouts(vec![String::from("bob_near"), String::from("dave_near")]);
+    }
+
+    #[test]
+    #[should_panic(expected = "Temporarily insufficient contract liquidity")]
+    fn withdraw_with_low_liquidity() {
+        let context = get_context(vec![], false);
+        testing_env!(context);
+        let mut contract = SlotMachine::new(String::from("carol_near"));
+        contract.deposit();
+
+        let mut context = get_context(vec![], false);
+        context.account_balance = 3 * ONE_NEAR;
+        context.attached_deposit = 0;
+        update_context(context);
+        assert_eq!(U128::from(3 * ONE_NEAR - contract.storage_cost()), contract.available_liquidity());
+        contract.withdraw(U128::from(3 * ONE_NEAR));
+    }
+
+    #[test]
+    #[should_panic(expected = "Account is blacklisted!")]
+    fn blacklisted_cannot_play() {
+        let mut context = get_context(vec![], false);
+        testing_env!(context.clone());
+        let mut contract = SlotMachine::new(String::from("carol_near"));
+        contract.deposit();
+
+        contract.blacklist_account(String::from("bob_near"));
+        assert!(contract.is_blacklisted(String::from("bob_near")));
+        without_deposit(&mut context);
+        contract.play(None, None);
+    }
+
+    #[test]
+    fn blacklisted_can_withdraw() {
+        let context = get_context(vec![], false);
+        testing_env!(context);
+        let mut contract = SlotMachine::new(String::from("carol_near"));
+        contract.deposit();
+
+        contract.blacklist_account(String::from("bob_near"));
+        contract.withdraw(U128::from(DEPOSIT_AMOUNT));
+        assert_eq!(U128::from(0), contract.get_credits(String::from("bob_near")));
+
+        contract.unblacklist_account(String::from("bob_near"));
+        assert!(!contract.is_blacklisted(String::from("bob_near")));
+    }
+
+    #[test]
+    fn get_storage_usage() {
+        let context = get_context(vec![], false);
+        testing_env!(context);
+        let mut contract = SlotMachine::new(String::from("carol_near"));
+        let before = contract.get_storage_usage();
+
+        contract.deposit();
+        contract.create_match(U128::from(ONE_NEAR));
+
+        let info = contract.get_storage_usage();
+        assert!(info.storage_usage > before.storage_usage);
+        assert_eq!(U128::from(info.storage_usage as u128 * env::storage_byte_cost()), info.storage_cost);
+        assert_eq!((1, 0, 1, 0), (info.credit_entries, info.vault_entries, info.match_entries, info.blacklist_entries));
+    }
+
+    #[test]
+    fn donations_accrue_per_play_and_flush_to_the_charity() {
+        let mut context = get_context(vec![], false);
+        testing_env!(context.clone());
+        let mut contract = SlotMachine::new(String::from("carol_near"));
+        contract.set_config(GameConfigUpdate {
+            payout_bps: Some(19_000),
+            charity_bps: Some(100),
+            charity_account: Some(String::from("charity_near")),
+            ..Default::default()
+        });
+        contract.deposit();
+        contract.config.odds = 0;
+        without_deposit(&mut context);
+        contract.play(None, None);
+        contract.play(None, None);
+
+        // 1% of two 1 NEAR stakes, taken from the house and not the player
+        let donation = 2 * ONE_NEAR / 100;
+        assert_eq!(U128::from(donation), contract.get_charity_pool().pool);
+        assert_eq!(DEPOSIT_AMOUNT - 2 * ONE_NEAR, contract.get_credits(String::from("bob_near")).0);
+        assert_eq!((2 * ONE_NEAR - donation) as i128, contract.house_pnl);
+
+        let logged = get_logs().len();
+        assert_eq!(U128::from(donation), contract.donate_to_charity());
+        let event = get_logs().into_iter().skip(logged).find(|log| log.contains("charity_donation")).unwrap();
+        assert!(event.contains("charity_near"));
+        assert_eq!(
+            CharityPool {
+                charity_account: Some(String::from("charity_near")),
+                charity_bps: 100,
+                pool: U128::from(0),
+                total_donated: U128::from(donation),
+            },
+            contract.get_charity_pool()
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Configuration gives the house a negative edge!")]
+    fn donations_cant_exceed_the_house_edge() {
+        let context = get_context(vec![], false);
+        testing_env!(context);
+        let mut contract = SlotMachine::new(String::from("carol_near"));
+        // a 1.9x even coin leaves the house 5%, which a 5% donation uses up exactly
+        contract.set_config(GameConfigUpdate {
+            payout_bps: Some(19_000),
+            charity_bps: Some(500),
+            charity_account: Some(String::from("charity_near")),
+            ..Default::default()
+        });
+
+        contract.set_config(GameConfigUpdate { charity_bps: Some(501), ..Default::default() });
+    }
+
+    #[test]
+    #[should_panic(expected = "Donations need a charity account!")]
+    fn donations_need_a_charity_account() {
+        let context = get_context(vec![], false);
+        testing_env!(context);
+        let mut contract = SlotMachine::new(String::from("carol_near"));
+        contract.set_config(GameConfigUpdate { charity_bps: Some(100), ..Default::default() });
+    }
+
+    #[test]
+    #[should_panic(expected = "Credits are full at 1 accounts!")]
+    fn full_credits_reject_new_accounts() {
+        let context = get_context(vec![], false);
+        testing_env!(context);
+        let mut contract = SlotMachine::new(String::from("carol_near"));
+        contract.set_config(GameConfigUpdate { max_credit_accounts: Some(1), ..Default::default() });
+        contract.deposit();
+        // an account that's already in the map can keep depositing
+        contract.deposit();
+        assert_eq!(1, contract.get_credits_capacity().accounts);
+
+        let mut context = get_context(vec![], false);
+        context.signer_account_id = "dave_near".to_string();
+        update_context(context);
+        contract.deposit();
+    }
+
+    #[test]
+    #[should_panic(expected = "Credits are full at 1 accounts!")]
+    fn full_credits_reject_funded_accounts() {
+        let context = get_context(vec![], false);
+        testing_env!(context);
+        let mut contract = SlotMachine::new(String::from("carol_near"));
+        contract.set_config(GameConfigUpdate { max_credit_accounts: Some(1), ..Default::default() });
+        contract.fund_accounts(vec![(String::from("dave_near"), U128::from(ONE_NEAR))]);
+        assert!(contract.last_activity.get(&String::from("dave_near")).is_some());
+
+        contract.fund_accounts(vec![(String::from("erin_near"), U128::from(ONE_NEAR))]);
+    }
+
+    #[test]
+    #[should_panic(expected = "Config is locked until 1000")]
+    fn deposit_match_respects_config_lock() {
+        let context = get_context(vec![], false);
+        testing_env!(context);
+        let mut contract = SlotMachine::new(String::from("carol_near"));
+        contract.lock_config(1_000);
+
+        contract.set_deposit_match(500, 2_000);
+    }
+
+    #[test]
+    fn full_credits_evict_a_dust_account() {
+        let context = get_context(vec![], false);
+        testing_env!(context);
+        let mut contract = SlotMachine::new(String::from("carol_near"));
+        contract.set_config(GameConfigUpdate {
+            max_credit_accounts: Some(1),
+            credits_cap_policy: Some(CreditsCapPolicy::EvictDust),
+            eviction_dust_threshold: Some(U128::from(ONE_NEAR)),
+            ..Default::default()
+        });
+        contract.deposit();
+        let bob = String::from("bob_near");
+        contract.remove_credits(&bob, DEPOSIT_AMOUNT - ONE_NEAR / 2);
+
+        let mut context = get_context(vec![], false);
+        context.signer_account_id = "dave_near".to_string();
+        update_context(context);
+        let balance = env::account_balance();
+        contract.deposit();
+
+        // the dust goes back to the evicted account, not to the house
+        assert_eq!(balance - ONE_NEAR / 2, env::account_balance());
+        assert!(get_logs().iter().any(|log| log.contains("account_evicted")));
+        assert!(contract.credits.get(&bob).is_none());
+        assert_eq!(U128::from(DEPOSIT_AMOUNT), contract.get_credits(String::from("dave_near")));
+        assert_eq!(DEPOSIT_AMOUNT, contract.total_player_credits);
+        assert_eq!(
+            CreditsCapacity { accounts: 1, max_accounts: 1, policy: CreditsCapPolicy::EvictDust },
+            contract.get_credits_capacity()
+        );
+    }
+
+    fn dust_eviction_contract() -> SlotMachine {
+        let mut context = get_context(vec![], false);
+        testing_env!(context.clone());
+        let mut contract = SlotMachine::new(String::from("carol_near"));
+        contract.set_config(GameConfigUpdate {
+            max_credit_accounts: Some(1),
+            credits_cap_policy: Some(CreditsCapPolicy::EvictDust),
+            eviction_dust_threshold: Some(U128::from(ONE_NEAR)),
+            ..Default::default()
+        });
+        contract.deposit();
+        contract.forced_roll = Some(255);
+        without_deposit(&mut context);
+        contract.play(None, None);
+        let bob = String::from("bob_near");
+        let credits = contract.credits.get(&bob).unwrap();
+        contract.remove_credits(&bob, credits - ONE_NEAR / 2);
+        contract
+    }
+
+    #[test]
+    fn eviction_clears_the_account_records() {
+        let mut contract = dust_eviction_contract();
+        let bob = String::from("bob_near");
+        assert!(contract.stats.get(&bob).is_some() && contract.history.get(&bob).is_some());
+
+        let mut context = get_context(vec![], false);
+        context.signer_account_id = "dave_near".to_string();
+        update_context(context);
+        contract.deposit();
+
+        assert!(contract.credits.get(&bob).is_none());
+        assert!(contract.stats.get(&bob).is_none());
+        assert!(contract.history.get(&bob).is_none());
+        assert!(contract.last_rolls.get(&bob).is_none());
+        assert!(contract.deposit_history.get(&bob).is_none());
+        // kept, so coming back doesn't hand out the trial spins again
+        assert!(contract.first_seen.get(&bob).is_some());
+    }
+
+    #[test]
+    #[should_panic(expected = "Credits are full and no dust account can be evicted!")]
+    fn eviction_skips_accounts_with_other_funds() {
+        let mut contract = dust_eviction_contract();
+        let bob = String::from("bob_near");
+        contract.claimable.insert(&bob, &ONE_NEAR);
+        contract.total_claimable += ONE_NEAR;
+
+        let mut context = get_context(vec![], false);
+        context.signer_account_id = "dave_near".to_string();
+        update_context(context);
+        contract.deposit();
+    }
+
+    #[test]
+    fn health_assertions_pass_on_a_healthy_contract() {
+        let context = get_context(vec![], false);
+        testing_env!(context);
+        let mut contract = SlotMachine::new(String::from("carol_near"));
+        contract.deposit();
+
+        let checks = contract.health_assertions();
+        assert_eq!(
+            vec!["solvency", "total_player_credits", "house_edge", "config_lock"],
+            checks.iter().map(|(name, _)| name.as_str()).collect::<Vec<_>>()
+        );
+        assert!(checks.iter().all(|(_, passed)| *passed));
+    }
+
+    #[test]
+    fn health_assertions_flag_broken_invariants() {
+        let context = get_context(vec![], false);
+        testing_env!(context);
+        let mut contract = SlotMachine::new(String::from("carol_near"));
+        contract.deposit();
+        let failing = |contract: &SlotMachine| {
+            contract.health_assertions().into_iter().filter(|(_, passed)| !passed).map(|(name, _)| name).collect::<Vec<_>>()
+        };
+
+        contract.total_player_credits = DEPOSIT_AMOUNT + 1;
+        assert_eq!(vec!["total_player_credits"], failing(&contract));
+        contract.total_player_credits = DEPOSIT_AMOUNT;
+
+        contract.total_claimable = env::account_balance();
+        assert_eq!(vec!["solvency"], failing(&contract));
+        contract.total_claimable = 0;
+
+        contract.config.payout_bps = 30_000;
+        assert_eq!(vec!["house_edge"], failing(&contract));
+        contract.config.payout_bps = 20_000;
+
+        contract.config.config_locked_until = 10;
+        contract.pending_config = Some(PendingConfigChange { update: GameConfigUpdate::default(), effective_at: 5 });
+        assert_eq!(vec!["config_lock"], failing(&contract));
+    }
+
+    #[test]
+    fn storage_keys_keep_collections_apart() {
+        let context = get_context(vec![], false);
+        testing_env!(context);
+        let bob = String::from("bob_near");
+
+        let mut savings: UnorderedMap<AccountId, Balance> = UnorderedMap::new(StorageKey::Savings);
+        let mut split: UnorderedMap<AccountId, Balance> = UnorderedMap::new(StorageKey::SavingsSplitBps);
+        savings.insert(&bob, &1);
+        assert_eq!(None, split.get(&bob));
+        split.insert(&bob, &2);
+        assert_eq!(Some(1), savings.get(&bob));
+        assert_eq!(Some(2), split.get(&bob));
+    }
+
+    #[test]
+    fn play_pays_for_its_storage() {
+        let mut context = get_context(vec![], false);
+        testing_env!(context.clone());
+        let mut contract = SlotMachine::new(String::from("carol_near"));
+        contract.set_config(GameConfigUpdate { charge_play_storage: Some(true), ..Default::default() });
+        contract.deposit();
+        let bob = String::from("bob_near");
+
+        // the first play creates stats, history and proof entries for the account
+        let before = env::storage_usage();
+        contract.forced_roll = Some(255);
+        without_deposit(&mut context);
+        contract.play(None, None);
+        let added = (env::storage_usage() - before) as u128 * env::storage_byte_cost();
+        assert!(added > 0);
+        assert_eq!(U128::from(DEPOSIT_AMOUNT - ONE_NEAR - added), contract.get_credits(bob.clone()));
+
+        // off, the house covers it
+        contract.config.charge_play_storage = false;
+        contract.forced_roll = Some(255);
+        contract.play(None, None);
+        assert_eq!(U128::from(DEPOSIT_AMOUNT - 2 * ONE_NEAR - added), contract.get_credits(bob));
+    }
+
+    #[test]
+    fn batch_plays_pay_for_their_storage() {
+        let context = get_context(vec![], false);
+        testing_env!(context);
+        let mut contract = SlotMachine::new(String::from("carol_near"));
+        contract.set_config(GameConfigUpdate { charge_play_storage: Some(true), ..Default::default() });
+        contract.deposit();
+
+        let before = env::storage_usage();
+        contract.forced_roll = Some(255);
+        let result = contract.play_batch(2, None);
+        let added = (env::storage_usage() - before) as u128 * env::storage_byte_cost();
+        assert!(added > 0);
+        assert_eq!(U128::from(DEPOSIT_AMOUNT - 2 * ONE_NEAR - added), result.ending_balance);
+        assert_eq!(result.ending_balance, contract.get_credits(String::from("bob_near")));
+    }
+
+    #[test]
+    fn contract_health() {
+        let context = get_context(vec![], false);
+        testing_env!(context);
+        let mut contract = SlotMachine::new(String::from("carol_near"));
+        contract.deposit();
+
+        let health = contract.contract_health();
+        let solvency = contract.get_solvency();
+        assert_eq!(1, health.player_count);
+        assert_eq!(contract.get_storage_usage().storage_usage, health.storage_usage);
+        assert_eq!(U128::from(DEPOSIT_AMOUNT), health.liabilities);
+        assert_eq!(solvency.house_equity, health.house_equity);
+        assert!(health.is_solvent);
+    }
+
+    // plays a 1000 yocto stake at a 0.15% fee, i.e. a fee of exactly 1.5 yocto
+    fn play_with_rounding(rounding: RoundingMode) -> SlotMachine {
+        let mut context = get_context(vec![], false);
+        testing_env!(context.clone());
+        let mut contract = SlotMachine::new(String::from("carol_near"));
+        contract.set_config(GameConfigUpdate {
+            payout_bps: Some(19_999),
+            fee_bps: Some(15),
+            min_bet: Some(U128::from(1_000)),
+            rounding: Some(rounding),
+            ..Default::default()
+        });
+        contract.deposit();
+        without_deposit(&mut context);
+        contract.play(Some(U128::from(1_000)), None);
+        contract
+    }
+
+    #[test]
+    fn rounding_floor() {
+        let contract = play_with_rounding(RoundingMode::Floor);
+        assert_eq!(1, contract.accumulated_fees);
+        assert_eq!(5, contract.config.payout(3));
+        assert_eq!(1, contract.config.fee(933));
+    }
+
+    #[test]
+    fn rounding_ceil() {
+        let contract = play_with_rounding(RoundingMode::Ceil);
+        assert_eq!(2, contract.accumulated_fees);
+        assert_eq!(6, contract.config.payout(3));
+        assert_eq!(2, contract.config.fee(933));
+    }
+
+    #[test]
+    fn rounding_nearest() {
+        let contract = play_with_rounding(RoundingMode::Nearest);
+        assert_eq!(2, contract.accumulated_fees);
+        assert_eq!(6, contract.config.payout(3));
+        // 1.3995 rounds down
+        assert_eq!(1, contract.config.fee(933));
+    }
+
+    #[test]
+    fn export_state_in_chunks() {
+        let context = get_context(vec![], false);
+        testing_env!(context);
+        let mut contract = SlotMachine::new(String::from("carol_near"));
+        contract.deposit();
+        contract.withdraw(U128::from(ONE_NEAR));
+        contract.add_credits(&String::from("dave_near"), 2 * ONE_NEAR);
+        contract.add_credits(&String::from("erin_near"), 3 * ONE_NEAR);
+        contract.add_claimable(&String::from("erin_near"), ONE_NEAR);
+
+        let first = contract.export_state(0, 2);
+        let second = contract.export_state(2, 2);
+        assert_eq!(3, first.total_accounts);
+        assert_eq!(2, first.accounts.len());
+        assert_eq!(1, second.accounts.len());
+
+        let accounts: Vec<AccountExport> = first.accounts.into_iter().chain(second.accounts).collect();
+        let bob = accounts.iter().find(|account| account.account_id == "bob_near").unwrap();
+        assert_eq!(U128::from(DEPOSIT_AMOUNT - ONE_NEAR), bob.credits);
+        assert_eq!(U128::from(DEPOSIT_AMOUNT), bob.total_deposited);
+        assert_eq!(U128::from(ONE_NEAR), bob.total_withdrawn);
+        let erin = accounts.iter().find(|account| account.account_id == "erin_near").unwrap();
+        assert_eq!(U128::from(3 * ONE_NEAR), erin.credits);
+        assert_eq!(U128::from(ONE_NEAR), erin.claimable);
+
+        // the accounts' credits add up to the exported global total
+        let total: u128 = accounts.iter().map(|account| account.credits.0).sum();
+        assert_eq!(U128::from(total), contract.export_config().total_player_credits);
+        assert!(contract.export_state(3, 2).accounts.is_empty());
+    }
+
+    #[test]
+    fn recompute_total_credits() {
+        let context = get_context(vec![], false);
+        testing_env!(context);
+        let mut contract = SlotMachine::new(String::from("carol_near"));
+        contract.deposit();
+        contract.add_credits(&String::from("dave_near"), 2 * ONE_NEAR);
+        contract.add_credits(&String::from("erin_near"), 3 * ONE_NEAR);
+        contract.total_player_credits = 7;
+        contract.paused = true;
+
+        contract.recompute_total_credits(0, 2);
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| contract.finalize_total_credits()));
+        assert!(result.is_err());
+
+        // counting a page twice is rejected
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| contract.recompute_total_credits(0, 2)));
+        assert!(result.is_err());
+        contract.recompute_total_credits(2, 2);
+
+        assert_eq!(U128::from(DEPOSIT_AMOUNT + 5 * ONE_NEAR), contract.finalize_total_credits());
+        assert_eq!(DEPOSIT_AMOUNT + 5 * ONE_NEAR, contract.total_player_credits);
+        assert!(contract.credits_recount.is_none());
+    }
+
+    #[test]
+    #[should_panic(expected = "Pause the game before recounting credits!")]
+    fn recompute_total_credits_while_live() {
+        let context = get_context(vec![], false);
+        testing_env!(context);
+        let mut contract = SlotMachine::new(String::from("carol_near"));
+
+        contract.recompute_total_credits(0, 10);
+    }
+
+    #[test]
+    fn get_net_position() {
+        let context = get_context(vec![], false);
+        testing_env!(context);
+        let mut contract = SlotMachine::new(String::from("carol_near"));
+        contract.deposit();
+        contract.deposit();
+        contract.withdraw(U128::from(5 * ONE_NEAR));
+        contract.create_match(U128::from(3 * ONE_NEAR));
+
+        let position = contract.get_net_position(String::from("bob_near"));
+        assert_eq!(U128::from(2 * DEPOSIT_AMOUNT), position.total_deposited);
+        assert_eq!(U128::from(5 * ONE_NEAR), position.total_withdrawn);
+        assert_eq!(U128::from(12 * ONE_NEAR), position.credits);
+        assert_eq!(I128::from(-3 * ONE_NEAR as i128), position.net);
+
+        let empty = contract.get_net_position(String::from("dave_near"));
+        assert_eq!(I128::from(0), empty.net);
+    }
+
+    #[test]
+    fn get_pending_actions() {
+        let context = get_context(vec![], false);
+        testing_env!(context);
+        let mut contract = SlotMachine::new(String::from("carol_near"));
+        let bob = String::from("bob_near");
+        assert_eq!(
+            PendingActions { claimable: U128::from(0), claimable_since: None, vault: None, open_matches: vec![] },
+            contract.get_pending_actions(bob.clone())
+        );
+
+        contract.deposit();
+        let first = contract.create_match(U128::from(ONE_NEAR));
+        let second = contract.create_match(U128::from(ONE_NEAR));
+        contract.stake_vault(U128::from(2 * ONE_NEAR), NS_PER_YEAR);
+        contract.add_claimable(&bob, 3 * ONE_NEAR);
+
+        let pending = contract.get_pending_actions(bob);
+        assert_eq!(U128::from(3 * ONE_NEAR), pending.claimable);
+        assert!(pending.claimable_since.is_some());
+        assert_eq!(U128::from(2 * ONE_NEAR), pending.vault.unwrap().amount);
+        assert_eq!(vec![first, second], pending.open_matches);
+
+        // a match someone else created isn't this account's pending action
+        assert!(contract.get_pending_actions(String::from("dave_near")).open_matches.is_empty());
+    }
+
+    #[test]
+    fn liquidity_share_of_house_profit() {
+        let mut context = get_context(vec![], false);
+        testing_env!(context.clone());
+        let mut contract = SlotMachine::new(String::from("carol_near"));
+        contract.set_config(GameConfigUpdate { allow_liquidity: Some(true), ..Default::default() });
+        contract.deposit();
+        let bob = String::from("bob_near");
+
+        let pool_before = contract.pool_value();
+        let shares = contract.provide_liquidity(U128::from(5 * ONE_NEAR));
+        assert_eq!(shares, contract.get_lp_shares(bob.clone()));
+        assert_eq!(U128::from(pool_before), contract.get_lp_shares(String::from("carol_near")));
+        assert_eq!(U128::from(5 * ONE_NEAR), contract.get_credits(bob.clone()));
+
+        // the house wins a stake, so every share is worth a little more
+        contract.forced_roll = Some(255);
+        without_deposit(&mut context);
+        contract.play(None, None);
+        let redeemed = contract.remove_liquidity(shares);
+        assert!(redeemed.0 > 5 * ONE_NEAR, "redeemed {}", redeemed.0);
+        assert_eq!(U128::from(4 * ONE_NEAR + redeemed.0), contract.get_credits(bob.clone()));
+        assert_eq!(U128::from(0), contract.get_lp_shares(bob));
+    }
+
+    #[test]
+    #[should_panic(expected = "Liquidity provision is disabled!")]
+    fn liquidity_opt_in() {
+        let context = get_context(vec![], false);
+        testing_env!(context);
+        let mut contract = SlotMachine::new(String::from("carol_near"));
+        contract.deposit();
+
+        contract.provide_liquidity(U128::from(ONE_NEAR));
+    }
+
+    #[test]
+    fn expire_stale_credits() {
+        let mut context = get_context(vec![], false);
+        context.block_timestamp = NS_PER_DAY;
+        testing_env!(context.clone());
+        let mut contract = SlotMachine::new(String::from("carol_near"));
+        contract.set_config(GameConfigUpdate { credit_expiry_ns: Some(30 * NS_PER_DAY), ..Default::default() });
+        contract.deposit();
+        let bob = String::from("bob_near");
+
+        context.block_timestamp = 31 * NS_PER_DAY;
+        update_context(context);
+        assert_eq!(U128::from(DEPOSIT_AMOUNT), contract.expire_credits(bob.clone()));
+        assert_eq!(U128::from(0), contract.get_credits(bob));
+        assert_eq!(0, contract.total_player_credits);
+
+        let event: serde_json::Value = serde_json::from_str(get_logs()[0].strip_prefix("EVENT_JSON:").unwrap()).unwrap();
+        assert_eq!("credits_expired", event["event"]);
+        assert_eq!(NS_PER_DAY, event["data"]["last_activity"]);
+    }
+
+    #[test]
+    #[should_panic(expected = "Credits don't expire before")]
+    fn active_credits_dont_expire() {
+        let mut context = get_context(vec![], false);
+        testing_env!(context.clone());
+        let mut contract = SlotMachine::new(String::from("carol_near"));
+        contract.set_config(GameConfigUpdate { credit_expiry_ns: Some(30 * NS_PER_DAY), ..Default::default() });
+        contract.deposit();
+
+        // a play a few weeks in keeps the balance alive
+        context.block_timestamp = 20 * NS_PER_DAY;
+        update_context(context.clone());
+        without_deposit(&mut context);
+        contract.play(None, None);
+        context.block_timestamp = 31 * NS_PER_DAY;
+        update_context(context);
+        contract.expire_credits(String::from("bob_near"));
+    }
+
+    #[test]
+    fn adjust_credits() {
+        let context = get_context(vec![], false);
+        testing_env!(context);
+        let mut contract = SlotMachine::new(String::from("carol_near"));
+        contract.deposit();
+
+        let mut context = get_context(vec![], false);
+        context.attached_deposit = 1;
+        update_context(context);
+        contract.adjust_credits(String::from("bob_near"), U128::from(3 * ONE_NEAR), String::from("refund double charge"));
+
+        assert_eq!(U128::from(3 * ONE_NEAR), contract.get_credits(String::from("bob_near")));
+        assert_eq!(3 * ONE_NEAR, contract.total_player_credits);
+
+        contract.adjust_credits(String::from("dave_near"), U128::from(ONE_NEAR), String::from("failed transfer"));
+        assert_eq!(4 * ONE_NEAR, contract.total_player_credits);
+
+        let logs = get_logs();
+        assert_eq!(2, logs.len());
+        let event: serde_json::Value = serde_json::from_str(logs[0].strip_prefix("EVENT_JSON:").unwrap()).unwrap();
+        assert_eq!("admin_adjustment", event["event"]);
+        assert_eq!("bob_near", event["data"]["account_id"]);
+        assert_eq!("10000000000000000000000000", event["data"]["old_balance"]);
+        assert_eq!("3000000000000000000000000", event["data"]["new_balance"]);
+        assert_eq!("refund double charge", event["data"]["reason"]);
+    }
+
+    #[test]
+    #[should_panic(expected = "Requires attached deposit of exactly 1 yoctoNEAR")]
+    fn adjust_credits_requires_one_yocto() {
+        let context = get_context(vec![], false);
+        testing_env!(context);
+        let mut contract = SlotMachine::new(String::from("carol_near"));
+
+        contract.adjust_credits(String::from("bob_near"), U128::from(ONE_NEAR), String::from("test"));
+    }
+
+    #[test]
+    #[should_panic(expected = "Only the owner can call this method!")]
+    fn adjust_credits_owner_only() {
+        let mut context = get_context(vec![], false);
+        context.attached_deposit = 1;
+        context.predecessor_account_id = "bob_near".to_string();
+        testing_env!(context);
+        let mut contract = SlotMachine::new(String::from("carol_near"));
+
+        contract.adjust_credits(String::from("bob_near"), U128::from(ONE_NEAR), String::from("test"));
+    }
+
+    fn signer_context(signer: &str) -> VMContext {
+        let mut context = get_context(vec![], false);
+        context.attached_deposit = 1;
+        context.predecessor_account_id = signer.to_string();
+        context
+    }
+
+    fn adjust_bob(new_balance: Balance) -> OwnerAction {
+        OwnerAction::AdjustCredits {
+            account_id: String::from("bob_near"),
+            new_balance: U128::from(new_balance),
+            reason: String::from("refund double charge"),
+        }
+    }
+
+    #[test]
+    fn proposal_reaches_threshold() {
+        testing_env!(signer_context("carol_near"));
+        let mut contract = SlotMachine::new(String::from("carol_near"));
+        contract.set_signers(vec![String::from("carol_near"), String::from("dave_near"), String::from("eve_near")], 2);
+
+        let id = contract.propose_action(adjust_bob(3 * ONE_NEAR));
+        assert_eq!(U128::from(0), contract.get_credits(String::from("bob_near")));
+        assert_eq!(vec![String::from("carol_near")], contract.get_proposal(id).unwrap().approvals);
+
+        update_context(signer_context("eve_near"));
+        assert!(contract.approve_action(id));
+        assert_eq!(U128::from(3 * ONE_NEAR), contract.get_credits(String::from("bob_near")));
+        assert!(contract.get_proposal(id).is_none());
+
+        // the owner can no longer act alone
+        update_context(signer_context("carol_near"));
+        let direct = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            contract.adjust_credits(String::from("bob_near"), U128::from(0), String::from("test"));
+        }));
+        assert!(direct.is_err());
+        assert_eq!(U128::from(3 * ONE_NEAR), contract.get_credits(String::from("bob_near")));
+    }
+
+    #[test]
+    fn proposal_below_threshold() {
+        testing_env!(signer_context("carol_near"));
+        let mut contract = SlotMachine::new(String::from("carol_near"));
+        contract.set_signers(vec![String::from("carol_near"), String::from("dave_near"), String::from("eve_near")], 3);
+
+        update_context(signer_context("dave_near"));
+        let id = contract.propose_action(adjust_bob(3 * ONE_NEAR));
+        update_context(signer_context("eve_near"));
+        assert!(!contract.approve_action(id));
+        assert_eq!(2, contract.get_proposal(id).unwrap().approvals.len());
+        assert_eq!(U128::from(0), contract.get_credits(String::from("bob_near")));
+
+        // approving twice doesn't count twice
+        let again = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| contract.approve_action(id)));
+        assert!(again.is_err());
+        assert_eq!(U128::from(0), contract.get_credits(String::from("bob_near")));
+    }
+
+    #[test]
+    #[should_panic(expected = "Only a signer can call this method!")]
+    fn proposal_signers_only() {
+        testing_env!(signer_context("carol_near"));
+        let mut contract = SlotMachine::new(String::from("carol_near"));
+        contract.set_signers(vec![String::from("dave_near"), String::from("eve_near")], 2);
+
+        contract.propose_action(adjust_bob(ONE_NEAR));
+    }
+
+    #[test]
+    fn odds_decay() {
+        let context = get_context(vec![], false);
+        testing_env!(context);
+        let mut contract = SlotMachine::new(String::from("carol_near"));
+        contract.set_config(GameConfigUpdate {
+            payout_bps: Some(19_000),
+            odds_decay_per_win: Some(10),
+            ..Default::default()
+        });
+        let bob = String::from("bob_near");
+        assert_eq!(128, contract.get_effective_odds(bob.clone()));
+
+        contract.stats.insert(&bob, &PlayerStats { win_streak: 3, ..Default::default() });
+        assert_eq!(98, contract.get_effective_odds(bob.clone()));
+
+        // a long streak bottoms out at the floor rather than making winning impossible
+        contract.stats.insert(&bob, &PlayerStats { win_streak: 1_000, ..Default::default() });
+        assert_eq!(MIN_EFFECTIVE_ODDS, contract.get_effective_odds(bob));
+    }
+
+    #[test]
+    fn comeback_after_loss_streak() {
+        let mut context = get_context(vec![], false);
+        testing_env!(context.clone());
+        let mut contract = SlotMachine::new(String::from("carol_near"));
+        contract.set_config(GameConfigUpdate {
+            odds: Some(100),
+            payout_bps: Some(19_000),
+            comeback_after_losses: Some(3),
+            comeback_bonus: Some(20),
+            ..Default::default()
+        });
+        contract.deposit();
+        let bob = String::from("bob_near");
+
+        for losses in 1..=3 {
+            contract.forced_roll = Some(255);
+            without_deposit(&mut context);
+            contract.play(None, None);
+            assert_eq!(losses, contract.get_loss_streak(bob.clone()));
+        }
+        assert_eq!(120, contract.get_effective_odds(bob.clone()));
+
+        // losing the comeback spin starts the streak over
+        contract.forced_roll = Some(255);
+        contract.play(None, None);
+        assert_eq!(0, contract.get_loss_streak(bob.clone()));
+        assert_eq!(100, contract.get_effective_odds(bob.clone()));
+
+        // so does a win
+        contract.forced_roll = Some(255);
+        contract.play(None, None);
+        contract.forced_roll = Some(0);
+        contract.play(None, None);
+        assert_eq!(0, contract.get_loss_streak(bob));
+    }
+
+    #[test]
+    #[should_panic(expected = "Configuration gives the house a negative edge!")]
+    fn comeback_bonus_counts_towards_house_edge() {
+        let context = get_context(vec![], false);
+        testing_env!(context);
+        let mut contract = SlotMachine::new(String::from("carol_near"));
+        // 128 odds at 1.9x is fine on its own, but not with 20 extra winning rolls
+        contract.set_config(GameConfigUpdate {
+            payout_bps: Some(19_000),
+            comeback_after_losses: Some(5),
+            comeback_bonus: Some(20),
+            ..Default::default()
+        });
+    }
+
+    #[test]
+    fn win_streak_resets_on_loss() {
+        let mut context = get_context(vec![], false);
+        testing_env!(context.clone());
+        let mut contract = SlotMachine::new(String::from("carol_near"));
+        contract.set_config(GameConfigUpdate {
+            payout_bps: Some(19_000),
+            odds_decay_per_win: Some(10),
+            ..Default::default()
+        });
+        contract.deposit();
+        let bob = String::from("bob_near");
+        contract.stats.insert(&bob, &PlayerStats { win_streak: 2, ..Default::default() });
+
+        without_deposit(&mut context);
+        let number = contract.play(None, None);
+
+        let streak = contract.stats.get(&bob).unwrap().win_streak;
+        if number < 108 {
+            assert_eq!(3, streak);
+            assert_eq!(98, contract.get_effective_odds(bob));
+        } else {
+            assert_eq!(0, streak);
+            assert_eq!(128, contract.get_effective_odds(bob));
+        }
+    }
+
+    #[test]
+    fn play_proof() {
+        let mut context = get_context(vec![], false);
+        context.random_seed = (0..32).collect();
+        testing_env!(context.clone());
+        let mut contract = SlotMachine::new(String::from("carol_near"));
+        contract.deposit();
+        assert!(contract.get_play_proof(String::from("bob_near")).is_none());
+
+        without_deposit(&mut context);
+        contract.play(None, None);
+        let number = contract.play(None, None);
+
+        let proof = contract.get_play_proof(String::from("bob_near")).unwrap();
+        assert_eq!((0..32).collect::<Vec<u8>>(), Vec::<u8>::from(proof.random_seed.clone()));
+        assert_eq!(1, proof.nonce);
+        assert_eq!(number, proof.roll);
+
+        let mut input = Vec::<u8>::from(proof.random_seed);
+        input.extend_from_slice(&Vec::<u8>::from(proof.entropy_pool));
+        input.extend_from_slice(proof.account_id.as_bytes());
+        input.extend_from_slice(&proof.nonce.to_le_bytes());
+        assert_eq!(proof.roll, env::sha256(&input)[0]);
+    }
+
+    // Recomputes a proof's roll for an account without a client seed
+    fn recompute_roll(proof: &PlayProof) -> u8 {
+        let mut input = Vec::<u8>::from(proof.random_seed.clone());
+        input.extend_from_slice(&Vec::<u8>::from(proof.entropy_pool.clone()));
+        input.extend_from_slice(proof.account_id.as_bytes());
+        input.extend_from_slice(&proof.nonce.to_le_bytes());
+        env::sha256(&input)[0]
+    }
+
+    #[test]
+    fn keeper_resolved_proof_matches_the_player() {
+        let mut context = get_context(vec![], false);
+        context.random_seed = (0..32).collect();
+        testing_env!(context.clone());
+        let mut contract = SlotMachine::new(String::from("carol_near"));
+        contract.set_config(GameConfigUpdate { delayed_resolution: Some(true), ..Default::default() });
+        contract.deposit();
+        without_deposit(&mut context);
+        contract.play(None, None);
+
+        context.signer_account_id = "keeper_near".to_string();
+        context.block_index += 1;
+        update_context(context);
+        let result = contract.resolve_for(String::from("bob_near"));
+
+        let proof = contract.get_play_proof(String::from("bob_near")).unwrap();
+        assert_eq!(String::from("bob_near"), proof.account_id);
+        assert_eq!(result.roll, proof.roll);
+        assert_eq!(proof.roll, recompute_roll(&proof));
+    }
+
+    #[test]
+    fn delegated_play_proof_matches_the_owner() {
+        let mut context = get_context(vec![], false);
+        context.random_seed = (0..32).collect();
+        testing_env!(context.clone());
+        let mut contract = SlotMachine::new(String::from("carol_near"));
+        contract.deposit();
+        contract.set_client_seed(String::from("bob's seed"));
+        contract.approve_player(String::from("dave_near"), U128::from(5 * ONE_NEAR));
+
+        context.signer_account_id = "dave_near".to_string();
+        context.predecessor_account_id = "dave_near".to_string();
+        update_context(context.clone());
+        let result = contract.play_for(String::from("bob_near"), U128::from(ONE_NEAR));
+        let proof = contract.get_play_proof(String::from("bob_near")).unwrap();
+        assert_eq!(String::from("bob_near"), proof.account_id);
+        assert_eq!((Some(String::from("bob's seed")), Some(0)), (proof.client_seed.clone(), proof.seed_nonce));
+        assert_eq!(1, contract.get_current_nonce(String::from("bob_near")));
+
+        // rotating reveals the server seed the owner's play was rolled with
+        context.signer_account_id = "bob_near".to_string();
+        update_context(context);
+        let rotation = contract.set_client_seed(String::from("next seed"));
+        let mut input = Vec::<u8>::from(proof.random_seed);
+        input.extend_from_slice(&Vec::<u8>::from(proof.entropy_pool));
+        input.extend_from_slice(proof.account_id.as_bytes());
+        input.extend_from_slice(&proof.nonce.to_le_bytes());
+        input.extend_from_slice(&Vec::<u8>::from(rotation.previous_server_seed.unwrap()));
+        input.extend_from_slice(proof.client_seed.unwrap().as_bytes());
+        input.extend_from_slice(&proof.seed_nonce.unwrap().to_le_bytes());
+        assert_eq!(result.roll, env::sha256(&input)[0]);
+    }
+
+    #[test]
+    fn client_seed_rotation() {
+        let mut context = get_context(vec![], false);
+        context.random_seed = (0..32).collect();
+        testing_env!(context.clone());
+        let mut contract = SlotMachine::new(String::from("carol_near"));
+        contract.deposit();
+        let bob = String::from("bob_near");
+
+        let first = contract.set_client_seed(String::from("lucky"));
+        assert_eq!((None, 0), (first.previous_server_seed, first.previous_nonce));
+        assert_eq!(Some(first.server_seed_hash.clone()), contract.get_server_seed_hash(bob.clone()));
+        without_deposit(&mut context);
+        contract.play(None, None);
+        let number = contract.play(None, None);
+        assert_eq!(2, contract.get_current_nonce(bob.clone()));
+        let proof = contract.get_play_proof(bob.clone()).unwrap();
+        assert_eq!((Some(String::from("lucky")), Some(1)), (proof.client_seed.clone(), proof.seed_nonce));
+
+        // rotating reveals the server seed, which recomputes the last roll
+        let second = contract.set_client_seed(String::from("luckier"));
+        assert_eq!((Some(String::from("lucky")), 2), (second.previous_client_seed, second.previous_nonce));
+        let server_seed = Vec::<u8>::from(second.previous_server_seed.unwrap());
+        assert_eq!(Vec::<u8>::from(first.server_seed_hash), env::sha256(&server_seed));
+        assert_eq!(0, contract.get_current_nonce(bob));
+
+        let mut input = Vec::<u8>::from(proof.random_seed);
+        input.extend_from_slice(&Vec::<u8>::from(proof.entropy_pool));
+        input.extend_from_slice(proof.account_id.as_bytes());
+        input.extend_from_slice(&proof.nonce.to_le_bytes());
+        input.extend_from_slice(&server_seed);
+        input.extend_from_slice(b"lucky");
+        input.extend_from_slice(&1u64.to_le_bytes());
+        assert_eq!(number, env::sha256(&input)[0]);
+    }
+
+    #[test]
+    fn last_roll_detail() {
+        let mut context = get_context(vec![], false);
+        context.random_seed = (0..32).collect();
+        testing_env!(context.clone());
+        let mut contract = SlotMachine::new(String::from("carol_near"));
+        contract.deposit();
+        let bob = String::from("bob_near");
+        assert!(contract.get_last_roll_detail(bob.clone()).is_none());
+
+        contract.forced_roll = Some(0);
+        without_deposit(&mut context);
+        contract.play(None, None);
+        let detail = contract.get_last_roll_detail(bob.clone()).unwrap();
+        assert_eq!(env::sha256(&(0..32).collect::<Vec<u8>>()), Vec::<u8>::from(detail.seed_hash));
+        assert_eq!((0, 0, true), (detail.nonce, detail.roll, detail.won));
+        assert_eq!(contract.effective_odds(&bob), detail.threshold);
+
+        contract.forced_roll = Some(255);
+        contract.play(None, None);
+        let detail = contract.get_last_roll_detail(bob).unwrap();
+        assert_eq!((1, 255, false), (detail.nonce, detail.roll, detail.won));
+        assert!(detail.roll >= detail.win_low + detail.threshold);
+    }
+
+    #[test]
+    #[should_panic(expected = "Fees already collected, can't undo!")]
+    fn undo_after_fees_are_collected() {
+        let mut context = get_context(vec![], false);
+        testing_env!(context.clone());
+        let config = GameConfig { undo_window_ns: 100, fee_bps: 100, ..Default::default() };
+        let mut contract = SlotMachine::new_with_options(String::from("carol_near"), config, GameMetadata::default(), NetworkMode::Testnet);
+        contract.deposit();
+
+        without_deposit(&mut context);
+        contract.play(None, None);
+        contract.collect_fees();
+        contract.undo_last_play();
+    }
+
+    #[test]
+    fn undo_last_play_on_testnet() {
+        let mut context = get_context(vec![], false);
+        context.block_timestamp = 1_000;
+        testing_env!(context.clone());
+        let config = GameConfig { undo_window_ns: 100, milestone_interval: 5, ..Default::default() };
+        let mut contract = SlotMachine::new_with_options(String::from("carol_near"), config, GameMetadata::default(), NetworkMode::Testnet);
+        contract.deposit();
+        let bob = String::from("bob_near");
+        let credits = contract.get_credits(bob.clone());
+        contract.stats.insert(&bob, &PlayerStats { loss_streak: 2, ..Default::default() });
+
+        contract.forced_roll = Some(0);
+        without_deposit(&mut context);
+        contract.play(None, None);
+        assert!(contract.get_last_roll_detail(bob.clone()).unwrap().won);
+        context.block_timestamp = 1_100;
+        update_context(context.clone());
+        contract.undo_last_play();
+        assert_eq!(credits, contract.get_credits(bob.clone()));
+        assert_eq!(0, contract.accumulated_fees);
+        assert_eq!(0, contract.stats.get(&bob).unwrap().total_plays);
+        assert!(contract.get_player_history(bob.clone(), 10).is_empty());
+        // nor does it count towards streaks, caps, the window or the next milestone
+        assert_eq!((0, 2), (contract.stats.get(&bob).unwrap().win_streak, contract.stats.get(&bob).unwrap().loss_streak));
+        assert_eq!(0, contract.winners_this_block());
+        assert_eq!(0, contract.paid_out_this_block());
+        assert_eq!(0, contract.get_window_stats(NS_PER_HOUR).plays);
+        assert_eq!(0, contract.plays_since_milestone);
+
+        // only once per play
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| contract.undo_last_play()));
+        assert!(result.is_err());
+
+        // and only within the window
+        contract.forced_roll = Some(255);
+        contract.play(None, None);
+        context.block_timestamp = 1_201;
+        update_context(context);
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| contract.undo_last_play()));
+        assert!(result.is_err());
+    }
+
+    #[test]
+    #[should_panic(expected = "Only available on testnet!")]
+    fn undo_last_play_needs_testnet() {
+        let mut context = get_context(vec![], false);
+        testing_env!(context.clone());
+        let mut contract = SlotMachine::new(String::from("carol_near"));
+        contract.set_config(GameConfigUpdate { undo_window_ns: Some(100), ..Default::default() });
+        contract.deposit();
+        without_deposit(&mut context);
+        contract.play(None, None);
+        contract.undo_last_play();
+    }
+
+    #[test]
+    fn loss_rebate() {
+        let mut context = get_context(vec![], false);
+        testing_env!(context.clone());
+        let mut contract = SlotMachine::new(String::from("carol_near"));
+
+        // pick odds just below the upcoming roll so the play is a loss
+        let roll = contract.derive_roll(&String::from("bob_near"), contract.nonce);
+        assert!(roll > 0);
+        contract.set_config(GameConfigUpdate {
+            odds: Some(roll),
+            payout_bps: Some(11_000),
+            rebate_bps: Some(1_000),
+            ..Default::default()
+        });
+        contract.deposit();
+
+        without_deposit(&mut context);
+        assert_eq!(roll, contract.play(None, None));
+        assert_eq!(U128::from(DEPOSIT_AMOUNT - ONE_NEAR + ONE_NEAR / 10), contract.get_credits(String::from("bob_near")));
+        assert_eq!(1_000, contract.get_config().rebate_bps);
+    }
+
+    #[test]
+    #[should_panic(expected = "Configuration gives the house a negative edge!")]
+    fn rebate_counts_towards_house_edge() {
+        let context = get_context(vec![], false);
+        testing_env!(context);
+        let mut contract = SlotMachine::new(String::from("carol_near"));
+
+        // even odds at 2x are break-even, so any rebate tips the edge negative
+        contract.set_config(GameConfigUpdate { rebate_bps: Some(1), ..Default::default() });
+    }
+
+    #[test]
+    fn get_top_holders() {
+        let context = get_context(vec![], false);
+        testing_env!(context);
+        let mut contract = SlotMachine::new(String::from("carol_near"));
+        for (account_id, credits) in [("a_near", 5), ("b_near", 9), ("c_near", 5), ("d_near", 1), ("e_near", 0)] {
+            contract.add_credits(&account_id.to_string(), credits * ONE_NEAR);
+        }
+
+        let holders = contract.get_top_holders(3);
+        assert_eq!(vec![
+            (String::from("b_near"), U128::from(9 * ONE_NEAR)),
+            (String::from("a_near"), U128::from(5 * ONE_NEAR)),
+            (String::from("c_near"), U128::from(5 * ONE_NEAR)),
+        ], holders);
+
+        // empty balances are left out
+        assert_eq!(4, contract.get_top_holders(10).len());
+    }
+
+    #[test]
+    fn vip_tiers() {
+        let mut context = get_context(vec![], false);
+        testing_env!(context.clone());
+        let mut contract = SlotMachine::new(String::from("carol_near"));
+        contract.set_config(GameConfigUpdate {
+            payout_bps: Some(18_000),
+            vip_tiers: Some(vec![(U128::from(10 * ONE_NEAR), 5), (U128::from(50 * ONE_NEAR), 10)]),
+            ..Default::default()
+        });
+        let bob = String::from("bob_near");
+
+        for (wagered, tier, odds) in [(0, 0, 128), (9, 0, 128), (10, 1, 133), (49, 1, 133), (50, 2, 138)] {
+            contract.stats.insert(&bob, &PlayerStats { total_wagered: wagered * ONE_NEAR, ..Default::default() });
+            assert_eq!(tier, contract.get_vip_tier(bob.clone()));
+            assert_eq!(odds, contract.get_effective_odds(bob.clone()));
+        }
+
+        contract.deposit();
+        without_deposit(&mut context);
+        contract.play(None, None);
+        assert_eq!(51 * ONE_NEAR, contract.stats.get(&bob).unwrap().total_wagered);
+    }
+
+    #[test]
+    #[should_panic(expected = "Configuration gives the house a negative edge!")]
+    fn vip_bonus_counts_towards_house_edge() {
+        let context = get_context(vec![], false);
+        testing_env!(context);
+        let mut contract = SlotMachine::new(String::from("carol_near"));
+
+        // fine at base odds, but not for a top-tier player
+        contract.set_config(GameConfigUpdate {
+            payout_bps: Some(19_500),
+            vip_tiers: Some(vec![(U128::from(10 * ONE_NEAR), 1), (U128::from(50 * ONE_NEAR), 10)]),
+            ..Default::default()
+        });
+    }
+
+    #[test]
+    fn pull_payouts() {
+        let mut context = get_context(vec![], false);
+        testing_env!(context.clone());
+        let mut contract = SlotMachine::new(String::from("carol_near"));
+        contract.set_config(GameConfigUpdate { pull_payouts: Some(true), ..Default::default() });
+        contract.deposit();
+        let bob = String::from("bob_near");
+
+        let mut won = 0;
+        without_deposit(&mut context);
+        for _ in 0..4 {
+            if contract.play(None, None) < 128 {
+                won += 2 * ONE_NEAR;
+            }
+        }
+        assert!(won > 0);
+        assert_eq!(U128::from(DEPOSIT_AMOUNT - 4 * ONE_NEAR), contract.get_credits(bob.clone()));
+        assert_eq!(U128::from(won), contract.get_claimable(bob.clone()));
+        assert_eq!(U128::from(won), contract.get_solvency().total_claimable);
+
+        assert_eq!(U128::from(won), contract.claim_winnings());
+        assert_eq!(U128::from(DEPOSIT_AMOUNT - 4 * ONE_NEAR + won), contract.get_credits(bob.clone()));
+        assert_eq!(U128::from(0), contract.get_claimable(bob));
+        assert_eq!(0, contract.total_claimable);
+    }
+
+    #[test]
+    #[should_panic(expected = "Nothing to claim!")]
+    fn claim_without_winnings() {
+        let context = get_context(vec![], false);
+        testing_env!(context);
+        let mut contract = SlotMachine::new(String::from("carol_near"));
+
+        contract.claim_winnings();
+    }
+
+    #[test]
+    #[cfg(feature = "solvency-check")]
+    #[should_panic(expected = "Contract would become insolvent!")]
+    fn deposit_without_storage_funds_reverts() {
+        // the deposit alone can't also pay for the storage it takes up
+        let mut context = get_context(vec![], false);
+        context.account_balance = 0;
+        testing_env!(context);
+        let mut contract = SlotMachine::new(String::from("carol_near"));
+
+        contract.deposit();
+    }
+
+    #[test]
+    #[cfg(feature = "solvency-check")]
+    #[should_panic(expected = "Contract would become insolvent!")]
+    fn overpaying_adjustment_reverts() {
+        let mut context = get_context(vec![], false);
+        context.attached_deposit = 1;
+        testing_env!(context);
+        let mut contract = SlotMachine::new(String::from("carol_near"));
+
+        contract.adjust_credits(String::from("bob_near"), U128::from(200 * ONE_NEAR), String::from("typo"));
+    }
+
+    #[test]
+    fn deposit_and_play() {
+        let mut context = get_context(vec![], false);
+        context.attached_deposit = ONE_NEAR;
+        testing_env!(context);
+        let mut contract = SlotMachine::new(String::from("carol_near"));
+
+        let result = contract.deposit_and_play(U128::from(ONE_NEAR));
+
+        let expected = if result.won { 2 * ONE_NEAR } else { 0 };
+        assert_eq!(result.won, result.roll < 128);
+        assert_eq!(U128::from(expected), result.payout);
+        assert_eq!(U128::from(expected), result.credits);
+        assert_eq!(result.credits, contract.get_credits(String::from("bob_near")));
+        assert_eq!(ONE_NEAR, contract.get_net_position(String::from("bob_near")).total_deposited.0);
+    }
+
+    #[test]
+    #[should_panic(expected = "doesn't cover the stake and fee")]
+    fn deposit_and_play_with_too_little_attached() {
+        let mut context = get_context(vec![], false);
+        context.attached_deposit = ONE_NEAR / 2;
+        testing_env!(context);
+        let mut contract = SlotMachine::new(String::from("carol_near"));
+
+        contract.deposit_and_play(U128::from(ONE_NEAR));
+    }
+
+    #[test]
+    fn deposit_history() {
+        let mut context = get_context(vec![], false);
+        context.block_timestamp = 100;
+        testing_env!(context.clone());
+        let mut contract = SlotMachine::new(String::from("carol_near"));
+        contract.deposit();
+        context.block_timestamp = 200;
+        context.attached_deposit = ONE_NEAR;
+        update_context(context);
+        contract.deposit();
+        contract.deposit_and_play(U128::from(ONE_NEAR));
+
+        let history = contract.get_deposit_history(String::from("bob_near"), 0, 10);
+        assert_eq!(3, history.len());
+        assert_eq!(
+            DepositRecord { amount: U128::from(DEPOSIT_AMOUNT), bonus: U128::from(0), timestamp: 100 },
+            history[0]
+        );
+        assert_eq!(U128::from(ONE_NEAR), history[2].amount);
+        assert_eq!(200, history[2].timestamp);
+
+        assert_eq!(history[1..].to_vec(), contract.get_deposit_history(String::from("bob_near"), 1, 5));
+        assert_eq!(history[..1].to_vec(), contract.get_deposit_history(String::from("bob_near"), 0, 1));
+        assert!(contract.get_deposit_history(String::from("bob_near"), 3, 5).is_empty());
+    }
+
+    #[test]
+    fn deposit_history_is_capped() {
+        let mut context = get_context(vec![], false);
+        context.attached_deposit = ONE_NEAR;
+        testing_env!(context.clone());
+        let mut contract = SlotMachine::new(String::from("carol_near"));
+        for i in 0..MAX_DEPOSIT_HISTORY as u64 + 2 {
+            context.block_timestamp = i;
+            update_context(context.clone());
+            contract.deposit();
+        }
+
+        // the two oldest deposits were evicted
+        let history = contract.get_deposit_history(String::from("bob_near"), 0, 100);
+        assert_eq!(MAX_DEPOSIT_HISTORY, history.len());
+        assert_eq!(2, history[0].timestamp);
+    }
+
+    #[test]
+    fn player_history_is_capped() {
+        let mut context = get_context(vec![], false);
+        context.attached_deposit = ONE_NEAR;
+        testing_env!(context);
+        let mut contract = SlotMachine::new(String::from("carol_near"));
+
+        let results: Vec<PlayResult> = (0..MAX_HISTORY + 5).map(|_| contract.deposit_and_play(U128::from(ONE_NEAR))).collect();
+        let history = contract.get_player_history(String::from("bob_near"), 100);
+
+        // the five oldest plays were evicted and the rest come back newest first
+        assert_eq!(MAX_HISTORY, history.len());
+        let expected: Vec<PlayResult> = results.into_iter().skip(5).rev().collect();
+        assert_eq!(expected, history);
+
+        assert_eq!(expected[..3].to_vec(), contract.get_player_history(String::from("bob_near"), 3));
+        assert!(contract.get_player_history(String::from("alice_near"), 10).is_empty());
+    }
+
+    #[test]
+    fn collect_fees_with_split() {
+        let context = get_context(vec![], false);
+        testing_env!(context);
+        let mut contract = SlotMachine::new(String::from("carol_near"));
+        assert_eq!(vec![(String::from("carol_near"), MAX_BPS)], contract.get_treasury_splits());
+
+        contract.set_treasury_splits(vec![(String::from("dave_near"), 7_000), (String::from("erin_near"), 3_000)]);
+        contract.accumulated_fees = 10 * ONE_NEAR + 1;
+        contract.collect_fees();
+
+        assert_eq!(0, contract.accumulated_fees);
+        let receipts: Vec<String> = get_created_receipts().iter().map(|receipt| format!("{:?}", receipt)).collect();
+        assert_eq!(2, receipts.len());
+        // the odd yoctoNEAR lost to rounding goes to the first beneficiary
+        assert!(receipts[0].contains("\"dave_near\"") && receipts[0].contains(&format!("deposit: {} ", 7 * ONE_NEAR + 1)));
+        assert!(receipts[1].contains("\"erin_near\"") && receipts[1].contains(&format!("deposit: {} ", 3 * ONE_NEAR)));
+    }
+
+    #[test]
+    #[should_panic(expected = "Treasury splits must sum to 10000 basis points!")]
+    fn treasury_splits_must_sum_to_whole() {
+        let context = get_context(vec![], false);
+        testing_env!(context);
+        let mut contract = SlotMachine::new(String::from("carol_near"));
+
+        contract.set_treasury_splits(vec![(String::from("dave_near"), 7_000), (String::from("erin_near"), 2_000)]);
+    }
+
+    #[test]
+    fn play_above_min_house_balance() {
+        let mut context = get_context(vec![], false);
+        testing_env!(context.clone());
+        let mut contract = SlotMachine::new(String::from("carol_near"));
+        contract.deposit();
+        let house_balance = contract.get_solvency().house_equity.0 as u128;
+
+        // a win would cost the house exactly one stake, which still leaves it at the floor
+        contract.set_config(GameConfigUpdate { min_house_balance: Some(U128::from(house_balance - ONE_NEAR)), ..Default::default() });
+        without_deposit(&mut context);
+        contract.play(None, None);
+        assert_eq!(U128::from(house_balance - ONE_NEAR), contract.get_config().min_house_balance);
+    }
+
+    #[test]
+    #[should_panic(expected = "House bankroll too low")]
+    fn play_below_min_house_balance() {
+        let mut context = get_context(vec![], false);
+        testing_env!(context.clone());
+        let mut contract = SlotMachine::new(String::from("carol_near"));
+        contract.deposit();
+        let house_balance = contract.get_solvency().house_equity.0 as u128;
+
+        contract.set_config(GameConfigUpdate { min_house_balance: Some(U128::from(house_balance - ONE_NEAR + 1)), ..Default::default() });
+        without_deposit(&mut context);
+        contract.play(None, None);
+    }
+
+    #[test]
+    fn soft_cap_scales_payout() {
+        let context = get_context(vec![], false);
+        testing_env!(context);
+        let mut contract = SlotMachine::new(String::from("carol_near"));
+        contract.deposit();
+        let house_balance = contract.get_solvency().house_equity.0 as u128;
+        assert_eq!(20_000, contract.get_current_payout_bps());
+
+        // above the band the full multiplier applies
+        let margin = 10 * ONE_NEAR;
+        contract.set_config(GameConfigUpdate {
+            min_house_balance: Some(U128::from(house_balance - 2 * margin)),
+            soft_cap_margin: Some(U128::from(margin)),
+            ..Default::default()
+        });
+        assert_eq!(20_000, contract.get_current_payout_bps());
+
+        // half way through the band half the winnings are left
+        contract.config.min_house_balance = U128::from(house_balance - margin / 2);
+        assert_eq!(15_000, contract.get_current_payout_bps());
+
+        // and at the floor a win only returns the stake
+        contract.config.min_house_balance = U128::from(house_balance);
+        assert_eq!(10_000, contract.get_current_payout_bps());
+    }
+
+    #[test]
+    fn soft_cap_applies_to_play() {
+        let mut context = get_context(vec![], false);
+        testing_env!(context.clone());
+        let mut contract = SlotMachine::new(String::from("carol_near"));
+        contract.deposit();
+        let house_balance = contract.get_solvency().house_equity.0 as u128;
+        let margin = 10 * ONE_NEAR;
+        contract.set_config(GameConfigUpdate {
+            min_house_balance: Some(U128::from(house_balance - margin / 4)),
+            soft_cap_margin: Some(U128::from(margin)),
+            ..Default::default()
+        });
+
+        contract.forced_roll = Some(0);
+        without_deposit(&mut context);
+        contract.play(None, None);
+
+        // 1.25x instead of 2x on a one NEAR stake
+        let history = contract.get_player_history(String::from("bob_near"), 1);
+        assert_eq!(U128::from(ONE_NEAR * 5 / 4), history[0].payout);
+    }
+
+    fn insured_contract() -> SlotMachine {
+        let context = get_context(vec![], false);
+        testing_env!(context);
+        let mut contract = SlotMachine::new(String::from("carol_near"));
+        contract.set_config(GameConfigUpdate {
+            max_insurance_bps: Some(2_000),
+            insurance_refund_bps: Some(3_000),
+            ..Default::default()
+        });
+        contract.deposit();
+        contract
+    }
+
+    #[test]
+    fn insured_win() {
+        let mut contract = insured_contract();
+        contract.forced_roll = Some(0);
+
+        let result = contract.play_with_insurance(U128::from(ONE_NEAR), 1_500);
+
+        // the premium is kept even though the insurance wasn't needed
+        assert!(result.won);
+        assert_eq!(U128::from(0), result.insurance_refund);
+        assert_eq!(U128::from(DEPOSIT_AMOUNT + ONE_NEAR - ONE_NEAR * 15 / 100), result.credits);
+    }
+
+    #[test]
+    fn insured_loss() {
+        let mut contract = insured_contract();
+        contract.forced_roll = Some(255);
+
+        let result = contract.play_with_insurance(U128::from(ONE_NEAR), 1_500);
+
+        assert!(!result.won);
+        assert_eq!(U128::from(ONE_NEAR * 3 / 10), result.insurance_refund);
+        assert_eq!(U128::from(DEPOSIT_AMOUNT - ONE_NEAR - ONE_NEAR * 15 / 100 + ONE_NEAR * 3 / 10), result.credits);
+    }
+
+    #[test]
+    #[should_panic(expected = "Rebate and insurance refund can't exceed 100% together!")]
+    fn rebate_and_insurance_refund_cant_exceed_the_stake() {
+        let mut contract = insured_contract();
+        contract.set_config(GameConfigUpdate { rebate_bps: Some(9_000), insurance_refund_bps: Some(9_000), ..Default::default() });
+    }
+
+    #[test]
+    fn insured_loss_with_a_full_refund() {
+        let mut contract = insured_contract();
+        contract.set_config(GameConfigUpdate {
+            odds: Some(1),
+            rebate_bps: Some(5_000),
+            insurance_refund_bps: Some(5_000),
+            max_insurance_bps: Some(MAX_BPS),
+            reinvest_bps: Some(5_000),
+            ..Default::default()
+        });
+        contract.forced_roll = Some(255);
+
+        // the refunds hand the whole stake back, leaving the house nothing to reinvest
+        let result = contract.play_with_insurance(U128::from(ONE_NEAR), 5_000);
+        assert!(!result.won);
+        assert_eq!(U128::from(ONE_NEAR / 2), result.insurance_refund);
+        assert_eq!(0, contract.reinvested_profit);
+    }
+
+    #[test]
+    #[should_panic(expected = "Insurance premium must be between 1500 and 2000 bps")]
+    fn insurance_premium_below_expected_refund() {
+        // a 30% refund on a 50% chance of losing is worth 15% of the stake
+        let mut contract = insured_contract();
+        contract.play_with_insurance(U128::from(ONE_NEAR), 1_000);
+    }
+
+    #[test]
+    fn off_tick_stake_is_rounded_down() {
+        let mut context = get_context(vec![], false);
+        testing_env!(context.clone());
+        let mut contract = SlotMachine::new(String::from("carol_near"));
+        contract.set_config(GameConfigUpdate {
+            max_bet: Some(U128::from(2 * ONE_NEAR)),
+            tick_size: Some(U128::from(ONE_NEAR / 10)),
+            ..Default::default()
+        });
+        assert_eq!(U128::from(ONE_NEAR / 10), contract.get_config().tick_size);
+        contract.deposit();
+
+        contract.forced_roll = Some(255);
+        without_deposit(&mut context);
+        contract.play(Some(U128::from(ONE_NEAR * 5 / 4)), None);
+
+        // 1.2 NEAR was staked and lost; the extra 0.05 NEAR never left the credits
+        let history = contract.get_player_history(String::from("bob_near"), 1);
+        assert_eq!(U128::from(ONE_NEAR * 6 / 5), history[0].stake);
+        assert_eq!(U128::from(DEPOSIT_AMOUNT - ONE_NEAR * 6 / 5), contract.get_credits(String::from("bob_near")));
+    }
+
+    #[test]
+    #[should_panic(expected = "Stake must be a multiple of 100000000000000000000000; the nearest valid stake is 1300000000000000000000000")]
+    fn off_tick_stake_is_rejected_without_rounding() {
+        let mut context = get_context(vec![], false);
+        testing_env!(context.clone());
+        let mut contract = SlotMachine::new(String::from("carol_near"));
+        contract.set_config(GameConfigUpdate {
+            max_bet: Some(U128::from(2 * ONE_NEAR)),
+            tick_size: Some(U128::from(ONE_NEAR / 10)),
+            round_stakes_to_tick: Some(false),
+            ..Default::default()
+        });
+        contract.deposit();
+
+        without_deposit(&mut context);
+        contract.play(Some(U128::from(ONE_NEAR * 5 / 4)), None);
+    }
+
+    #[test]
+    fn delegated_play() {
+        let context = get_context(vec![], false);
+        testing_env!(context);
+        let mut contract = SlotMachine::new(String::from("carol_near"));
+        contract.deposit();
+        let bob = String::from("bob_near");
+        let client = String::from("client_near");
+        contract.approve_player(client.clone(), U128::from(ONE_NEAR * 5 / 2));
+        assert_eq!(U128::from(ONE_NEAR * 5 / 2), contract.get_allowance(bob.clone(), client.clone()));
+
+        let mut context = get_context(vec![], false);
+        context.predecessor_account_id = client.clone();
+        update_context(context);
+        contract.forced_roll = Some(255);
+        let result = contract.play_for(bob.clone(), U128::from(ONE_NEAR));
+
+        // the stake came out of the player's credits and the delegate's allowance
+        assert_eq!(U128::from(DEPOSIT_AMOUNT - ONE_NEAR), result.credits);
+        assert_eq!(U128::from(ONE_NEAR * 3 / 2), contract.get_allowance(bob.clone(), client.clone()));
+        contract.play_for(bob.clone(), U128::from(ONE_NEAR));
+        assert_eq!(U128::from(ONE_NEAR / 2), contract.get_allowance(bob.clone(), client.clone()));
+
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| contract.play_for(bob.clone(), U128::from(ONE_NEAR))));
+        assert!(result.is_err());
+        assert_eq!(U128::from(ONE_NEAR / 2), contract.get_allowance(bob, client));
+    }
+
+    #[test]
+    #[should_panic(expected = "Allowance of 0 doesn't cover the stake and fee of 1000000000000000000000000")]
+    fn play_for_without_approval() {
+        let mut context = get_context(vec![], false);
+        context.predecessor_account_id = String::from("client_near");
+        testing_env!(context);
+        let mut contract = SlotMachine::new(String::from("carol_near"));
+        contract.deposit();
+
+        contract.play_for(String::from("bob_near"), U128::from(ONE_NEAR));
+    }
+
+    #[test]
+    fn priority_play_skips_cooldown() {
+        let mut context = get_context(vec![], false);
+        context.block_timestamp = 1_000;
+        testing_env!(context.clone());
+        let mut contract = SlotMachine::new(String::from("carol_near"));
+        contract.set_config(GameConfigUpdate {
+            cooldown_ns: Some(500),
+            priority_fee: Some(U128::from(ONE_NEAR / 10)),
+            ..Default::default()
+        });
+        contract.deposit();
+        without_deposit(&mut context);
+        contract.play(None, None);
+
+        context.block_timestamp = 1_499;
+        update_context(context.clone());
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| contract.play(None, None)));
+        assert!(result.is_err());
+
+        // the priority fee leaves the player's credits for the house bankroll, and isn't staked
+        let credits = contract.get_credits(String::from("bob_near")).0;
+        contract.forced_roll = Some(255);
+        let result = contract.play_priority(U128::from(ONE_NEAR));
+        assert_eq!(U128::from(ONE_NEAR), result.stake);
+        assert_eq!(U128::from(credits - ONE_NEAR - ONE_NEAR / 10), result.credits);
+        assert_eq!(0, contract.accumulated_fees);
+
+        // and the cooldown restarts from the priority play
+        context.block_timestamp = 1_998;
+        update_context(context.clone());
+        assert!(std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| contract.play(None, None))).is_err());
+        context.block_timestamp = 1_999;
+        update_context(context);
+        contract.play(None, None);
+    }
+
+    #[test]
+    fn reward_points_reset_cooldown() {
+        let mut context = get_context(vec![], false);
+        context.block_timestamp = 1_000;
+        testing_env!(context.clone());
+        let mut contract = SlotMachine::new(String::from("carol_near"));
+        contract.set_config(GameConfigUpdate {
+            cooldown_ns: Some(500),
+            points_per_cooldown_reset: Some(U128::from(100)),
+            ..Default::default()
+        });
+        contract.deposit();
+        without_deposit(&mut context);
+        contract.play(None, None);
+        let bob = String::from("bob_near");
+        contract.reward_points.insert(&bob, &150);
+
+        context.block_timestamp = 1_200;
+        update_context(context.clone());
+        contract.spend_points_reset_cooldown(U128::from(100));
+        assert_eq!(U128::from(50), contract.get_reward_points(bob.clone()));
+        contract.play(None, None);
+
+        // the play restarted the cooldown, and 50 points don't cover another reset
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            contract.spend_points_reset_cooldown(U128::from(100))
+        }));
+        assert!(result.is_err());
+        assert_eq!(U128::from(50), contract.get_reward_points(bob));
+    }
+
+    #[test]
+    fn dynamic_payout_follows_realized_payouts() {
+        let mut context = get_context(vec![], false);
+        testing_env!(context.clone());
+        let mut contract = SlotMachine::new(String::from("carol_near"));
+        // the edge has to survive the largest upward adjustment
+        let invalid = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            contract.set_config(GameConfigUpdate {
+                dynamic_payout_window_ns: Some(NS_PER_HOUR),
+                max_payout_adjust_bps: Some(500),
+                ..Default::default()
+            })
+        }));
+        assert!(invalid.is_err());
+        contract.set_config(GameConfigUpdate {
+            payout_bps: Some(19_000),
+            dynamic_payout_window_ns: Some(NS_PER_HOUR),
+            max_payout_adjust_bps: Some(500),
+            ..Default::default()
+        });
+        contract.deposit();
+        let bob = String::from("bob_near");
+        assert_eq!(10_000, contract.get_payout_adjustment_bps());
+
+        // a streak of house losses nudges the payout down, but no further than the bound
+        for _ in 0..3 {
+            contract.forced_roll = Some(0);
+            without_deposit(&mut context);
+            contract.play(None, None);
+        }
+        assert_eq!(9_500, contract.get_payout_adjustment_bps());
+        assert_eq!(U128::from(ONE_NEAR * 18_050 / 10_000), contract.get_last_roll_detail(bob.clone()).unwrap().payout);
+
+        // a fresh window of losses nudges it back up
+        context.block_timestamp = NS_PER_HOUR;
+        update_context(context);
+        contract.forced_roll = Some(255);
+        contract.play(None, None);
+        assert_eq!(10_500, contract.get_payout_adjustment_bps());
+        contract.forced_roll = Some(0);
+        contract.play(None, None);
+        assert_eq!(U128::from(ONE_NEAR * 19_950 / 10_000), contract.get_last_roll_detail(bob).unwrap().payout);
+    }
+
+    #[test]
+    fn batch_stops_at_the_burst_limit() {
+        let context = get_context(vec![], false);
+        testing_env!(context);
+        let mut contract = SlotMachine::new(String::from("carol_near"));
+        contract.set_config(GameConfigUpdate {
+            rapid_play_limit: Some(3),
+            rapid_play_window_ns: Some(1_000),
+            break_duration_ns: Some(10_000),
+            ..Default::default()
+        });
+        contract.deposit();
+        contract.config.odds = 0;
+
+        let result = contract.play_batch(5, None);
+        assert_eq!(3, result.rounds_played);
+        assert_eq!(U128::from(DEPOSIT_AMOUNT - 3 * ONE_NEAR), result.ending_balance);
+        assert_eq!(Some(10_000), contract.get_enforced_break(String::from("bob_near")));
+    }
+
+    // a batch of four winning rounds against `cap`, which should only let the first two through
+    fn winning_batch_under(cap: GameConfigUpdate) {
+        let context = get_context(vec![], false);
+        testing_env!(context);
+        let mut contract = SlotMachine::new(String::from("carol_near"));
+        contract.set_config(cap);
+        contract.deposit();
+        // win every play
+        contract.config.odds = u8::MAX;
+
+        // the wins before the cap stand
+        let result = contract.play_batch(4, None);
+        assert_eq!(2, result.rounds_played);
+        assert_eq!(U128::from(DEPOSIT_AMOUNT + 2 * ONE_NEAR), result.ending_balance);
+    }
+
+    #[test]
+    fn batch_stops_at_the_block_winner_cap() {
+        winning_batch_under(GameConfigUpdate { max_winners_per_block: Some(2), ..Default::default() });
+    }
+
+    #[test]
+    fn batch_stops_at_the_block_payout_cap() {
+        winning_batch_under(GameConfigUpdate { max_block_payout: Some(U128::from(5 * ONE_NEAR)), ..Default::default() });
+    }
+
+    #[test]
+    fn batch_stops_at_the_daily_win_limit() {
+        winning_batch_under(GameConfigUpdate { daily_win_limit: Some(U128::from(2 * ONE_NEAR)), ..Default::default() });
+    }
+
+    #[test]
+    fn batch_stops_at_the_lifetime_play_limit() {
+        winning_batch_under(GameConfigUpdate { max_lifetime_plays: Some(2), ..Default::default() });
+    }
+
+    #[test]
+    fn rapid_plays_enforce_a_break() {
+        let mut context = get_context(vec![], false);
+        testing_env!(context.clone());
+        let mut contract = SlotMachine::new(String::from("carol_near"));
+        contract.set_config(GameConfigUpdate {
+            rapid_play_limit: Some(3),
+            rapid_play_window_ns: Some(1_000),
+            break_duration_ns: Some(10_000),
+            ..Default::default()
+        });
+        contract.deposit();
+        let bob = String::from("bob_near");
+
+        // plays spread wider than the window never add up to a burst
+        for timestamp in [0, 1_000, 2_000, 3_000] {
+            context.block_timestamp = timestamp;
+            update_context(context.clone());
+            without_deposit(&mut context);
+            contract.play(None, None);
+        }
+        assert_eq!(None, contract.get_enforced_break(bob.clone()));
+
+        for timestamp in [3_100, 3_200] {
+            context.block_timestamp = timestamp;
+            update_context(context.clone());
+            contract.play(None, None);
+        }
+        assert_eq!(Some(13_200), contract.get_enforced_break(bob.clone()));
+        assert!(get_logs().iter().any(|log| log.contains("break_enforced")));
+
+        context.block_timestamp = 13_199;
+        update_context(context.clone());
+        match contract.try_play(U128::from(ONE_NEAR)) {
+            PlayOutcome::Rejected(reason) => assert_eq!("Take a break: play resumes at 13200", reason),
+            PlayOutcome::Ok(_) => panic!("expected a rejection"),
+        }
+
+        context.block_timestamp = 13_200;
+        update_context(context);
+        assert_eq!(None, contract.get_enforced_break(bob));
+        contract.play(None, None);
+    }
+
+    #[test]
+    fn cooldown_scales_with_stake() {
+        let mut context = get_context(vec![], false);
+        context.block_timestamp = 1_000;
+        testing_env!(context.clone());
+        let mut contract = SlotMachine::new(String::from("carol_near"));
+        contract.set_config(GameConfigUpdate {
+            max_bet: Some(U128::from(5 * ONE_NEAR)),
+            cooldown_ns: Some(100),
+            cooldown_per_near_ns: Some(1_000),
+            ..Default::default()
+        });
+        assert_eq!(100, contract.get_effective_cooldown(U128::from(ONE_NEAR / 2)));
+        assert_eq!(1_100, contract.get_effective_cooldown(U128::from(ONE_NEAR)));
+        assert_eq!(5_100, contract.get_effective_cooldown(U128::from(5 * ONE_NEAR)));
+
+        let bob = String::from("bob_near");
+        assert_eq!(0, contract.get_next_play_at(bob.clone()));
+        contract.deposit();
+        without_deposit(&mut context);
+        contract.play(Some(U128::from(5 * ONE_NEAR)), None);
+        assert_eq!(6_100, contract.get_next_play_at(bob.clone()));
+        assert!(!contract.can_play(bob));
+    }
+
+    #[test]
+    #[should_panic(expected = "Cooling down until 1500; use play_priority to play now")]
+    fn play_within_cooldown() {
+        let mut context = get_context(vec![], false);
+        context.block_timestamp = 1_000;
+        testing_env!(context.clone());
+        let mut contract = SlotMachine::new(String::from("carol_near"));
+        contract.set_config(GameConfigUpdate { cooldown_ns: Some(500), ..Default::default() });
+        contract.deposit();
+        without_deposit(&mut context);
+        contract.play(None, None);
+
+        contract.play(None, None);
+    }
+
+    #[test]
+    fn owner_earnings() {
+        let mut context = get_context(vec![], false);
+        testing_env!(context.clone());
+        let mut contract = SlotMachine::new(String::from("carol_near"));
+        contract.set_config(GameConfigUpdate { fee_bps: Some(100), payout_bps: Some(19_000), ..Default::default() });
+        contract.deposit();
+
+        // one win (-0.9 NEAR for the house) and two losses (+1 NEAR each)
+        for roll in [0, 255, 255] {
+            contract.forced_roll = Some(roll);
+            without_deposit(&mut context);
+            contract.play(None, None);
+        }
+        let earnings = contract.get_owner_earnings();
+        assert_eq!(U128::from(3 * ONE_NEAR / 100), earnings.accumulated_fees);
+        assert_eq!(I128::from((ONE_NEAR + ONE_NEAR / 10) as i128), earnings.house_pnl);
+        assert_eq!(U128::from(0), earnings.fees_collected);
+        assert_eq!(U128::from(3 * ONE_NEAR / 100), earnings.available_to_withdraw);
+
+        contract.collect_fees();
+        let earnings = contract.get_owner_earnings();
+        assert_eq!(U128::from(0), earnings.accumulated_fees);
+        assert_eq!(U128::from(3 * ONE_NEAR / 100), earnings.fees_collected);
+        assert_eq!(U128::from(0), earnings.available_to_withdraw);
+    }
+
+    #[test]
+    fn reinvested_losses_stay_in_the_bankroll() {
+        let mut context = get_context(vec![], false);
+        testing_env!(context.clone());
+        let mut contract = SlotMachine::new(String::from("carol_near"));
+        contract.set_config(GameConfigUpdate { reinvest_bps: Some(2_500), ..Default::default() });
+        contract.deposit();
+
+        for _ in 0..4 {
+            contract.forced_roll = Some(255);
+            without_deposit(&mut context);
+            contract.play(None, None);
+        }
+        let earnings = contract.get_owner_earnings();
+        assert_eq!(U128::from(ONE_NEAR), earnings.reinvested);
+        // the house took 4 NEAR, but a quarter of it has to stay
+        assert_eq!(contract.free_balance() - contract.liabilities() - ONE_NEAR, earnings.withdrawable_surplus.0);
+    }
+
+    #[test]
+    fn reward_points_discount_fees() {
+        let mut context = get_context(vec![], false);
+        testing_env!(context.clone());
+        let mut contract = SlotMachine::new(String::from("carol_near"));
+        contract.set_config(GameConfigUpdate {
+            fee_bps: Some(200),
+            payout_bps: Some(19_000),
+            points_fee_tiers: Some(vec![(U128::from(100), 50), (U128::from(1_000), 100)]),
+            ..Default::default()
+        });
+        contract.deposit();
+        let bob = String::from("bob_near");
+
+        assert_eq!(200, contract.get_effective_fee_bps(bob.clone()));
+        contract.reward_points.insert(&bob, &150);
+        assert_eq!(150, contract.get_effective_fee_bps(bob.clone()));
+        contract.reward_points.insert(&bob, &1_000);
+        assert_eq!(100, contract.get_effective_fee_bps(bob.clone()));
+
+        contract.forced_roll = Some(255);
+        without_deposit(&mut context);
+        contract.play(None, None);
+        assert_eq!(ONE_NEAR / 100, contract.accumulated_fees);
+        assert_eq!(U128::from(DEPOSIT_AMOUNT - ONE_NEAR - ONE_NEAR / 100), contract.get_credits(bob));
+    }
+
+    #[test]
+    #[should_panic(expected = "Configuration gives the house a negative edge!")]
+    fn fee_discounts_must_keep_the_edge() {
+        let context = get_context(vec![], false);
+        testing_env!(context);
+        let mut contract = SlotMachine::new(String::from("carol_near"));
+        contract.set_config(GameConfigUpdate { fee_bps: Some(100), payout_bps: Some(20_100), ..Default::default() });
+        contract.set_config(GameConfigUpdate { points_fee_tiers: Some(vec![(U128::from(1), 100)]), ..Default::default() });
+    }
+
+    #[test]
+    fn win_boundary_inclusivity() {
+        let mut context = get_context(vec![], false);
+        testing_env!(context.clone());
+        let mut contract = SlotMachine::new(String::from("carol_near"));
+        contract.set_config(GameConfigUpdate { payout_bps: Some(19_000), ..Default::default() });
+        let bob = String::from("bob_near");
+
+        // by default the boundary roll loses
+        assert!(contract.outcome_for_roll(127, None));
+        assert!(!contract.outcome_for_roll(128, None));
+        assert_eq!(128, contract.get_effective_odds(bob.clone()));
+        assert_eq!((0, 127), contract.get_win_range());
+
+        contract.set_config(GameConfigUpdate { win_boundary_inclusive: Some(true), ..Default::default() });
+        assert!(contract.outcome_for_roll(128, Some(bob.clone())));
+        assert!(!contract.outcome_for_roll(129, None));
+        assert_eq!(129, contract.get_effective_odds(bob.clone()));
+        assert_eq!((0, 128), contract.get_win_range());
+
+        // play decides the boundary roll the same way
+        contract.deposit();
+        contract.forced_roll = Some(128);
+        without_deposit(&mut context);
+        contract.play(None, None);
+        assert!(contract.get_player_history(bob.clone(), 1)[0].won);
+
+        // a win range keeps meaning exactly the rolls in it
+        contract.set_win_range(10, 19);
+        assert_eq!(9, contract.get_config().odds);
+        assert!(contract.outcome_for_roll(19, None) && !contract.outcome_for_roll(20, None));
+    }
+
+    #[test]
+    #[should_panic(expected = "Configuration gives the house a negative edge!")]
+    fn inclusive_boundary_counts_towards_house_edge() {
+        let context = get_context(vec![], false);
+        testing_env!(context);
+        let mut contract = SlotMachine::new(String::from("carol_near"));
+        // 128 winning rolls at 2x is break-even, 129 isn't
+        contract.set_config(GameConfigUpdate { win_boundary_inclusive: Some(true), ..Default::default() });
+    }
+
+    #[test]
+    fn stop_loss_halts_batch() {
+        let context = get_context(vec![], false);
+        testing_env!(context);
+        let mut contract = SlotMachine::new(String::from("carol_near"));
+        contract.deposit();
+        // nothing but losses
+        contract.config.win_low = 255;
+        contract.config.odds = 1;
+        contract.config.payout_bps = 20_000;
+
+        let result = contract.play_until_loss(U128::from(ONE_NEAR), U128::from(ONE_NEAR * 7 / 2));
+
+        // a fourth loss would go past 3.5 NEAR, so the batch stops with credits left
+        assert_eq!(3, result.rounds_played);
+        assert_eq!(U128::from(DEPOSIT_AMOUNT - 3 * ONE_NEAR), result.ending_balance);
+    }
+
+    #[test]
+    fn stop_loss_counts_wins() {
+        let context = get_context(vec![], false);
+        testing_env!(context);
+        let mut contract = SlotMachine::new(String::from("carol_near"));
+        contract.deposit();
+
+        let result = contract.play_until_loss(U128::from(ONE_NEAR), U128::from(2 * ONE_NEAR));
+
+        // whatever the rolls, the net loss never passes the limit
+        let wins = result.results.iter().filter(|result| result.won).count() as i128;
+        let net_loss = result.rounds_played as i128 - 2 * wins;
+        assert!(net_loss <= 2);
+        assert!(result.rounds_played == MAX_BATCH_ROUNDS || net_loss == 2);
+        assert_eq!(U128::from((DEPOSIT_AMOUNT as i128 - net_loss * ONE_NEAR as i128) as u128), result.ending_balance);
+    }
+
+    #[test]
+    fn player_events_can_be_suppressed() {
+        let mut context = get_context(vec![], false);
+        testing_env!(context.clone());
+        let mut contract = SlotMachine::new(String::from("carol_near"));
+        contract.deposit();
+        assert!(get_logs().iter().any(|log| log.contains("\"event\":\"deposit\"")));
+
+        contract.set_config(GameConfigUpdate { emit_events: Some(false), ..Default::default() });
+        assert!(!contract.get_config().emit_events);
+        update_context(get_context(vec![], false));
+        contract.deposit();
+        without_deposit(&mut context);
+        contract.play(None, None);
+        contract.withdraw(U128::from(ONE_NEAR));
+
+        // everything still happened, just without events
+        assert!(!get_logs().iter().any(|log| log.starts_with("EVENT_JSON:")));
+        assert_eq!(1, contract.stats.get(&String::from("bob_near")).unwrap().total_plays);
+        assert_eq!(1, contract.get_event_seq());
+    }
+
+    #[test]
+    fn player_exposure_cap() {
+        let context = get_context(vec![], false);
+        testing_env!(context);
+        let mut contract = SlotMachine::new(String::from("carol_near"));
+        contract.set_config(GameConfigUpdate {
+            pull_payouts: Some(true),
+            max_player_exposure_bps: Some(1_000),
+            ..Default::default()
+        });
+        contract.deposit();
+        let bob = String::from("bob_near");
+
+        // unclaimed wins pile up until another 2 NEAR payout would pass 10% of the ~86 NEAR house
+        let mut wins = 0;
+        loop {
+            contract.forced_roll = Some(0);
+            match contract.try_play(U128::from(ONE_NEAR)) {
+                PlayOutcome::Ok(_) => wins += 1,
+                PlayOutcome::Rejected(reason) => {
+                    assert_eq!("Player exposure cap reached", reason);
+                    break;
+                }
+            }
+        }
+        assert_eq!(4, wins);
+        assert_eq!(U128::from(8 * ONE_NEAR), contract.get_player_exposure(bob.clone()));
+        let house_balance = contract.free_balance() - contract.liabilities();
+        assert!(contract.get_player_exposure(bob).0 + 2 * ONE_NEAR > house_balance / 10);
+    }
+
+    #[test]
+    fn max_stake_as_share_of_credits() {
+        let mut context = get_context(vec![], false);
+        testing_env!(context.clone());
+        let mut contract = SlotMachine::new(String::from("carol_near"));
+        contract.set_config(GameConfigUpdate { max_bet: Some(U128::from(5 * ONE_NEAR)), ..Default::default() });
+        contract.deposit();
+        let bob = String::from("bob_near");
+
+        // disabled, the whole max bet is allowed
+        assert_eq!(U128::from(5 * ONE_NEAR), contract.get_max_stake(bob.clone()));
+        contract.forced_roll = Some(0);
+        without_deposit(&mut context);
+        contract.play(Some(U128::from(5 * ONE_NEAR)), None);
+
+        contract.set_config(GameConfigUpdate { max_stake_pct_bps: Some(2_000), ..Default::default() });
+        let credits = contract.get_credits(bob.clone()).0;
+        assert_eq!(U128::from(credits / 5), contract.get_max_stake(bob.clone()));
+        contract.forced_roll = Some(255);
+        contract.play(Some(U128::from(2 * ONE_NEAR)), None);
+
+        // 20% of the remaining credits is under 3 NEAR
+        assert!(contract.get_max_stake(bob).0 < 3 * ONE_NEAR);
+        match contract.try_play(U128::from(3 * ONE_NEAR)) {
+            PlayOutcome::Rejected(reason) => assert!(reason.starts_with("Stake can't exceed")),
+            PlayOutcome::Ok(_) => panic!("expected a rejection"),
+        }
+    }
+
+    #[test]
+    fn daily_win_limit() {
+        let mut context = get_context(vec![], false);
+        context.block_timestamp = 0;
+        testing_env!(context.clone());
+        let mut contract = SlotMachine::new(String::from("carol_near"));
+        contract.set_config(GameConfigUpdate { daily_win_limit: Some(U128::from(2 * ONE_NEAR)), ..Default::default() });
+        contract.deposit();
+        let bob = String::from("bob_near");
+
+        // each win is worth one NEAR on top of the stake
+        for _ in 0..2 {
+            contract.forced_roll = Some(0);
+            without_deposit(&mut context);
+            contract.play(None, None);
+        }
+        assert_eq!(U128::from(0), contract.get_remaining_daily_winnings(bob.clone()));
+        assert_eq!(
+            PlayOutcome::Rejected(String::from("Daily win limit reached")),
+            contract.try_play(U128::from(ONE_NEAR))
+        );
+
+        // a day later the limit is back
+        context.block_timestamp = NS_PER_DAY;
+        update_context(context);
+        assert_eq!(U128::from(2 * ONE_NEAR), contract.get_remaining_daily_winnings(bob));
+        contract.play(None, None);
+    }
+
+    #[test]
+    fn kelly_stake() {
+        let context = get_context(vec![], false);
+        testing_env!(context);
+        let mut contract = SlotMachine::new(String::from("carol_near"));
+        contract.deposit();
+        let bob = String::from("bob_near");
+
+        // a fair or house-favoured game is never worth betting on
+        assert_eq!(U128::from(0), contract.kelly_stake(bob.clone()));
+        contract.config.payout_bps = 19_000;
+        assert_eq!(U128::from(0), contract.kelly_stake(bob.clone()));
+
+        // at 75% to double up the optimal fraction is p - q = 50%
+        contract.config.payout_bps = 20_000;
+        contract.config.odds = 192;
+        assert_eq!(U128::from(DEPOSIT_AMOUNT / 2), contract.kelly_stake(bob.clone()));
+
+        // a 50% rebate halves the loss, so f = 0.75 / 0.5 - 0.25 / 1, capped at everything
+        contract.config.rebate_bps = 5_000;
+        assert_eq!(U128::from(DEPOSIT_AMOUNT), contract.kelly_stake(bob));
+    }
+
+    #[test]
+    fn play_batch_runs_out_of_credits() {
+        let mut context = get_context(vec![], false);
+        context.attached_deposit = 3 * ONE_NEAR;
+        testing_env!(context);
+        let mut contract = SlotMachine::new(String::from("carol_near"));
+        contract.deposit();
+        // lose every round so the credits run out after three
+        contract.config.odds = 0;
+
+        let batch = contract.play_batch(10, None);
+        assert_eq!((3, 10), (batch.rounds_played, batch.rounds_requested));
+        assert_eq!(3, batch.results.len());
+        assert!(batch.results.iter().all(|result| !result.won));
+        assert_eq!(U128::from(0), batch.ending_balance);
+        assert_eq!(batch.results, contract.get_player_history(String::from("bob_near"), 10).into_iter().rev().collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn play_batch_plays_every_round() {
+        let context = get_context(vec![], false);
+        testing_env!(context);
+        let mut contract = SlotMachine::new(String::from("carol_near"));
+        contract.deposit();
+        let logged = get_logs().len();
+
+        let batch = contract.play_batch(5, None);
+        assert_eq!((5, 5), (batch.rounds_played, batch.rounds_requested));
+        assert_eq!(batch.results[4].credits, batch.ending_balance);
+        assert_eq!(batch.ending_balance, contract.get_credits(String::from("bob_near")));
+
+        // the rounds share a single event that still lists each of them
+        let events: Vec<_> = get_logs().into_iter().skip(logged).filter(|log| log.starts_with("EVENT_JSON:")).collect();
+        assert_eq!(1, events.len());
+        let event: serde_json::Value = serde_json::from_str(&events[0]["EVENT_JSON:".len()..]).unwrap();
+        assert_eq!("batch_play", event["event"]);
+        assert_eq!(5, event["data"]["rounds"]);
+        let rolls: Vec<u8> = batch.results.iter().map(|result| result.roll).collect();
+        assert_eq!(json!(rolls), event["data"]["rolls"]);
+    }
+
+    #[test]
+    #[should_panic(expected = "Rounds must be between 1 and 20")]
+    fn play_batch_too_many_rounds() {
+        let context = get_context(vec![], false);
+        testing_env!(context);
+        let mut contract = SlotMachine::new(String::from("carol_near"));
+        contract.deposit();
+
+        contract.play_batch(MAX_BATCH_ROUNDS + 1, None);
+    }
+
+    #[test]
+    fn degenerate_seed_pauses_the_game() {
+        let mut context = get_context(vec![], false);
+        context.random_seed = vec![0; 32];
+        testing_env!(context.clone());
+        let mut contract = SlotMachine::new(String::from("carol_near"));
+        contract.deposit();
+
+        without_deposit(&mut context);
+        assert_eq!(u8::MAX, contract.play(None, None));
+        assert!(contract.is_paused());
+        assert!(contract.contract_health().paused);
+        assert_eq!(U128::from(DEPOSIT_AMOUNT), contract.get_credits(String::from("bob_near")));
+        assert!(contract.get_play_proof(String::from("bob_near")).is_none());
+        assert!(get_logs().iter().any(|log| log.contains("\"event\":\"auto_paused\"")));
+
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| contract.play(None, None)));
+        assert!(result.is_err());
+
+        // once the owner unpauses, a healthy seed plays normally again
+        context.random_seed = vec![0, 1, 2];
+        update_context(context);
+        contract.set_paused(false);
+        contract.play(None, None);
+        assert!(contract.get_play_proof(String::from("bob_near")).is_some());
     }
-}
 
-#[near_bindgen]
-impl SlotMachine {
-    #[init]
-    pub fn new(owner_id: AccountId) -> Self {
-        assert!(env::is_valid_account_id(&owner_id.as_bytes()), "Invalid owner account!");
-        assert!(!env::state_exists(), "Already initialized!");
+    #[test]
+    #[should_panic(expected = "Random seed looks degenerate!")]
+    fn degenerate_seed_without_auto_pause() {
+        let mut context = get_context(vec![], false);
+        context.random_seed = vec![7; 32];
+        testing_env!(context.clone());
+        let mut contract = SlotMachine::new(String::from("carol_near"));
+        contract.deposit();
+        contract.set_config(GameConfigUpdate { auto_pause_on_degenerate_seed: Some(false), ..Default::default() });
 
-        env::log(format!("Creating a SlotMachine with owner id '{}'", &owner_id).as_bytes());
+        without_deposit(&mut context);
+        contract.play(None, None);
+    }
 
-        Self {
-            owner_id,
-            credits: UnorderedMap::new(b"credits".to_vec()),
+    #[test]
+    #[should_panic(expected = "Game is paused!")]
+    fn play_while_paused() {
+        let mut context = get_context(vec![], false);
+        testing_env!(context.clone());
+        let mut contract = SlotMachine::new(String::from("carol_near"));
+        contract.deposit();
+        contract.set_paused(true);
+
+        without_deposit(&mut context);
+        contract.play(None, None);
+    }
+
+    #[test]
+    fn process_payouts() {
+        let context = get_context(vec![], false);
+        testing_env!(context);
+        let mut contract = SlotMachine::new(String::from("carol_near"));
+        for account_id in &["bob_near", "dave_near"] {
+            contract.claimable.insert(&account_id.to_string(), &(2 * ONE_NEAR));
         }
+        contract.total_claimable = 4 * ONE_NEAR;
+
+        contract.process_payouts(vec![String::from("bob_near"), String::from("erin_near"), String::from("dave_near")]);
+
+        assert_eq!(0, contract.total_claimable);
+        assert_eq!(U128::from(0), contract.get_claimable(String::from("bob_near")));
+        assert_eq!(U128::from(0), contract.get_claimable(String::from("dave_near")));
+        // a transfer and a callback for each of the two accounts with winnings
+        let receipts: Vec<String> = get_created_receipts().iter().map(|receipt| format!("{:?}", receipt)).collect();
+        assert_eq!(4, receipts.len());
+        let callback_gas = format!("gas: {}", contract.get_gas_requirements().payout_callback);
+        assert!(receipts[1].contains(&callback_gas) && receipts[3].contains(&callback_gas));
+        let events: Vec<String> = get_logs().into_iter().filter(|log| log.contains("payout_processed")).collect();
+        assert_eq!(2, events.len());
+        assert!(events[0].contains("bob_near") && events[1].contains("dave_near"));
     }
 
-    #[payable]
-    pub fn deposit(&mut self) {
-        let account_id = env::signer_account_id();
-        let deposit_amount = env::attached_deposit();
+    #[test]
+    fn reclaim_stuck() {
+        let mut context = get_context(vec![], false);
+        context.block_timestamp = 0;
+        testing_env!(context);
+        let mut contract = SlotMachine::new(String::from("carol_near"));
+        let bob = String::from("bob_near");
+        contract.add_claimable(&bob, 2 * ONE_NEAR);
+        let timeout = contract.get_config().stuck_reclaim_timeout;
 
-        let mut credits = self.credits.get(&account_id).unwrap_or(0);
-        credits += deposit_amount;
+        let mut context = get_context(vec![], false);
+        context.block_timestamp = timeout - 1;
+        update_context(context);
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| contract.reclaim_stuck(bob.clone())));
+        assert!(result.is_err());
+        assert_eq!(U128::from(2 * ONE_NEAR), contract.get_claimable(bob.clone()));
 
-        self.credits.insert(&account_id, &credits);
+        let mut context = get_context(vec![], false);
+        context.block_timestamp = timeout;
+        update_context(context);
+        let house_equity = contract.get_solvency().house_equity.0;
+        assert_eq!(U128::from(2 * ONE_NEAR), contract.reclaim_stuck(bob.clone()));
+
+        assert_eq!(U128::from(0), contract.get_claimable(bob.clone()));
+        assert_eq!(0, contract.total_claimable);
+        assert!(contract.claimable_since.get(&bob).is_none());
+        assert!(contract.get_solvency().house_equity.0 >= house_equity + 2 * ONE_NEAR as i128);
+        assert!(get_logs().iter().any(|log| log.contains("\"event\":\"stuck_reclaimed\"")));
     }
 
-    pub fn play(&mut self) -> u8{
-        let account_id = env::signer_account_id();
-        let mut credits = self.credits.get(&account_id).unwrap_or(0);
+    #[test]
+    #[should_panic(expected = "Entry can't be reclaimed before")]
+    fn reclaim_stuck_within_timeout() {
+        let context = get_context(vec![], false);
+        testing_env!(context);
+        let mut contract = SlotMachine::new(String::from("carol_near"));
+        contract.add_claimable(&String::from("bob_near"), ONE_NEAR);
 
-        assert!(credits >= ONE_NEAR, "No credits to play!!!");
+        contract.reclaim_stuck(String::from("bob_near"));
+    }
 
-        credits -= ONE_NEAR;
-        let random_number = *env::random_seed().get(0).unwrap();
-        if random_number < PROB {
-            credits += 10 * ONE_NEAR;
-        }
+    #[test]
+    #[should_panic(expected = "No claim time recorded!")]
+    fn reclaim_stuck_without_claim_time() {
+        let mut context = get_context(vec![], false);
+        context.block_timestamp = u64::MAX;
+        testing_env!(context);
+        let mut contract = SlotMachine::new(String::from("carol_near"));
+        let bob = String::from("bob_near");
+        contract.add_claimable(&bob, ONE_NEAR);
+        contract.claimable_since.remove(&bob);
 
-        self.credits.insert(&account_id, &credits);
-        
-        random_number
+        contract.reclaim_stuck(bob);
     }
 
-    pub fn get_credits(&self, account_id: AccountId) -> U128 {
-        println!("get_credits");
-        self.credits.get(&account_id).unwrap_or(0).into()
+    #[test]
+    fn auto_payout_win() {
+        let mut context = get_context(vec![], false);
+        testing_env!(context.clone());
+        let mut contract = SlotMachine::new(String::from("carol_near"));
+        contract.set_config(GameConfigUpdate { allow_auto_payout: Some(true), ..Default::default() });
+        contract.deposit();
+        contract.set_auto_payout(true);
+        assert!(contract.get_auto_payout(String::from("bob_near")));
+
+        contract.forced_roll = Some(0);
+        without_deposit(&mut context);
+        contract.play(None, None);
+
+        // the winnings went out as a transfer, with the callback chained after it
+        assert_eq!(U128::from(DEPOSIT_AMOUNT - ONE_NEAR), contract.get_credits(String::from("bob_near")));
+        let receipts: Vec<String> = get_created_receipts().iter().map(|receipt| format!("{:?}", receipt)).collect();
+        assert_eq!(2, receipts.len());
+        assert!(receipts[0].contains("\"bob_near\"") && receipts[0].contains(&format!("deposit: {} ", 2 * ONE_NEAR)));
+        assert!(receipts[1].contains("\"alice_near\"") && receipts[1].contains(&format!("{:?}", b"on_auto_payout_transfer".to_vec())));
+        assert!(receipts[1].contains(&format!("gas: {}", contract.get_gas_requirements().auto_payout_callback)));
     }
-}
 
-/*
- * The rest of this file holds the inline tests for the code above
- * Learn more about Rust tests: https://doc.rust-lang.org/book/ch11-01-writing-tests.html
- *
- * To run from contract directory:
- * cargo test -- --nocapture
- *
- * From project root, to run in combination with frontend tests:
- * yarn test
- *
- */
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use near_sdk::MockedBlockchain;
-    use near_sdk::{testing_env, VMContext};
+    #[test]
+    #[should_panic(expected = "Attach at least 30000000000000 gas")]
+    fn auto_payout_play_with_too_little_gas() {
+        let mut context = get_context(vec![], false);
+        testing_env!(context.clone());
+        let mut contract = SlotMachine::new(String::from("carol_near"));
+        contract.set_config(GameConfigUpdate { allow_auto_payout: Some(true), ..Default::default() });
+        contract.deposit();
+        contract.set_auto_payout(true);
 
-    const DEPOSIT_AMOUNT: u128 = 10 * ONE_NEAR;
+        context.prepaid_gas = contract.get_gas_requirements().play_with_auto_payout - 1;
+        update_context(context.clone());
+        without_deposit(&mut context);
+        contract.play(None, None);
+    }
 
-    // mock the context for testing, notice "signer_account_id" that was accessed above from env::
-    fn get_context(input: Vec<u8>, is_view: bool) -> VMContext {
-        VMContext {
-            current_account_id: "alice_near".to_string(),
-            signer_account_id: "bob_near".to_string(),
-            signer_account_pk: vec![0, 1, 2],
-            predecessor_account_id: "carol_near".to_string(),
-            input,
-            block_index: 0,
-            block_timestamp: 0,
-            account_balance: 0,
-            account_locked_balance: 0,
-            storage_usage: 0,
-            attached_deposit: DEPOSIT_AMOUNT,
-            prepaid_gas: 10u64.pow(18),
-            random_seed: vec![0, 1, 2],
-            is_view,
-            output_data_receivers: vec![],
-            epoch_height: 19,
-        }
+    #[test]
+    fn failed_auto_payout_is_credited() {
+        let mut context = get_context(vec![], false);
+        testing_env!(context.clone());
+        let mut contract = SlotMachine::new(String::from("carol_near"));
+        contract.set_config(GameConfigUpdate { allow_auto_payout: Some(true), ..Default::default() });
+        contract.deposit();
+        contract.set_auto_payout(true);
+        contract.forced_roll = Some(0);
+        without_deposit(&mut context);
+        contract.play(None, None);
+        let player_credits = contract.total_player_credits;
+
+        let mut context = get_context(vec![], false);
+        context.predecessor_account_id = context.current_account_id.clone();
+        context.storage_usage = env::storage_usage();
+        testing_env_with_promise_results(context, PromiseResult::Failed);
+        contract.on_auto_payout_transfer(String::from("bob_near"), U128::from(2 * ONE_NEAR));
+
+        assert_eq!(U128::from(DEPOSIT_AMOUNT + ONE_NEAR), contract.get_credits(String::from("bob_near")));
+        assert_eq!(player_credits + 2 * ONE_NEAR, contract.total_player_credits);
     }
 
     #[test]
-    fn deposit() {
+    #[should_panic(expected = "Auto-payout is disabled!")]
+    fn auto_payout_needs_owner_opt_in() {
         let context = get_context(vec![], false);
         testing_env!(context);
-        
         let mut contract = SlotMachine::new(String::from("carol_near"));
+        contract.set_auto_payout(true);
+    }
+
+    #[cfg(feature = "debug")]
+    #[test]
+    fn account_state_size_grows() {
+        let context = get_context(vec![], false);
+        testing_env!(context);
+        let mut contract = SlotMachine::new(String::from("carol_near"));
+        let bob = String::from("bob_near");
+        assert_eq!(0, contract.account_state_size(bob.clone()));
 
         contract.deposit();
+        let after_deposit = contract.account_state_size(bob.clone());
+        assert!(after_deposit > 0);
 
-        assert_eq!(
-            U128::from(DEPOSIT_AMOUNT),
-            contract.get_credits(String::from("bob_near"))
-        );
+        contract.play(None, None);
+        let after_play = contract.account_state_size(bob.clone());
+        assert!(after_play > after_deposit);
 
-        assert_eq!(
-            U128::from(0),
-            contract.get_credits(String::from("carol_near"))
-        );
+        // each play adds another history entry
+        contract.play(None, None);
+        assert!(contract.account_state_size(bob) > after_play);
     }
 
     #[test]
-    fn play() {
+    fn fund_accounts() {
+        let context = get_context(vec![], false);
+        testing_env!(context);
+        let mut contract = SlotMachine::new(String::from("carol_near"));
+
+        contract.fund_accounts(vec![
+            (String::from("dave_near"), U128::from(6 * ONE_NEAR)),
+            (String::from("erin_near"), U128::from(4 * ONE_NEAR)),
+        ]);
+
+        assert_eq!(U128::from(6 * ONE_NEAR), contract.get_credits(String::from("dave_near")));
+        assert_eq!(U128::from(4 * ONE_NEAR), contract.get_credits(String::from("erin_near")));
+        assert_eq!(DEPOSIT_AMOUNT, contract.total_player_credits);
+        assert_eq!(2, get_logs().iter().filter(|log| log.contains("\"event\":\"account_funded\"")).count());
+        assert!(get_created_receipts().is_empty());
+    }
+
+    #[test]
+    fn fund_accounts_refunds_remainder() {
+        let context = get_context(vec![], false);
+        testing_env!(context);
+        let mut contract = SlotMachine::new(String::from("carol_near"));
+
+        contract.fund_accounts(vec![(String::from("dave_near"), U128::from(7 * ONE_NEAR))]);
+
+        let receipts: Vec<String> = get_created_receipts().iter().map(|receipt| format!("{:?}", receipt)).collect();
+        assert_eq!(1, receipts.len());
+        assert!(receipts[0].contains("\"carol_near\"") && receipts[0].contains(&format!("deposit: {} ", 3 * ONE_NEAR)));
+    }
+
+    #[test]
+    #[should_panic(expected = "Allocations of 11000000000000000000000000 exceed the attached deposit of 10000000000000000000000000")]
+    fn fund_accounts_over_allocation() {
+        let context = get_context(vec![], false);
+        testing_env!(context);
+        let mut contract = SlotMachine::new(String::from("carol_near"));
+
+        contract.fund_accounts(vec![
+            (String::from("dave_near"), U128::from(6 * ONE_NEAR)),
+            (String::from("erin_near"), U128::from(5 * ONE_NEAR)),
+        ]);
+    }
+
+    #[test]
+    fn failed_payout_is_restored() {
+        let context = get_context(vec![], false);
+        testing_env!(context);
+        let mut contract = SlotMachine::new(String::from("carol_near"));
+        contract.claimable.insert(&String::from("bob_near"), &(2 * ONE_NEAR));
+        contract.total_claimable = 2 * ONE_NEAR;
+        contract.process_payouts(vec![String::from("bob_near")]);
+
+        let mut context = get_context(vec![], false);
+        context.predecessor_account_id = context.current_account_id.clone();
+        context.storage_usage = env::storage_usage();
+        testing_env_with_promise_results(context, PromiseResult::Failed);
+        contract.on_payout_transfer(String::from("bob_near"), U128::from(2 * ONE_NEAR));
+
+        assert_eq!(U128::from(2 * ONE_NEAR), contract.get_claimable(String::from("bob_near")));
+        assert_eq!(2 * ONE_NEAR, contract.total_claimable);
+    }
+
+    #[test]
+    fn effective_odds_match_play() {
         let context = get_context(vec![], false);
         testing_env!(context);
         let mut contract = SlotMachine::new(String::from("carol_near"));
-        
-        // Deposit 10 NEAR to 'bob_near'
         contract.deposit();
+        let bob = String::from("bob_near");
 
-        let number = contract.play();
-        let mut credits = DEPOSIT_AMOUNT;
-        
-        if number < 128 {
-            credits += 10 * ONE_NEAR;
-        }
+        // a +20 VIP bonus clamped to 140, then one win of decay at 5 per win
+        contract.config.vip_tiers = vec![(U128::from(0), 20)];
+        contract.config.max_effective_odds = 140;
+        contract.config.odds_decay_per_win = 5;
+        contract.stats.insert(&bob, &PlayerStats { win_streak: 1, ..Default::default() });
+        assert_eq!(135, contract.get_effective_odds(bob.clone()));
 
-        credits -= ONE_NEAR;
+        // whatever the upcoming roll, play decides it against the same odds
+        let roll = contract.derive_roll(&String::from("bob_near"), contract.nonce);
+        let result = contract.try_play(U128::from(ONE_NEAR));
+        assert_eq!(PlayOutcome::Ok(PlayResult {
+            roll,
+            won: roll < 135,
+            stake: U128::from(ONE_NEAR),
+            payout: U128::from(if roll < 135 { 2 * ONE_NEAR } else { 0 }),
+            credits: contract.get_credits(bob.clone()),
+            insurance_refund: U128::from(0),
+        }), result);
+    }
 
-        assert_eq!(
-            U128::from(credits),
-            contract.get_credits(String::from("bob_near"))
-        );
+    #[test]
+    fn max_effective_odds_clamps_bonuses() {
+        let context = get_context(vec![], false);
+        testing_env!(context);
+        let mut contract = SlotMachine::new(String::from("carol_near"));
+
+        // a +50 top tier would be a losing game at 1.8x, but the clamp keeps it at 140
+        contract.set_config(GameConfigUpdate {
+            payout_bps: Some(18_000),
+            vip_tiers: Some(vec![(U128::from(10 * ONE_NEAR), 5), (U128::from(50 * ONE_NEAR), 50)]),
+            max_effective_odds: Some(140),
+            ..Default::default()
+        });
+        let bob = String::from("bob_near");
+
+        contract.stats.insert(&bob, &PlayerStats { total_wagered: 10 * ONE_NEAR, ..Default::default() });
+        assert_eq!(133, contract.get_effective_odds(bob.clone()));
+        contract.stats.insert(&bob, &PlayerStats { total_wagered: 50 * ONE_NEAR, ..Default::default() });
+        assert_eq!(140, contract.get_effective_odds(bob.clone()));
+
+        // lifting the clamp exposes the losing top tier, which is rejected
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            contract.set_config(GameConfigUpdate { max_effective_odds: Some(u8::MAX), ..Default::default() });
+        }));
+        assert!(result.is_err());
+        assert_eq!(140, contract.get_config().max_effective_odds);
     }
 
     #[test]
-    fn get_initial_credits() {
-        let context = get_context(vec![], true);
+    fn derive_roll_is_deterministic() {
+        let context = get_context(vec![], false);
         testing_env!(context);
         let contract = SlotMachine::new(String::from("carol_near"));
-        // this test did not call set_greeting so should return the default "Hello" greeting
-        assert_eq!(
-            U128::from(0),
-            contract.get_credits(String::from("bob_near"))
-        );
+
+        let bob = String::from("bob_near");
+        let rolls: Vec<u8> = (0..16).map(|nonce| contract.derive_roll(&bob, nonce)).collect();
+        let again: Vec<u8> = (0..16).map(|nonce| contract.derive_roll(&bob, nonce)).collect();
+        assert_eq!(rolls, again);
+        assert!(rolls.iter().any(|roll| *roll != rolls[0]));
+
+        let other: Vec<u8> = (0..16).map(|nonce| contract.derive_roll(&String::from("dave_near"), nonce)).collect();
+        assert_ne!(rolls, other);
+    }
+
+    #[test]
+    fn contributed_entropy_changes_rolls() {
+        let mut context = get_context(vec![], false);
+        testing_env!(context.clone());
+        let mut contract = SlotMachine::new(String::from("carol_near"));
+        contract.deposit();
+
+        let before: Vec<u8> = (0..16).map(|nonce| contract.derive_roll(&String::from("bob_near"), nonce)).collect();
+        contract.contribute_entropy(Base64VecU8::from(b"third party".to_vec()));
+        let after: Vec<u8> = (0..16).map(|nonce| contract.derive_roll(&String::from("bob_near"), nonce)).collect();
+        assert_ne!(before, after);
+
+        // the proof carries the pool used, and the pool rolls forward after the play
+        let pool = contract.entropy_pool.clone();
+        without_deposit(&mut context);
+        let roll = contract.play(None, None);
+        let proof = contract.get_play_proof(String::from("bob_near")).unwrap();
+        assert_eq!(pool, Vec::<u8>::from(proof.entropy_pool));
+        assert_eq!(after[0], roll);
+        assert_ne!(pool, contract.entropy_pool);
+    }
+
+    #[test]
+    #[should_panic(expected = "Entropy can be at most 64 bytes!")]
+    fn contribute_too_much_entropy() {
+        let context = get_context(vec![], false);
+        testing_env!(context);
+        let mut contract = SlotMachine::new(String::from("carol_near"));
+
+        contract.contribute_entropy(Base64VecU8::from(vec![1; MAX_ENTROPY_LENGTH + 1]));
+    }
+
+    #[test]
+    fn derive_roll_distribution() {
+        let context = get_context(vec![], false);
+        testing_env!(context);
+        let contract = SlotMachine::new(String::from("carol_near"));
+
+        let mut seen = [false; 256];
+        let mut wins = 0;
+        for nonce in 0..1024 {
+            let roll = contract.derive_roll(&String::from("bob_near"), nonce);
+            seen[roll as usize] = true;
+            if roll < PROB {
+                wins += 1;
+            }
+        }
+
+        // expect ~512 wins and ~251 distinct bytes for a uniform roll
+        assert!((448..=576).contains(&wins), "{} wins out of 1024", wins);
+        assert!(seen.iter().filter(|s| **s).count() > 200);
+    }
+
+    #[test]
+    fn exact_odds_win_rate() {
+        let mut context = get_context(vec![], false);
+        testing_env!(context.clone());
+        let mut contract = SlotMachine::new(String::from("carol_near"));
+        contract.set_config(GameConfigUpdate {
+            odds_numerator: Some(4875),
+            odds_denominator: Some(10_000),
+            payout_bps: Some(20_000),
+            ..Default::default()
+        });
+        assert_eq!((4875, 10_000), contract.get_win_probability(String::from("bob_near")));
+        assert_eq!(124, contract.get_effective_odds(String::from("bob_near")));
+
+        // expect ~1950 wins out of 4000 at 48.75%
+        let wins = (0..4000).filter(|nonce| contract.derive_exact_roll(&String::from("bob_near"), *nonce, 10_000) < 4875).count();
+        assert!((1850..=2050).contains(&wins), "{} wins out of 4000", wins);
+
+        contract.deposit();
+        contract.forced_roll = Some(0);
+        without_deposit(&mut context);
+        contract.play(None, None);
+        assert_eq!(Some(true), contract.get_last_roll_detail(String::from("bob_near")).map(|detail| detail.won));
+        contract.forced_roll = Some(255);
+        contract.play(None, None);
+        let detail = contract.get_last_roll_detail(String::from("bob_near")).unwrap();
+        assert_eq!((Some(9960), false), (detail.exact_roll, detail.won));
+    }
+
+    #[test]
+    #[should_panic(expected = "Odds numerator must be below the denominator!")]
+    fn exact_odds_numerator_below_denominator() {
+        let context = get_context(vec![], false);
+        testing_env!(context);
+        let mut contract = SlotMachine::new(String::from("carol_near"));
+
+        contract.set_config(GameConfigUpdate { odds_numerator: Some(3), odds_denominator: Some(3), ..Default::default() });
+    }
+
+    #[test]
+    #[should_panic(expected = "Vault position is still locked!")]
+    fn unstake_before_unlock() {
+        let context = get_context(vec![], false);
+        testing_env!(context);
+        let mut contract = SlotMachine::new(String::from("carol_near"));
+        contract.deposit();
+
+        contract.stake_vault(U128::from(DEPOSIT_AMOUNT), 1_000);
+
+        let mut context = get_context(vec![], false);
+        context.block_timestamp = 999;
+        update_context(context);
+        contract.unstake();
+    }
+
+    #[test]
+    fn vault_interest() {
+        let context = get_context(vec![], false);
+        testing_env!(context);
+        let mut contract = SlotMachine::new(String::from("carol_near"));
+        contract.deposit();
+
+        // 5% APR, locked for a full year
+        contract.set_vault_apr_bps(500);
+        contract.stake_vault(U128::from(DEPOSIT_AMOUNT), NS_PER_YEAR);
+        assert_eq!(U128::from(0), contract.get_credits(String::from("bob_near")));
+
+        let mut context = get_context(vec![], false);
+        context.block_timestamp = NS_PER_YEAR;
+        update_context(context);
+
+        // the interest is owed from the moment the position opens
+        assert_eq!(DEPOSIT_AMOUNT + ONE_NEAR / 2, contract.liabilities());
+        let payout = contract.unstake();
+        assert_eq!(U128::from(DEPOSIT_AMOUNT + ONE_NEAR / 2), payout);
+        assert_eq!(DEPOSIT_AMOUNT + ONE_NEAR / 2, contract.liabilities());
+        assert_eq!(payout, contract.get_credits(String::from("bob_near")));
+        assert!(contract.get_vault(String::from("bob_near")).is_none());
+    }
+
+    #[test]
+    #[should_panic(expected = "House can't cover")]
+    fn vault_interest_must_be_covered_when_staking() {
+        // leaves the house well short of the half NEAR of interest a year at 5% would owe
+        let mut context = get_context(vec![], false);
+        context.account_balance = ONE_NEAR / 4;
+        testing_env!(context);
+        let mut contract = SlotMachine::new(String::from("carol_near"));
+        contract.deposit();
+
+        contract.set_vault_apr_bps(500);
+        contract.stake_vault(U128::from(DEPOSIT_AMOUNT), NS_PER_YEAR);
+    }
+
+    #[test]
+    #[should_panic(expected = "Duration overflows the unlock time!")]
+    fn vault_duration_cant_overflow() {
+        let mut context = get_context(vec![], false);
+        context.block_timestamp = 1;
+        testing_env!(context);
+        let mut contract = SlotMachine::new(String::from("carol_near"));
+        contract.deposit();
+
+        contract.stake_vault(U128::from(ONE_NEAR), u64::MAX);
+    }
+
+    #[test]
+    #[should_panic(expected = "Insufficient credits: have 0, need 1000000000000000000000000")]
+    fn cannot_play_with_staked_credits() {
+        let mut context = get_context(vec![], false);
+        testing_env!(context.clone());
+        let mut contract = SlotMachine::new(String::from("carol_near"));
+        contract.deposit();
+
+        contract.stake_vault(U128::from(DEPOSIT_AMOUNT), NS_PER_YEAR);
+        without_deposit(&mut context);
+        contract.play(None, None);
     }
 }